@@ -1,7 +1,10 @@
-use crate::lexer::Span;
+use crate::{
+    instructions::{EncodingForm, IntegerRole, OperandAccess},
+    lexer::Span,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub(crate) enum Register {
+pub enum Register {
     R0,
     R1,
     R2,
@@ -41,6 +44,32 @@ impl Register {
             Self::R15 => 15,
         }
     }
+
+    /// The register encoded by the low nibble of `value`
+    pub fn decode(value: u8) -> Self {
+        match value & 0x0f {
+            0 => Self::R0,
+            1 => Self::R1,
+            2 => Self::R2,
+            3 => Self::R3,
+            4 => Self::R4,
+            5 => Self::R5,
+            6 => Self::R6,
+            7 => Self::R7,
+            8 => Self::R8,
+            9 => Self::R9,
+            10 => Self::R10,
+            11 => Self::R11,
+            12 => Self::R12,
+            13 => Self::R13,
+            14 => Self::R14,
+            _ => Self::R15,
+        }
+    }
+
+    pub fn name(self) -> String {
+        format!("r{}", self.encode())
+    }
 }
 
 impl TryFrom<&str> for Register {
@@ -72,7 +101,7 @@ impl TryFrom<&str> for Register {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub(crate) enum Opcode {
+pub enum Opcode {
     Nop,
     Add,
     Ldi,
@@ -91,6 +120,85 @@ pub(crate) enum Opcode {
 }
 
 impl Opcode {
+    /// Iterates over every opcode in the ISA, in encoding order
+    pub fn all() -> impl Iterator<Item = Self> {
+        [
+            Self::Nop,
+            Self::Add,
+            Self::Ldi,
+            Self::Sub,
+            Self::And,
+            Self::Or,
+            Self::Inv,
+            Self::Xor,
+            Self::Sr,
+            Self::Sl,
+            Self::In,
+            Self::Out,
+            Self::Jz,
+            Self::Jlt,
+            Self::J,
+        ]
+        .into_iter()
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Self::Nop => "nop",
+            Self::Add => "add",
+            Self::Ldi => "ldi",
+            Self::Sub => "sub",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Inv => "inv",
+            Self::Xor => "xor",
+            Self::Sr => "sr",
+            Self::Sl => "sl",
+            Self::In => "in",
+            Self::Out => "out",
+            Self::Jz => "jz",
+            Self::Jlt => "jlt",
+            Self::J => "j",
+        }
+    }
+
+    /// The way the operands of this opcode are packed into the instruction word
+    pub fn encoding_form(self) -> EncodingForm {
+        match self {
+            Self::Nop => EncodingForm::NoOperand,
+            Self::Add | Self::Sub | Self::And | Self::Or | Self::Xor | Self::Sr | Self::Sl => {
+                EncodingForm::DoubleRegister
+            }
+            Self::Inv => EncodingForm::SingleRegister,
+            Self::Ldi | Self::Jz | Self::Jlt | Self::J => EncodingForm::Immediate,
+            Self::In | Self::Out => EncodingForm::Io,
+        }
+    }
+
+    /// What an integer operand of this opcode is used for, if it takes one
+    pub fn integer_role(self) -> Option<IntegerRole> {
+        match self {
+            Self::Ldi => Some(IntegerRole::Immediate),
+            Self::In | Self::Out => Some(IntegerRole::Port),
+            Self::Jz | Self::Jlt | Self::J => Some(IntegerRole::JumpDestination),
+            _ => None,
+        }
+    }
+
+    /// How each operand of this opcode is used, in the order the operands are written
+    pub fn operand_access(self) -> &'static [OperandAccess] {
+        match self {
+            Self::Nop => &[],
+            Self::Add | Self::Sub | Self::And | Self::Or | Self::Xor | Self::Sr | Self::Sl => {
+                &[OperandAccess::ReadWrite, OperandAccess::Read]
+            }
+            Self::Inv => &[OperandAccess::ReadWrite],
+            Self::Ldi | Self::In => &[OperandAccess::Write, OperandAccess::Constant],
+            Self::Out | Self::Jz | Self::Jlt => &[OperandAccess::Read, OperandAccess::Constant],
+            Self::J => &[OperandAccess::Constant],
+        }
+    }
+
     pub fn encode(self) -> u8 {
         match self {
             Self::Nop => 0,
@@ -110,6 +218,11 @@ impl Opcode {
             Self::J => 15,
         }
     }
+
+    /// The opcode encoded as `value`, if any
+    pub fn decode(value: u8) -> Option<Self> {
+        Self::all().find(|opcode| opcode.encode() == value)
+    }
 }
 
 impl TryFrom<&str> for Opcode {
@@ -139,36 +252,358 @@ impl TryFrom<&str> for Opcode {
     }
 }
 
-pub(crate) type LabelId = usize;
+pub type LabelId = usize;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
-pub(crate) enum Operand {
+pub enum Operand {
     Register { value: Register, span: Span },
     Integer { value: i8, span: Span },
     Label { value: LabelId, span: Span },
 }
 
-#[derive(Debug, Clone, Copy)]
-pub(crate) enum Instruction {
+impl Operand {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Register { span, .. } => *span,
+            Self::Integer { span, .. } => *span,
+            Self::Label { span, .. } => *span,
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone)]
+pub enum Instruction {
     NoOperand(Opcode),
     SingleOperand(Opcode, Operand),
     DoubleOperand(Opcode, Operand, Operand),
+    /// Three or more operands, which no instruction of the ISA takes yet
+    MultiOperand(Opcode, Vec<Operand>),
 }
 
 impl Instruction {
-    #[allow(dead_code)]
     pub fn opcode(&self) -> Opcode {
         match self {
             Self::NoOperand(op) => *op,
             Self::SingleOperand(op, _) => *op,
             Self::DoubleOperand(op, _, _) => *op,
+            Self::MultiOperand(op, _) => *op,
+        }
+    }
+
+    /// The operands of this instruction, in the order they were written
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Self::NoOperand(_) => Vec::new(),
+            Self::SingleOperand(_, operand) => vec![*operand],
+            Self::DoubleOperand(_, operand1, operand2) => vec![*operand1, *operand2],
+            Self::MultiOperand(_, operands) => operands.clone(),
         }
     }
+
+    /// Each operand paired with how the instruction uses it
+    pub fn operands_with_access(&self) -> impl Iterator<Item = (Operand, OperandAccess)> {
+        self.operands()
+            .into_iter()
+            .zip(self.opcode().operand_access().iter().copied())
+    }
+
+    /// The registers whose values this instruction uses
+    pub fn registers_read(&self) -> Vec<Register> {
+        self.registers_where(OperandAccess::reads)
+    }
+
+    /// The registers this instruction changes
+    pub fn registers_written(&self) -> Vec<Register> {
+        self.registers_where(OperandAccess::writes)
+    }
+
+    fn registers_where(&self, predicate: fn(OperandAccess) -> bool) -> Vec<Register> {
+        self.operands_with_access()
+            .filter_map(|(operand, access)| match operand {
+                Operand::Register { value, .. } if predicate(access) => Some(value),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub(crate) enum Item {
+/// How wide each value of a data directive is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataWidth {
+    /// `.byte` or `db`
+    Byte,
+    /// `.word` or `dw`, two bytes in the order chosen when generating
+    Word,
+}
+
+impl DataWidth {
+    /// The role of each value, which decides the range it may take
+    pub fn integer_role(self) -> IntegerRole {
+        match self {
+            Self::Byte => IntegerRole::Byte,
+            Self::Word => IntegerRole::Word,
+        }
+    }
+}
+
+/// Values placed directly in the output by a data directive
+#[derive(Debug, Clone)]
+pub struct Data {
+    pub width: DataWidth,
+    pub values: Vec<DataValue>,
+}
+
+/// A value of a data directive
+#[derive(Debug, Clone)]
+pub enum DataValue {
+    /// A value known while parsing, already range-checked for the width and stored as its unsigned bit pattern
+    Known(u16),
+    /// A value that refers to labels, which is worked out and range-checked once they have values
+    Deferred(Expression),
+}
+
+impl Data {
+    /// Number of bytes the values take up, before padding
+    pub fn size_bytes(&self) -> usize {
+        match self.width {
+            DataWidth::Byte => self.values.len(),
+            DataWidth::Word => self.values.len() * 2,
+        }
+    }
+
+    /// Number of instruction slots taken up, since data is padded to keep instructions aligned
+    pub fn num_slots(&self) -> usize {
+        self.size_bytes().div_ceil(2)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Item {
     Label(LabelId),
-    Instruction(Instruction),
+    /// An instruction along with the span from its mnemonic to its last operand, and of the comment after it on the
+    /// same line if there is one
+    Instruction(Instruction, Span, Option<Span>),
+    /// A data directive along with the span from the directive to its last value
+    Data(Data, Span),
+    /// A `.checksum_here` directive, a byte filled in with the sum of every byte before it once the rest of the
+    /// program has been generated
+    Checksum(Span),
+    /// An `.assert` directive along with the span from the directive to its message, which takes up no space
+    Assert(Assertion, Span),
+}
+
+/// The comments around an item, for tools that reproduce the source, such as a formatter
+#[derive(Debug, Clone, Default)]
+pub struct ItemComments {
+    /// Comments on the lines between the item before and this one, in source order
+    pub leading: Vec<Span>,
+    /// The comment after the item on the line it ends on
+    pub trailing: Option<Span>,
+}
+
+/// A condition checked once every label has a value, failing assembly if it is false
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    /// True if it isn't zero
+    pub condition: Expression,
+    /// Reported if the condition is false
+    pub message: Option<String>,
+}
+
+/// An integer worked out from integers, constants and labels, which can only be evaluated once every label has a value
+///
+/// Constants are replaced by their value when parsed, since their value depends on where they are used. Parentheses
+/// only group, and aren't kept
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Integer(i64, Span),
+    /// A label, whose value is the index of the instruction slot it points to
+    Label(LabelId, Span),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Integer(_, span) | Self::Label(_, span) => *span,
+            Self::Binary(_, left, right) => left.span().merge(right.span()),
+        }
+    }
+
+    /// Works out the value of the expression, looking up each label with `label_value`
+    ///
+    /// An operation whose result doesn't fit in an `i64` is an error at the span of that operation
+    pub fn evaluate<E: From<Overflow>>(
+        &self,
+        label_value: &mut impl FnMut(LabelId, Span) -> Result<i64, E>,
+    ) -> Result<i64, E> {
+        match self {
+            Self::Integer(value, _) => Ok(*value),
+            Self::Label(label_id, span) => label_value(*label_id, *span),
+            Self::Binary(operator, left, right) => {
+                let left = left.evaluate(label_value)?;
+                let right = right.evaluate(label_value)?;
+
+                operator
+                    .apply(left, right)
+                    .ok_or_else(|| Overflow(self.span()).into())
+            }
+        }
+    }
+}
+
+/// An operation in an expression whose result doesn't fit in an `i64`, at the span of the operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow(pub Span);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    BitAnd,
+    BitOr,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl BinaryOperator {
+    /// Comparisons give 1 if they hold and 0 if they don't, and None is given if the result doesn't fit in an `i64`
+    pub fn apply(self, left: i64, right: i64) -> Option<i64> {
+        match self {
+            Self::Add => left.checked_add(right),
+            Self::Subtract => left.checked_sub(right),
+            Self::Multiply => left.checked_mul(right),
+            Self::BitAnd => Some(left & right),
+            Self::BitOr => Some(left | right),
+            Self::Equal => Some((left == right) as i64),
+            Self::NotEqual => Some((left != right) as i64),
+            Self::Less => Some((left < right) as i64),
+            Self::LessEqual => Some((left <= right) as i64),
+            Self::Greater => Some((left > right) as i64),
+            Self::GreaterEqual => Some((left >= right) as i64),
+        }
+    }
+
+    /// How tightly the operator binds, from comparisons, which bind the most loosely, through `|`, `&`, and adding
+    /// and subtracting to multiplying, the same order as in Rust
+    pub fn precedence(self) -> u8 {
+        match self {
+            Self::Equal
+            | Self::NotEqual
+            | Self::Less
+            | Self::LessEqual
+            | Self::Greater
+            | Self::GreaterEqual => 0,
+            Self::BitOr => 1,
+            Self::BitAnd => 2,
+            Self::Add | Self::Subtract => 3,
+            Self::Multiply => 4,
+        }
+    }
+
+    /// The precedence of the operators that bind most tightly
+    pub const MAX_PRECEDENCE: u8 = 4;
+}
+
+/// A `.meta` entry, describing the program to whatever loads it rather than being part of the program itself
+#[derive(Debug, Clone)]
+pub struct MetaEntry {
+    pub key: String,
+    pub value: MetaValue,
+    /// The span from the directive to its value
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetaValue {
+    Integer(i64),
+    /// The text between the quotes, with escapes already removed
+    String(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(value: Register) -> Operand {
+        Operand::Register {
+            value,
+            span: Span { index: 0, len: 0 },
+        }
+    }
+
+    fn integer(value: i8) -> Operand {
+        Operand::Integer {
+            value,
+            span: Span { index: 0, len: 0 },
+        }
+    }
+
+    #[test]
+    fn add_reads_both_and_writes_the_first() {
+        let add = Instruction::DoubleOperand(Opcode::Add, register(Register::R1), register(Register::R2));
+
+        assert_eq!(Opcode::Add.operand_access(), [OperandAccess::ReadWrite, OperandAccess::Read]);
+        assert_eq!(add.registers_read(), [Register::R1, Register::R2]);
+        assert_eq!(add.registers_written(), [Register::R1]);
+    }
+
+    #[test]
+    fn ldi_only_writes() {
+        let ldi = Instruction::DoubleOperand(Opcode::Ldi, register(Register::R3), integer(5));
+
+        assert_eq!(Opcode::Ldi.operand_access(), [OperandAccess::Write, OperandAccess::Constant]);
+        assert!(ldi.registers_read().is_empty());
+        assert_eq!(ldi.registers_written(), [Register::R3]);
+    }
+
+    #[test]
+    fn inv_reads_and_writes_its_register() {
+        let inv = Instruction::SingleOperand(Opcode::Inv, register(Register::R5));
+
+        assert_eq!(inv.registers_read(), [Register::R5]);
+        assert_eq!(inv.registers_written(), [Register::R5]);
+    }
+
+    #[test]
+    fn out_and_jz_only_read() {
+        for opcode in [Opcode::Out, Opcode::Jz] {
+            let instruction = Instruction::DoubleOperand(opcode, register(Register::R4), integer(1));
+
+            assert_eq!(instruction.registers_read(), [Register::R4]);
+            assert!(instruction.registers_written().is_empty());
+        }
+    }
+
+    #[test]
+    fn j_and_nop_use_no_registers() {
+        let j = Instruction::SingleOperand(Opcode::J, integer(0));
+        let nop = Instruction::NoOperand(Opcode::Nop);
+
+        assert_eq!(j.operands_with_access().map(|(_, access)| access).collect::<Vec<_>>(), [OperandAccess::Constant]);
+        assert!(j.registers_read().is_empty() && j.registers_written().is_empty());
+        assert!(nop.operands().is_empty());
+    }
+
+    #[test]
+    fn every_opcode_has_its_own_nibble() {
+        let mut encodings: Vec<u8> = Opcode::all().map(Opcode::encode).collect();
+        encodings.sort_unstable();
+        encodings.dedup();
+
+        assert_eq!(encodings.len(), Opcode::all().count());
+        assert!(encodings.iter().all(|&encoding| encoding < 16));
+
+        for opcode in Opcode::all() {
+            assert_eq!(Opcode::decode(opcode.encode()), Some(opcode));
+        }
+        assert_eq!(Opcode::decode(4), None);
+    }
 }
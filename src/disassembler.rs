@@ -0,0 +1,291 @@
+//! Turns assembled output back into assembly language
+
+use std::io::Write;
+
+use std::fmt::Display;
+
+use crate::{
+    ast::{Opcode, Register},
+    generator::INSTRUCTION_MEMORY_SIZE_BYTES,
+    instructions::{EncodingForm, IntegerRole},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodedOperand {
+    Register(Register),
+    /// An immediate value or a port
+    Integer(i64),
+    /// The index of the instruction jumped to
+    JumpDestination(u8),
+}
+
+/// An instruction slot of the output, decoded
+#[derive(Debug, Clone)]
+pub struct DecodedWord {
+    /// Byte offset of the word in the output
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    /// The instruction the bytes encode, or `None` if no instruction assembles to exactly these bytes
+    pub instruction: Option<(Opcode, Vec<DecodedOperand>)>,
+}
+
+/// A way in which an image isn't one the assembler could have produced from instructions alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageIssue {
+    /// The image is longer than instruction memory, with its length in bytes
+    TooLong(usize),
+    /// The image ends partway through an instruction, with its length in bytes
+    OddLength(usize),
+    /// A word whose high nibble isn't the opcode of any instruction
+    UnknownOpcode { address: usize, value: u8 },
+    /// A word with a nonzero value in bits that the instruction doesn't use
+    UnusedBitsSet { address: usize, opcode: Opcode },
+    /// A jump to an index past the end of instruction memory
+    JumpOutOfRange { address: usize, destination: u8 },
+}
+
+impl Display for ImageIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::TooLong(len) => write!(
+                f,
+                "image is {} bytes, but instruction memory is only {} bytes",
+                len, INSTRUCTION_MEMORY_SIZE_BYTES
+            ),
+            Self::OddLength(len) => write!(f, "image is {} bytes, which isn't a whole number of instructions", len),
+            Self::UnknownOpcode { address, value } => {
+                write!(f, "0x{:02X}: 0x{:X} is not an opcode", address, value)
+            }
+            Self::UnusedBitsSet { address, opcode } => write!(
+                f,
+                "0x{:02X}: `{}` has bits set that it doesn't use",
+                address,
+                opcode.mnemonic()
+            ),
+            Self::JumpOutOfRange { address, destination } => write!(
+                f,
+                "0x{:02X}: jump to instruction {} is past the end of instruction memory",
+                address, destination
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DisassemblyOptions {
+    /// Give every jump destination inside the output a label, and write the output so it can be assembled again
+    pub labels: bool,
+    /// Where to stop writing words
+    pub stop: DisassemblyStop,
+    /// How the immediate of an `ldi` is written
+    pub immediates: DisassemblyImmediates,
+}
+
+/// How much of the output a disassembly covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DisassemblyStop {
+    /// Up to the last word that isn't a `nop`, leaving out the padding after the program
+    #[default]
+    Auto,
+    /// Every word, including padding
+    Full,
+}
+
+/// How the immediate of an `ldi` is written in a disassembly, since its byte can be read as signed or unsigned
+///
+/// Ports and jump destinations are always unsigned, and data is always written in hex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DisassemblyImmediates {
+    /// As a signed byte, such as -1, which can be assembled again as is
+    #[default]
+    Signed,
+    /// As an unsigned byte, such as 255, which needs `--relax` to be assembled again
+    Unsigned,
+    /// As the byte in hex, such as 0xff, which needs `--relax` to be assembled again
+    Hex,
+}
+
+impl DisassemblyImmediates {
+    pub fn format(self, value: i8) -> String {
+        match self {
+            Self::Signed => value.to_string(),
+            Self::Unsigned => (value as u8).to_string(),
+            Self::Hex => format!("0x{:02x}", value as u8),
+        }
+    }
+}
+
+/// Decodes every 2-byte word of `bytes`, with a shorter last word if the length is odd
+pub fn disassemble(bytes: &[u8]) -> Vec<DecodedWord> {
+    bytes
+        .chunks(2)
+        .enumerate()
+        .map(|(index, chunk)| DecodedWord {
+            address: index * 2,
+            bytes: chunk.to_vec(),
+            instruction: match *chunk {
+                [first, second] => decode(index * 2, first, second).ok(),
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+/// Checks that every word of an image is an instruction that the assembler could have produced, and that the image
+/// fits in instruction memory
+///
+/// This is the encoding described in the README, so data in the image that doesn't happen to look like instructions
+/// is reported too. Padding is made of `nop`s, which are always valid.
+pub fn validate_image(bytes: &[u8]) -> Result<(), Vec<ImageIssue>> {
+    let mut issues = Vec::new();
+
+    if bytes.len() > INSTRUCTION_MEMORY_SIZE_BYTES as usize {
+        issues.push(ImageIssue::TooLong(bytes.len()));
+    }
+
+    if !bytes.len().is_multiple_of(2) {
+        issues.push(ImageIssue::OddLength(bytes.len()));
+    }
+
+    for (index, chunk) in bytes.chunks_exact(2).enumerate() {
+        if let Err(issue) = decode(index * 2, chunk[0], chunk[1]) {
+            issues.push(issue);
+        }
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(issues)
+    }
+}
+
+/// Decodes a single instruction at byte offset `address`, only if assembling it again gives back the same bytes
+///
+/// Bits the processor ignores must be zero, since the assembler always leaves them that way
+fn decode(address: usize, first: u8, second: u8) -> Result<(Opcode, Vec<DecodedOperand>), ImageIssue> {
+    let opcode = Opcode::decode(first >> 4).ok_or(ImageIssue::UnknownOpcode {
+        address,
+        value: first >> 4,
+    })?;
+    let unused_bits_set = ImageIssue::UnusedBitsSet { address, opcode };
+    let register = DecodedOperand::Register(Register::decode(first));
+    let destination_range = IntegerRole::JumpDestination.range();
+
+    let operands = match opcode.encoding_form() {
+        EncodingForm::NoOperand if first & 0x0f == 0 && second == 0 => vec![],
+        EncodingForm::SingleRegister if second == 0 => vec![register],
+        EncodingForm::DoubleRegister if second & 0x0f == 0 => vec![
+            register,
+            DecodedOperand::Register(Register::decode(second >> 4)),
+        ],
+        EncodingForm::Io if second & 0x0f == 0 => {
+            vec![register, DecodedOperand::Integer((second >> 4) as i64)]
+        }
+        EncodingForm::Immediate => {
+            if opcode == Opcode::Ldi {
+                vec![register, DecodedOperand::Integer(second as i8 as i64)]
+            } else if !destination_range.contains(&(second as i64)) {
+                return Err(ImageIssue::JumpOutOfRange {
+                    address,
+                    destination: second,
+                });
+            } else if opcode == Opcode::J {
+                // The register of `j` is ignored
+                if first & 0x0f != 0 {
+                    return Err(unused_bits_set);
+                }
+
+                vec![DecodedOperand::JumpDestination(second)]
+            } else {
+                vec![register, DecodedOperand::JumpDestination(second)]
+            }
+        }
+        _ => return Err(unused_bits_set),
+    };
+
+    Ok((opcode, operands))
+}
+
+/// Writes decoded words, one per line
+///
+/// Each line starts with the address and bytes of the word, like `0x0A: 2d 05    ldi r13, 5`, unless labels are
+/// enabled, in which case they go in a comment at the end of the line instead so that the output can be assembled
+pub fn write_disassembly(
+    out: &mut dyn Write,
+    words: &[DecodedWord],
+    options: &DisassemblyOptions,
+) -> std::io::Result<()> {
+    let words = match options.stop {
+        // Padding is all zero bytes, which are `nop`s
+        DisassemblyStop::Auto => {
+            let end = words
+                .iter()
+                .rposition(|word| word.bytes.iter().any(|&b| b != 0))
+                .map_or(0, |index| index + 1);
+
+            &words[..end]
+        }
+        DisassemblyStop::Full => words,
+    };
+
+    let label_of = |index: usize| format!("L_{:02X}", index * 2);
+    let is_label_target = |index: u8| options.labels && (index as usize) < words.len();
+
+    let mut targets: Vec<usize> = words
+        .iter()
+        .flat_map(|word| word.instruction.iter())
+        .flat_map(|(_, operands)| operands.iter())
+        .filter_map(|operand| match *operand {
+            DecodedOperand::JumpDestination(index) if is_label_target(index) => Some(index as usize),
+            _ => None,
+        })
+        .collect();
+    targets.sort();
+    targets.dedup();
+
+    for (index, word) in words.iter().enumerate() {
+        let text = match &word.instruction {
+            Some((opcode, operands)) => {
+                let operands: Vec<String> = operands
+                    .iter()
+                    .map(|operand| match *operand {
+                        DecodedOperand::Register(register) => register.name(),
+                        DecodedOperand::Integer(value) if *opcode == Opcode::Ldi => {
+                            options.immediates.format(value as i8)
+                        }
+                        DecodedOperand::Integer(value) => value.to_string(),
+                        DecodedOperand::JumpDestination(index) if is_label_target(index) => {
+                            label_of(index as usize)
+                        }
+                        DecodedOperand::JumpDestination(index) => index.to_string(),
+                    })
+                    .collect();
+
+                format!("{} {}", opcode.mnemonic(), operands.join(", "))
+                    .trim_end()
+                    .to_string()
+            }
+            None => {
+                let values: Vec<String> = word.bytes.iter().map(|b| format!("0x{:02x}", b)).collect();
+
+                format!(".byte {}", values.join(", "))
+            }
+        };
+
+        let bytes: Vec<String> = word.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let location = format!("0x{:02X}: {}", word.address, bytes.join(" "));
+
+        if options.labels {
+            if targets.binary_search(&index).is_ok() {
+                writeln!(out, "{}:", label_of(index))?;
+            }
+
+            writeln!(out, "    {:<20} ; {}", text, location)?;
+        } else {
+            writeln!(out, "{:<11}    {}", location, text)?;
+        }
+    }
+
+    Ok(())
+}
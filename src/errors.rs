@@ -1,23 +1,30 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    io::{IsTerminal, Write},
+};
 
-use crate::{generator::{GeneratorError, MAX_NUM_INSTRUCTIONS}, lexer::Span, parser::ParseError, sources::SourceManager};
+use crate::{generator::{GeneratorError, INSTRUCTION_MEMORY_SIZE_BYTES, MAX_NUM_INSTRUCTIONS}, instructions::IntegerRole, lexer::{Span, SpanContext, Token, TokenType}, parser::{ParseError, ParseWarning, Visibility}, sources::SourceManager};
 
-#[derive(Debug, Clone)]
-pub(crate) struct Diagnostic {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
     kind: DiagnosticKind,
     label: String,
     label_span: Option<Span>,
+    /// The macro invocation the labeled span was expanded from
+    expansion_span: Option<Span>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum DiagnosticKind {
+pub enum DiagnosticKind {
     Error,
+    Warning,
 }
 
 impl Display for DiagnosticKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
             Self::Error => "error",
+            Self::Warning => "warning",
         })
     }
 }
@@ -28,6 +35,7 @@ impl Diagnostic {
             kind,
             label: label.into(),
             label_span: None,
+            expansion_span: None,
         }
     }
 
@@ -36,6 +44,7 @@ impl Diagnostic {
             kind,
             label: label.into(),
             label_span: Some(span),
+            expansion_span: None,
         }
     }
 
@@ -47,6 +56,28 @@ impl Diagnostic {
         Self::new_with_span(DiagnosticKind::Error, label, span)
     }
 
+    pub fn warning(label: impl Into<String>) -> Self {
+        Self::new(DiagnosticKind::Warning, label)
+    }
+
+    pub fn warning_with_span(label: impl Into<String>, span: Span) -> Self {
+        Self::new_with_span(DiagnosticKind::Warning, label, span)
+    }
+
+    /// Turns a warning into an error, such as when warnings are denied
+    pub fn into_error(mut self) -> Self {
+        self.kind = DiagnosticKind::Error;
+        self
+    }
+
+    /// Like `error_with_span`, but also points at the macro invocation the span was expanded from, if any
+    pub fn error_with_context(label: impl Into<String>, context: SpanContext) -> Self {
+        let mut diagnostic = Self::error_with_span(label, context.span);
+        diagnostic.expansion_span = context.expanded_from;
+
+        diagnostic
+    }
+
     pub fn kind(&self) -> DiagnosticKind {
         self.kind
     }
@@ -58,60 +89,434 @@ impl Diagnostic {
     pub fn label_span(&self) -> Option<Span> {
         self.label_span
     }
+
+    pub fn expansion_span(&self) -> Option<Span> {
+        self.expansion_span
+    }
+}
+
+/// Removes every diagnostic that is the same as one before it, in kind, message and span, keeping the rest in order
+pub fn dedup_diagnostics(diagnostics: &mut Vec<Diagnostic>) {
+    let mut unique: Vec<Diagnostic> = Vec::with_capacity(diagnostics.len());
+
+    for diagnostic in diagnostics.drain(..) {
+        if !unique.contains(&diagnostic) {
+            unique.push(diagnostic);
+        }
+    }
+
+    *diagnostics = unique;
+}
+
+/// Whether diagnostics should be colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Color only when writing to a terminal, unless `CLICOLOR_FORCE`, `NO_COLOR` or `CLICOLOR` say otherwise
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// An explicit choice always wins, otherwise `CLICOLOR_FORCE` forces color, `NO_COLOR` and
+    /// `CLICOLOR=0` disable it, and failing all of those it is used only when stderr is a terminal
+    pub fn should_color(self) -> bool {
+        self.should_color_with(|name| std::env::var_os(name), std::io::stderr().is_terminal())
+    }
+
+    /// The same as `should_color`, looking up environment variables with `var` and taking whether stderr is a
+    /// terminal as given
+    fn should_color_with(self, var: impl Fn(&str) -> Option<std::ffi::OsString>, is_terminal: bool) -> bool {
+        // An empty variable is the same as one that isn't set
+        let var = |name| var(name).filter(|value| !value.is_empty());
+
+        match self {
+            Self::Auto => {
+                if var("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+                    true
+                } else if var("NO_COLOR").is_some() || var("CLICOLOR").is_some_and(|value| value == "0") {
+                    false
+                } else {
+                    is_terminal
+                }
+            }
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// How much of each diagnostic is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ErrorFormat {
+    /// The message followed by the source line with the span underlined
+    #[default]
+    Rich,
+    /// A single line of `file:line:column: kind: message`, like GCC, for logs and tools that read them
+    Short,
+}
+
+/// The characters used to draw the source snippet of a diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Charset {
+    #[default]
+    Ascii,
+    Unicode,
+}
+
+impl Charset {
+    /// Uses Unicode only when writing to a terminal whose locale says it can display it
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if std::io::stderr().is_terminal() && (locale.contains("utf-8") || locale.contains("utf8")) {
+            Self::Unicode
+        } else {
+            Self::Ascii
+        }
+    }
+
+    fn arrow(self) -> &'static str {
+        match self {
+            Self::Ascii => "-->",
+            Self::Unicode => "──▶",
+        }
+    }
+
+    fn gutter(self) -> &'static str {
+        match self {
+            Self::Ascii => "|",
+            Self::Unicode => "│",
+        }
+    }
+
+    fn underline(self) -> char {
+        match self {
+            Self::Ascii => '^',
+            Self::Unicode => '━',
+        }
+    }
+}
+
+/// Somewhere diagnostics are reported to, such as the terminal or a library user's own interface
+pub trait Emitter {
+    fn emit(&mut self, diagnostic: &Diagnostic, source_manager: &SourceManager);
+}
+
+/// Keeps every diagnostic emitted to it, in order, so that they can be shown however the caller likes
+#[derive(Debug, Clone, Default)]
+pub struct CollectingEmitter {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl CollectingEmitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+}
+
+impl Emitter for CollectingEmitter {
+    fn emit(&mut self, diagnostic: &Diagnostic, _source_manager: &SourceManager) {
+        self.diagnostics.push(diagnostic.clone());
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalEmitterOptions {
+    /// Lines longer than this many characters are cut down to a window around the span
+    pub context_width: Option<usize>,
+    pub color: bool,
+    pub charset: Charset,
+    pub format: ErrorFormat,
+}
+
+pub struct TerminalEmitter {
+    options: TerminalEmitterOptions,
 }
 
-pub(crate) struct TerminalEmitter {}
+impl Emitter for TerminalEmitter {
+    fn emit(&mut self, diagnostic: &Diagnostic, source_manager: &SourceManager) {
+        // There's nowhere left to report a diagnostic that can't be written to stderr
+        let _ = self.write_diagnostic(&mut std::io::stderr().lock(), diagnostic, source_manager);
+    }
+}
 
 impl TerminalEmitter {
-    pub(crate) fn emit(diagnostic: Diagnostic, source_manager: &SourceManager) {
-        eprintln!("{}: {}", diagnostic.kind(), diagnostic.label());
+    pub fn new(options: TerminalEmitterOptions) -> Self {
+        Self { options }
+    }
+
+    /// Writes the diagnostic as it is shown in the terminal
+    pub fn write_diagnostic(
+        &self,
+        out: &mut dyn Write,
+        diagnostic: &Diagnostic,
+        source_manager: &SourceManager,
+    ) -> std::io::Result<()> {
+        let kind_style = match diagnostic.kind() {
+            DiagnosticKind::Error => STYLE_ERROR,
+            DiagnosticKind::Warning => STYLE_WARNING,
+        };
+
+        if self.options.format == ErrorFormat::Short {
+            return self.write_short(out, diagnostic, kind_style, source_manager);
+        }
+
+        writeln!(
+            out,
+            "{}{}",
+            self.paint(&diagnostic.kind().to_string(), kind_style),
+            self.paint(&format!(": {}", diagnostic.label()), STYLE_BOLD)
+        )?;
 
         if let Some(label_span) = diagnostic.label_span() {
-            let (line, line_number, column) = source_manager.get_span_line(label_span).unwrap();
-
-            let line_number_width = format!("{}", line_number).len();
-            let line_number_padding: String =
-                std::iter::repeat(' ').take(line_number_width).collect();
-
-            eprintln!(
-                " {} --> {}:{}:{}",
-                line_number_padding,
-                source_manager.file_name(),
-                line_number,
-                column
-            );
+            self.write_snippet(out, label_span, kind_style, source_manager)?;
+        }
 
-            // Fixes tab rendering to be what we define
-            let line_fixed = line.replace('\t', "    ");
+        if let Some(expansion_span) = diagnostic.expansion_span() {
+            writeln!(
+                out,
+                "{}{}",
+                self.paint("note", STYLE_NOTE),
+                self.paint(": in this macro invocation", STYLE_BOLD)
+            )?;
 
-            eprintln!(" {} | {}", line_number, line_fixed);
+            self.write_snippet(out, expansion_span, STYLE_NOTE, source_manager)?;
+        }
 
-            let mut pointer = line_number_padding.clone();
+        Ok(())
+    }
 
-            for _ in 0..(column + 4) {
-                pointer.push(' ');
+    /// Writes the diagnostic on one line, starting with where it is, followed by a line for the macro invocation it was
+    /// expanded from, if any
+    fn write_short(
+        &self,
+        out: &mut dyn Write,
+        diagnostic: &Diagnostic,
+        kind_style: &str,
+        source_manager: &SourceManager,
+    ) -> std::io::Result<()> {
+        let location = |span: Option<Span>| match span {
+            Some(span) => {
+                let (_, line_number, column) = source_manager.get_span_line(span).unwrap();
+                format!("{}:{}:{}", source_manager.file_name(), line_number, column)
             }
+            None => source_manager.file_name().clone(),
+        };
+
+        writeln!(
+            out,
+            "{}: {}{}",
+            location(diagnostic.label_span()),
+            self.paint(&diagnostic.kind().to_string(), kind_style),
+            self.paint(&format!(": {}", diagnostic.label()), STYLE_BOLD)
+        )?;
+
+        if let Some(expansion_span) = diagnostic.expansion_span() {
+            writeln!(
+                out,
+                "{}: {}{}",
+                location(Some(expansion_span)),
+                self.paint("note", STYLE_NOTE),
+                self.paint(": in this macro invocation", STYLE_BOLD)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the location of `span`, the line containing it, and an underline beneath it
+    fn write_snippet(
+        &self,
+        out: &mut dyn Write,
+        span: Span,
+        underline_style: &str,
+        source_manager: &SourceManager,
+    ) -> std::io::Result<()> {
+        let charset = self.options.charset;
+        let (line, line_number, column) = source_manager.get_span_line(span).unwrap();
 
-            for _ in 0..label_span.len {
-                pointer.push('^');
+        let line_number_width = format!("{}", line_number).len();
+        let line_number_padding = " ".repeat(line_number_width);
+
+        writeln!(
+            out,
+            " {} {} {}:{}:{}",
+            line_number_padding,
+            self.paint(charset.arrow(), STYLE_GUTTER),
+            source_manager.file_name(),
+            line_number,
+            column
+        )?;
+
+        // Fixes tab rendering to be what we define
+        let line_fixed = line.replace('\t', "    ");
+
+        // Only the first line of a span that crosses lines is shown, so the underline stops at the end of it
+        let text = source_manager.get_span(span).unwrap_or_default();
+        let (first_line_text, crosses_lines) = match text.split_once('\n') {
+            // A span that only ends with the line ending, such as a newline token, is still on one line
+            Some((first_line_text, rest)) => (first_line_text, !rest.is_empty()),
+            None => (text, false),
+        };
+        let first_line_text = first_line_text.trim_end_matches('\r');
+
+        // Spans are in bytes, but carets are drawn per character, with tabs as wide as they are shown. A zero-length
+        // span still needs something to point with
+        let caret_len = first_line_text
+            .chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum::<usize>()
+            .max(1);
+
+        let (shown_line, caret_column, caret_len) = match self.options.context_width {
+            Some(width) if line_fixed.chars().count() > width => {
+                window_line(&line_fixed, column as usize, caret_len, width)
             }
+            _ => (line_fixed, column as usize, caret_len),
+        };
+
+        writeln!(
+            out,
+            " {} {}",
+            self.paint(&format!("{} {}", line_number, charset.gutter()), STYLE_GUTTER),
+            shown_line
+        )?;
+
+        // The carets are display-only, so multi-byte underline characters don't affect where they start
+        let mut pointer = line_number_padding.clone();
+
+        for _ in 0..(caret_column + 4) {
+            pointer.push(' ');
+        }
+
+        let carets: String = std::iter::repeat_n(charset.underline(), caret_len).collect();
+        pointer.push_str(&self.paint(&carets, underline_style));
+
+        if crosses_lines {
+            pointer.push_str(&self.paint(" ...spans multiple lines", STYLE_NOTE));
+        }
+
+        writeln!(out, "{}", pointer)
+    }
+
+    /// Wraps `text` in the ANSI escape codes for `style` if color is enabled
+    fn paint(&self, text: &str, style: &str) -> String {
+        if self.options.color {
+            format!("\x1b[{}m{}\x1b[0m", style, text)
+        } else {
+            String::from(text)
+        }
+    }
+}
+
+const STYLE_ERROR: &str = "1;31";
+const STYLE_WARNING: &str = "1;33";
+const STYLE_BOLD: &str = "1";
+const STYLE_GUTTER: &str = "1;34";
+const STYLE_NOTE: &str = "1;32";
+
+/// Cuts `line` down to `width` characters centered on the span as far as the line allows, marking elided text with
+/// `...` on either side
+///
+/// Returns the shown text along with the caret column and length adjusted to it
+fn window_line(line: &str, column: usize, len: usize, width: usize) -> (String, usize, usize) {
+    const ELLIPSIS: &str = "...";
 
-            eprintln!("{}", pointer);
+    let chars: Vec<char> = line.chars().collect();
+    let span_middle = column + len / 2;
+    let start = span_middle
+        .saturating_sub(width / 2)
+        .min(chars.len() - width);
+    let end = start + width;
+
+    let mut shown = String::new();
+    let mut caret_column = column.saturating_sub(start);
+
+    if start > 0 {
+        shown.push_str(ELLIPSIS);
+        caret_column += ELLIPSIS.len();
+    }
+
+    shown.extend(&chars[start..end]);
+
+    if end < chars.len() {
+        shown.push_str(ELLIPSIS);
+    }
+
+    // A span wider than the window is cut off at the edges of the window
+    let caret_start = column.max(start);
+    let caret_end = (column + len).min(end).max(caret_start + 1);
+
+    (shown, caret_column, caret_end - caret_start)
+}
+
+pub fn lexer_error_into_diagnostic(token: Token, source_manager: &SourceManager) -> Diagnostic {
+    let text = source_manager.get_span(token.span).unwrap();
+
+    let label = match token.tt {
+        TokenType::InvalidIntegerError => format!("Invalid integer value `{}`", text),
+        TokenType::InvalidLabelError => format!("Invalid label `{}`, labels cannot start with a digit", text),
+        TokenType::NumericLabelError => format!(
+            "Invalid label `{}`, labels cannot be numbers, give it a name that starts with a letter or `_`",
+            text
+        ),
+        TokenType::DashedLabelError => format!(
+            "Invalid label `{}`, labels cannot contain `-` unless --allow-dash-idents is given",
+            text
+        ),
+        TokenType::UnterminatedStringError => {
+            format!("Unterminated string `{}`, strings must end on the line they start on", text)
         }
+        _ => format!("Invalid token found `{}`", text),
+    };
+
+    Diagnostic::error_with_span(label, token.span)
+}
+
+/// How a kind of token the parser expected is named in a diagnostic
+fn describe_token_type(tt: TokenType) -> String {
+    match tt {
+        TokenType::Identifier => String::from("a name"),
+        TokenType::Comma => String::from("`,`"),
+        TokenType::RightParen => String::from("`)`"),
+        TokenType::Integer => String::from("an integer"),
+        TokenType::Newline => String::from("the end of the line"),
+        TokenType::String => String::from("a string in double quotes"),
+        _ => format!("`{:?}`", tt),
     }
 }
 
-pub(crate) fn parse_error_into_diagnostic(
+pub fn parse_error_into_diagnostic(
     error: ParseError,
     source_manager: &SourceManager,
 ) -> Diagnostic {
     match error {
-        ParseError::MissingToken(tt) => {
-            Diagnostic::error(format!("Expected `{:?}`, found the end of file", tt))
+        ParseError::MissingToken(tt, span) => {
+            let label = format!("Expected {} here, found the end of the file", describe_token_type(tt));
+
+            Diagnostic::error_with_span(label, span)
         }
         ParseError::UnexpectedToken(tt, t) => {
-            let text = source_manager.get_span(t.span).unwrap();
-            let label = format!("Expected `{:?}`, found `{}`", tt, text);
+            let found = if t.tt == TokenType::Newline {
+                String::from("the end of the line")
+            } else {
+                format!("`{}`", source_manager.get_span(t.span).unwrap())
+            };
+            let label = format!("Expected {}, found {}", describe_token_type(tt), found);
 
             Diagnostic::error_with_span(label, t.span)
         }
@@ -136,6 +541,17 @@ pub(crate) fn parse_error_into_diagnostic(
 
             Diagnostic::error_with_span(label, t.span)
         }
+        ParseError::SeededLabelDefined(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("Label `{}` is already given a value outside the source", &text[..text.len() - 1]);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::CommaAtStatementStart(t) => {
+            let label = "Unexpected `,` at the start of a statement, the operand after it may belong on the line above";
+
+            Diagnostic::error_with_span(label, t.span)
+        }
         ParseError::ExpectedNoOperands(t) => {
             let text = source_manager.get_span(t.span).unwrap();
             let label = format!("Instruction takes no operands, found `{}`", text);
@@ -148,23 +564,68 @@ pub(crate) fn parse_error_into_diagnostic(
 
             Diagnostic::error_with_span(label, t.span)
         }
-        ParseError::ExpectedOperand(t, expected) => {
+        ParseError::ExpectedOperand(t, operand_index, found, expected) => {
             let text = source_manager.get_span(t.span).unwrap();
+            let found_text = source_manager.get_span(found.span).unwrap();
             let label = format!(
-                "Expected instruction operand (one of {}), found `{}`",
-                expected, text
+                "`{}` expects {} as its {} operand, found `{}`",
+                text,
+                expected,
+                ordinal(operand_index),
+                found_text
             );
 
-            Diagnostic::error_with_span(label, t.span)
+            Diagnostic::error_with_span(label, found.span)
+        }
+        ParseError::RegisterAsLabel(t, operand_index, found, expected) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let found_text = source_manager.get_span(found.span).unwrap();
+            let label = format!(
+                "`{}` expects {} as its {} operand, found register `{}`",
+                text,
+                expected,
+                ordinal(operand_index),
+                found_text
+            );
+
+            Diagnostic::error_with_span(label, found.span)
         }
-        ParseError::ExpectedOperandFoundEOF(t) => {
+        ParseError::LabelNotAllowed(t, operand_index, found, expected) => {
             let text = source_manager.get_span(t.span).unwrap();
+            let found_text = source_manager.get_span(found.span).unwrap();
             let label = format!(
-                "Expected instruction operand for `{}`, found end of file",
+                "`{}` expects {} as its {} operand, found `{}`, which isn't a constant, and labels can't be used there",
+                text,
+                expected,
+                ordinal(operand_index),
+                found_text
+            );
+
+            Diagnostic::error_with_span(label, found.span)
+        }
+        ParseError::MissingOperand(t, operand_index, span) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!(
+                "Missing {} operand for `{}`",
+                ordinal(operand_index),
                 text
             );
 
-            Diagnostic::error_with_span(label, t.span)
+            Diagnostic::error_with_span(label, span)
+        }
+        ParseError::UnexpectedComma(t, operand_index, comma) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = if operand_index == 0 {
+                format!("Expected the first operand of `{}`, found `,`", text)
+            } else {
+                format!(
+                    "Expected the {} operand of `{}` after `,`, found another `,`",
+                    ordinal(operand_index),
+                    text
+                )
+            };
+
+            Diagnostic::error_with_span(label, comma.span)
         }
         ParseError::ExpectedRegister(t) => {
             let text = source_manager.get_span(t.span).unwrap();
@@ -175,14 +636,151 @@ pub(crate) fn parse_error_into_diagnostic(
 
             Diagnostic::error_with_span(label, t.span)
         }
-        ParseError::IntegerOutOfRange(t) => {
-            let label = format!("Value is out of range for an 8-bit signed integer value");
+        ParseError::NoSuchRegister(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("No such register `{}` (valid registers are r0 to r15)", text);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::ImmediateShift(t, amount) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!(
+                "`{}` can only shift by the amount in a register, load it into one with `ldi` first",
+                text
+            );
+
+            Diagnostic::error_with_span(label, amount.span)
+        }
+        ParseError::IntegerOutOfRange(t, role) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = integer_out_of_range_label(&format!("`{text}`"), role);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::ConstantOutOfRange(t, value, role) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = integer_out_of_range_label(&format!("`{text}` (which is {value})"), role);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::ExpressionOutOfRange(span, value, role) => {
+            let text = source_manager.get_span(span).unwrap();
+            let label = integer_out_of_range_label(&format!("`{text}` (which is {value})"), role);
+
+            Diagnostic::error_with_span(label, span)
+        }
+        ParseError::ExpressionOverflow(span) => expression_overflow_diagnostic(span, source_manager),
+        ParseError::InvalidDirective(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("`{}` is not a valid directive", text);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::NotAConstantDefinition(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("A constants file can only use `.set` and `.port`, found `{}`", text.trim_end());
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::DuplicatePort(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("Duplicate port `{}`", text);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::InvalidConstantName(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("`{}` cannot be used as the name of a constant, since it looks like a register", text);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::UnknownConstant(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("Constant `{}` is not defined, constants must be defined with `.set` before they are used", text);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::ConflictingVisibility(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("Label `{}` can't be both `.global` and `.local`", text);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::SignWithoutInteger(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("Expected an integer directly after `{}`", text);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::DuplicateMetaKey(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("Metadata `{}` is already given a value", text);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::UnclosedParen(t) => Diagnostic::error_with_span("This `(` is never closed", t.span),
+        ParseError::UnmatchedParen(t) => {
+            Diagnostic::error_with_span("This `)` has no `(` before it to close", t.span)
+        }
+        ParseError::ExpectedValue(t) => {
+            let found = if t.tt == TokenType::Newline {
+                String::from("the end of the line")
+            } else {
+                format!("`{}`", source_manager.get_span(t.span).unwrap())
+            };
+            let label = format!("Expected an integer, constant or label, found {}", found);
+
+            Diagnostic::error_with_span(label, t.span)
+        }
+        ParseError::UnknownPort(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!("Port `{}` is not defined, ports must be defined with `.port` before they are used", text);
+
             Diagnostic::error_with_span(label, t.span)
         }
     }
 }
 
-pub(crate) fn generator_error_into_diagnostic(
+pub fn parse_warning_into_diagnostic(
+    warning: ParseWarning,
+    source_manager: &SourceManager,
+) -> Diagnostic {
+    match warning {
+        ParseWarning::RelaxedImmediate(t, value) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let label = format!(
+                "Value `{}` is out of range for an 8-bit signed integer value, and was taken as {}",
+                text, value
+            );
+
+            Diagnostic::warning_with_span(label, t.span)
+        }
+        ParseWarning::UnusedConstant(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+
+            Diagnostic::warning_with_span(format!("Constant `{}` is never used", text), t.span)
+        }
+        ParseWarning::UnusedPort(t) => {
+            let text = source_manager.get_span(t.span).unwrap();
+
+            Diagnostic::warning_with_span(format!("Port `{}` is never used", text), t.span)
+        }
+        ParseWarning::UndefinedVisibility(t, visibility) => {
+            let text = source_manager.get_span(t.span).unwrap();
+            let directive = match visibility {
+                Visibility::Global => ".global",
+                Visibility::Local => ".local",
+            };
+
+            Diagnostic::warning_with_span(
+                format!("`{}` of label `{}`, which is never defined", directive, text),
+                t.span,
+            )
+        }
+    }
+}
+
+pub fn generator_error_into_diagnostic(
     error: GeneratorError,
     source_manager: &SourceManager,
 ) -> Diagnostic {
@@ -206,10 +804,10 @@ pub(crate) fn generator_error_into_diagnostic(
 
             Diagnostic::error_with_span(label, span)
         }
-        GeneratorError::MaximumInstructionsError => {
+        GeneratorError::MaximumInstructionsError(span) => {
             let label = format!("Maximum number of instructions reached ({MAX_NUM_INSTRUCTIONS})");
 
-            Diagnostic::error(label)
+            Diagnostic::error_with_span(label, span)
         }
         GeneratorError::UndefinedLabelError(span) => {
             let text = source_manager.get_span(span).unwrap();
@@ -217,5 +815,407 @@ pub(crate) fn generator_error_into_diagnostic(
 
             Diagnostic::error_with_span(label, span)
         }
+        GeneratorError::ForwardReferenceError(span) => {
+            let text = source_manager.get_span(span).unwrap();
+            let label = format!("Label `{text}` is used before it is defined");
+
+            Diagnostic::error_with_span(label, span)
+        }
+        GeneratorError::SlotRangeError(span, range) => {
+            let text = source_manager.get_span(span).unwrap();
+            let label = format!("`{text}` doesn't fit in the slot range {}:{}", range.start, range.end);
+
+            Diagnostic::error_with_span(label, span)
+        }
+        GeneratorError::AssertionFailed(span, message) => {
+            let label = match message {
+                Some(message) => format!("Assertion failed: {message}"),
+                None => {
+                    let text = source_manager.get_span(span).unwrap();
+                    format!("Assertion failed: `{text}`")
+                }
+            };
+
+            Diagnostic::error_with_span(label, span)
+        }
+        GeneratorError::DataOutOfRange(span, value, role) => {
+            let text = source_manager.get_span(span).unwrap();
+            let label = integer_out_of_range_label(&format!("`{text}` (which is {value})"), role);
+
+            Diagnostic::error_with_span(label, span)
+        }
+        GeneratorError::TooManyOperands(span) => {
+            let text = source_manager.get_span(span).unwrap();
+            let label = format!("`{text}` has more operands than an instruction can be encoded with");
+
+            Diagnostic::error_with_span(label, span)
+        }
+        GeneratorError::ExpressionOverflow(span) => expression_overflow_diagnostic(span, source_manager),
+        GeneratorError::MemorySizeError(span, len) => {
+            let label = format!(
+                "Output of {len} bytes exceeds the instruction memory size ({INSTRUCTION_MEMORY_SIZE_BYTES} bytes)"
+            );
+
+            Diagnostic::error_with_span(label, span)
+        }
+    }
+}
+
+/// The diagnostic for an operation in an expression whose result doesn't fit in 64 bits
+fn expression_overflow_diagnostic(span: Span, source_manager: &SourceManager) -> Diagnostic {
+    let text = source_manager.get_span(span).unwrap();
+    let label = format!("`{text}` is too large to work out, as it doesn't fit in a 64-bit signed integer");
+
+    Diagnostic::error_with_span(label, span)
+}
+
+/// The message for an integer out of range for `role`, where `found` describes the value given
+fn integer_out_of_range_label(found: &str, role: IntegerRole) -> String {
+    let range = role.range();
+
+    match role {
+        IntegerRole::Immediate => format!(
+            "Value {} is out of range for an 8-bit signed integer value ({} to {})",
+            found,
+            range.start(),
+            range.end()
+        ),
+        IntegerRole::Port => format!(
+            "Port must be in the range of {}-{}, found {}",
+            range.start(),
+            range.end(),
+            found
+        ),
+        IntegerRole::JumpDestination => format!(
+            "Jump destination must be in the range of {}-{}, found {}",
+            range.start(),
+            range.end(),
+            found
+        ),
+        IntegerRole::Byte => format!(
+            "Value {} does not fit in a byte ({} to {})",
+            found,
+            range.start(),
+            range.end()
+        ),
+        IntegerRole::Word => format!(
+            "Value {} does not fit in a word ({} to {})",
+            found,
+            range.start(),
+            range.end()
+        ),
+        IntegerRole::Constant => format!(
+            "Value {} is out of range for a constant ({} to {})",
+            found,
+            range.start(),
+            range.end()
+        ),
+        IntegerRole::Size => format!(
+            "Can't reserve {} bytes, the size must be in the range of {}-{}",
+            found,
+            range.start(),
+            range.end()
+        ),
+    }
+}
+
+fn ordinal(index: usize) -> &'static str {
+    match index {
+        0 => "first",
+        1 => "second",
+        2 => "third",
+        _ => "next",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 40 characters, so that every window of 10 is cut down
+    const LONG_LINE: &str = "0123456789abcdefghijklmnopqrstuvwxyzABCD";
+
+    #[test]
+    fn window_near_start_of_line() {
+        let (shown, caret_column, caret_len) = window_line(LONG_LINE, 1, 2, 10);
+
+        assert_eq!(shown, "0123456789...");
+        assert_eq!(&shown[caret_column..caret_column + caret_len], "12");
+    }
+
+    #[test]
+    fn window_in_middle_of_line() {
+        let (shown, caret_column, caret_len) = window_line(LONG_LINE, 20, 2, 10);
+
+        assert_eq!(shown, "...ghijklmnop...");
+        assert_eq!(&shown[caret_column..caret_column + caret_len], "kl");
+    }
+
+    #[test]
+    fn window_near_end_of_line() {
+        let (shown, caret_column, caret_len) = window_line(LONG_LINE, 38, 2, 10);
+
+        assert_eq!(shown, "...uvwxyzABCD");
+        assert_eq!(&shown[caret_column..caret_column + caret_len], "CD");
+    }
+
+    #[test]
+    fn window_narrower_than_span() {
+        let (shown, caret_column, caret_len) = window_line(LONG_LINE, 5, 30, 10);
+
+        assert_eq!(shown.len(), 16);
+        // The carets are cut off at the edges of the window
+        assert_eq!(caret_column, 3);
+        assert_eq!(caret_len, 10);
+    }
+
+    fn render(options: TerminalEmitterOptions, source: &str, diagnostic: &Diagnostic) -> String {
+        let source_manager = SourceManager::new(source, String::from("test.basm"));
+        let mut out = Vec::new();
+
+        TerminalEmitter::new(options)
+            .write_diagnostic(&mut out, diagnostic, &source_manager)
+            .unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    fn bad_register() -> Diagnostic {
+        Diagnostic::error_with_span("No such register `r99`", Span { index: 13, len: 3 })
+    }
+
+    #[test]
+    fn ascii_snippet() {
+        let options = TerminalEmitterOptions {
+            charset: Charset::Ascii,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(options, "nop\n\tadd r0, r99\n", &bad_register()),
+            "\
+error: No such register `r99`
+   --> test.basm:2:12
+ 2 |     add r0, r99
+                 ^^^
+"
+        );
+    }
+
+    #[test]
+    fn unicode_snippet() {
+        let options = TerminalEmitterOptions {
+            charset: Charset::Unicode,
+            ..Default::default()
+        };
+
+        // The underline is made of multi-byte characters, but still starts in the same column as the ASCII one
+        assert_eq!(
+            render(options, "nop\n\tadd r0, r99\n", &bad_register()),
+            "\
+error: No such register `r99`
+   ──▶ test.basm:2:12
+ 2 │     add r0, r99
+                 ━━━
+"
+        );
+    }
+
+    fn ascii_options() -> TerminalEmitterOptions {
+        TerminalEmitterOptions {
+            charset: Charset::Ascii,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merged_span_across_lines_stops_at_end_of_first_line() {
+        let source = "add r0, r1\nsub r2, r3\n";
+        // From `r1` on the first line to `r2` on the second
+        let span = Span { index: 8, len: 2 }.merge(Span { index: 15, len: 2 });
+        let diagnostic = Diagnostic::error_with_span("Operands don't match", span);
+
+        assert_eq!(
+            render(ascii_options(), source, &diagnostic),
+            "\
+error: Operands don't match
+   --> test.basm:1:8
+ 1 | add r0, r1
+             ^^ ...spans multiple lines
+"
+        );
+    }
+
+    #[test]
+    fn span_ending_at_newline_is_on_one_line() {
+        // `r1` and the newline after it
+        let diagnostic = Diagnostic::error_with_span("Unexpected end of line", Span { index: 8, len: 3 });
+
+        assert_eq!(
+            render(ascii_options(), "add r0, r1\nnop\n", &diagnostic),
+            "\
+error: Unexpected end of line
+   --> test.basm:1:8
+ 1 | add r0, r1
+             ^^
+"
+        );
+    }
+
+    #[test]
+    fn color_choice_precedence() {
+        use std::ffi::OsString;
+
+        // (choice, CLICOLOR_FORCE, NO_COLOR, CLICOLOR, stderr is a terminal, colored)
+        let cases = [
+            // The flag wins over everything
+            (ColorChoice::Never, Some("1"), None, None, true, false),
+            (ColorChoice::Always, None, Some("1"), Some("0"), false, true),
+            // Then forcing, even when piped and when told not to
+            (ColorChoice::Auto, Some("1"), Some("1"), Some("0"), false, true),
+            (ColorChoice::Auto, Some("0"), None, None, false, false),
+            // Then either of the ways to turn it off
+            (ColorChoice::Auto, None, Some("1"), None, true, false),
+            (ColorChoice::Auto, None, None, Some("0"), true, false),
+            (ColorChoice::Auto, None, None, Some("1"), true, true),
+            // Then whether it is a terminal, where empty variables count as unset
+            (ColorChoice::Auto, Some(""), Some(""), None, true, true),
+            (ColorChoice::Auto, None, None, None, false, false),
+        ];
+
+        for (choice, force, no_color, clicolor, is_terminal, colored) in cases {
+            let var = |name: &str| {
+                match name {
+                    "CLICOLOR_FORCE" => force,
+                    "NO_COLOR" => no_color,
+                    "CLICOLOR" => clicolor,
+                    _ => None,
+                }
+                .map(OsString::from)
+            };
+
+            assert_eq!(
+                choice.should_color_with(var, is_terminal),
+                colored,
+                "{choice:?} CLICOLOR_FORCE={force:?} NO_COLOR={no_color:?} CLICOLOR={clicolor:?} terminal={is_terminal}"
+            );
+        }
+    }
+
+    #[test]
+    fn zero_length_span_has_one_caret() {
+        // Just after `add r0,`, where the missing operand would go
+        let span = Span { index: 0, len: 7 }.point_at_end();
+        let diagnostic = Diagnostic::error_with_span("Missing second operand for `add`", span);
+
+        assert_eq!(
+            render(ascii_options(), "add r0,\n", &diagnostic),
+            "\
+error: Missing second operand for `add`
+   --> test.basm:1:7
+ 1 | add r0,
+            ^
+"
+        );
+    }
+
+    #[test]
+    fn dedup_keeps_first_of_each() {
+        let first = Span { index: 0, len: 3 };
+        let second = Span { index: 4, len: 3 };
+
+        let mut diagnostics = vec![
+            Diagnostic::error_with_span("Invalid token found `$`", first),
+            Diagnostic::error_with_span("Invalid token found `$`", first),
+            Diagnostic::warning_with_span("Invalid token found `$`", first),
+            Diagnostic::error_with_span("Invalid token found `$`", second),
+            Diagnostic::error_with_span("Invalid token found `$`", first),
+            Diagnostic::error("Invalid token found `$`"),
+        ];
+        dedup_diagnostics(&mut diagnostics);
+
+        // Only those the same in kind, message and span are removed, and the rest stay in order
+        assert_eq!(
+            diagnostics,
+            [
+                Diagnostic::error_with_span("Invalid token found `$`", first),
+                Diagnostic::warning_with_span("Invalid token found `$`", first),
+                Diagnostic::error_with_span("Invalid token found `$`", second),
+                Diagnostic::error("Invalid token found `$`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn short_and_rich_formats_of_one_error() {
+        let source = "nop\n\tadd r0, r99\n";
+        let short = TerminalEmitterOptions {
+            format: ErrorFormat::Short,
+            ..ascii_options()
+        };
+
+        let rich_output = render(ascii_options(), source, &bad_register());
+        let short_output = render(short, source, &bad_register());
+
+        // The same position, without the snippet
+        assert_eq!(short_output, "test.basm:2:12: error: No such register `r99`\n");
+        assert!(rich_output.contains("   --> test.basm:2:12\n"), "{rich_output}");
+        assert!(rich_output.starts_with("error: No such register `r99`\n"), "{rich_output}");
+    }
+
+    #[test]
+    fn short_format_without_a_span() {
+        let short = TerminalEmitterOptions {
+            format: ErrorFormat::Short,
+            ..ascii_options()
+        };
+
+        assert_eq!(
+            render(short, "nop\n", &Diagnostic::warning("Nothing to assemble")),
+            "test.basm: warning: Nothing to assemble\n"
+        );
+    }
+
+    fn first_error_rendered(source: &str) -> String {
+        let source_manager = SourceManager::new(source, String::from("test.basm"));
+        let diagnostics = crate::assemble(&source_manager, &crate::AssembleOptions::default()).unwrap_err();
+
+        render(ascii_options(), source, &diagnostics[0])
+    }
+
+    #[test]
+    fn caret_after_last_token_at_end_of_file() {
+        // Both the comma and the second operand are missing, and the caret goes right after `r0`
+        assert_eq!(
+            first_error_rendered("add r0"),
+            "\
+error: Missing second operand for `add`
+   --> test.basm:1:6
+ 1 | add r0
+           ^
+"
+        );
+        assert_eq!(
+            first_error_rendered(".set X"),
+            "\
+error: Expected `,` here, found the end of the file
+   --> test.basm:1:6
+ 1 | .set X
+           ^
+"
+        );
+    }
+
+    #[test]
+    fn caret_at_end_of_crlf_line() {
+        assert_eq!(
+            first_error_rendered("nop\r\nadd r0,\r\nnop\r\n"),
+            "\
+error: Missing second operand for `add`
+   --> test.basm:2:7
+ 2 | add r0,
+            ^
+"
+        );
     }
 }
@@ -0,0 +1,892 @@
+use std::{collections::HashMap, io::Write};
+
+use crate::{
+    ast::{DataValue, DataWidth, Item, MetaEntry, MetaValue, Opcode, Operand, Register},
+    generator::{DataRecord, InstructionRecord, INSTRUCTION_MEMORY_SIZE_BYTES, INSTRUCTION_SIZE_BYTES},
+    instructions::CycleTable,
+    json,
+    lexer::Span,
+    parser::{LabelManager, Visibility},
+    sources::SourceManager,
+    SymbolInfo,
+};
+
+/// An algorithm for the optional checksum byte appended to the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Checksum {
+    /// The bytes added together, wrapping on overflow
+    Sum,
+    /// The bytes combined with exclusive or
+    Xor,
+}
+
+impl Checksum {
+    pub fn compute(self, bytes: &[u8]) -> u8 {
+        match self {
+            Self::Sum => bytes.iter().fold(0, |acc, b| acc.wrapping_add(*b)),
+            Self::Xor => bytes.iter().fold(0, |acc, b| acc ^ b),
+        }
+    }
+}
+
+/// A 64-bit FNV-1a hash of `bytes`, which is the same on every platform and across runs, for telling whether an
+/// assembled program has changed
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes
+        .iter()
+        .fold(OFFSET_BASIS, |hash, &b| (hash ^ b as u64).wrapping_mul(PRIME))
+}
+
+/// The format the assembled program is written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// The raw bytes of the program
+    #[default]
+    Bin,
+    /// A C header declaring the program as an array of bytes
+    CArray,
+    /// Each instruction as hex on its own line, next to its source
+    Hexlines,
+    /// A picture of memory, 8 bytes to a row with each word boxed on its own
+    Grid,
+    /// The raw bytes of the program, along with a summary of it printed as JSON to standard output for build tools
+    Json,
+}
+
+/// Writes the assembled program in one of the output [`Format`]s
+pub trait OutputFormat {
+    /// The extension of the output file when no output path is given
+    fn extension(&self) -> &'static str;
+
+    /// Writes the output, which is padded and checksummed if that was asked for
+    fn write(&self, bytes: &[u8], out: &mut dyn Write) -> std::io::Result<()>;
+}
+
+/// The raw bytes, unchanged
+pub struct Bin;
+
+impl OutputFormat for Bin {
+    fn extension(&self) -> &'static str {
+        "bin"
+    }
+
+    fn write(&self, bytes: &[u8], out: &mut dyn Write) -> std::io::Result<()> {
+        out.write_all(bytes)
+    }
+}
+
+/// The bytes as a C array named `name`, along with a define holding its length, and a comment for each `.meta` entry
+///
+/// The define is the array name in upper case followed by `_LEN`, so `program` gives `PROGRAM_LEN`
+pub struct CArray<'a> {
+    pub name: &'a str,
+    pub metadata: &'a [MetaEntry],
+}
+
+impl OutputFormat for CArray<'_> {
+    fn extension(&self) -> &'static str {
+        "h"
+    }
+
+    fn write(&self, bytes: &[u8], out: &mut dyn Write) -> std::io::Result<()> {
+        for entry in self.metadata {
+            writeln!(out, "// {}: {}", entry.key, json_meta_value(&entry.value))?;
+        }
+
+        if !self.metadata.is_empty() {
+            writeln!(out)?;
+        }
+
+        writeln!(out, "#include <stdint.h>")?;
+        writeln!(out)?;
+        writeln!(out, "#define {}_LEN {}", self.name.to_uppercase(), bytes.len())?;
+        writeln!(out)?;
+        writeln!(out, "const uint8_t {}[] = {{", self.name)?;
+
+        for chunk in bytes.chunks(8) {
+            let literals: Vec<String> = chunk.iter().map(|b| format!("0x{:02x},", b)).collect();
+
+            writeln!(out, "    {}", literals.join(" "))?;
+        }
+
+        writeln!(out, "}};")?;
+
+        Ok(())
+    }
+}
+
+/// Each instruction's two bytes as hex on its own line, followed by the source it was generated from and any comment
+/// at the end of its line, after a comment line for each `.meta` entry
+///
+/// Unlike the listing, this leaves out addresses and lines without instructions, so that assemblies of different
+/// versions of a program can be diffed. Padding isn't included.
+pub struct Hexlines<'a> {
+    pub records: &'a [InstructionRecord],
+    pub source_manager: &'a SourceManager<'a>,
+    pub metadata: &'a [MetaEntry],
+}
+
+impl OutputFormat for Hexlines<'_> {
+    fn extension(&self) -> &'static str {
+        "hex"
+    }
+
+    fn write(&self, bytes: &[u8], out: &mut dyn Write) -> std::io::Result<()> {
+        for entry in self.metadata {
+            writeln!(out, "; {}: {}", entry.key, json_meta_value(&entry.value))?;
+        }
+
+        for record in self.records {
+            let text = self.source_manager.get_span(record.span).unwrap_or_default();
+            let comment = record
+                .comment
+                .and_then(|comment| self.source_manager.get_span(comment).ok())
+                .map(|comment| format!(" {}", comment))
+                .unwrap_or_default();
+
+            writeln!(
+                out,
+                "{:02x}{:02x}  ; {}{}",
+                bytes[record.address],
+                bytes[record.address + 1],
+                text,
+                comment
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A picture of memory with the address of each row down the side and every 2-byte word in its own box, 8 bytes to a
+/// row
+///
+/// When `mnemonics` is set, each row is followed by the mnemonic of every word generated from an instruction, leaving
+/// data and padding blank
+pub struct Grid<'a> {
+    pub records: &'a [InstructionRecord],
+    /// Offset added to every displayed address
+    pub base_address: u32,
+    pub mnemonics: bool,
+}
+
+impl OutputFormat for Grid<'_> {
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn write(&self, bytes: &[u8], out: &mut dyn Write) -> std::io::Result<()> {
+        const ROW_BYTES: usize = 8;
+
+        let mnemonics: HashMap<usize, &str> = self
+            .records
+            .iter()
+            .map(|record| (record.address, record.opcode.mnemonic()))
+            .collect();
+
+        let offsets: Vec<String> = (0..ROW_BYTES)
+            .step_by(2)
+            .map(|offset| format!("{:<5}", format!("+{}", offset)))
+            .collect();
+
+        let header = format!("{:4}   {}", "", offsets.join("   "));
+        writeln!(out, "{}", header.trim_end())?;
+
+        for (row, row_bytes) in bytes.chunks(ROW_BYTES).enumerate() {
+            let address = row * ROW_BYTES;
+            let words: Vec<String> = row_bytes
+                .chunks(2)
+                .map(|word| match word {
+                    [first, second] => format!("{:02x} {:02x}", first, second),
+                    // Only a checksum can leave a byte on its own
+                    [single] => format!("{:02x}   ", single),
+                    _ => unreachable!(),
+                })
+                .collect();
+
+            writeln!(
+                out,
+                "{:04x} | {} |",
+                self.base_address as usize + address,
+                words.join(" | ")
+            )?;
+
+            if self.mnemonics {
+                let names: Vec<String> = (0..words.len())
+                    .map(|word| {
+                        let name = mnemonics.get(&(address + word * 2)).copied().unwrap_or("");
+                        format!("{:<5}", name)
+                    })
+                    .collect();
+
+                writeln!(out, "{:4} | {} |", "", names.join(" | "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct ListingOptions<'a> {
+    /// Offset added to every displayed address
+    pub base_address: u32,
+    /// When present, each instruction is annotated with its cost and a running total from this table
+    pub cycles: Option<&'a CycleTable>,
+}
+
+/// Writes a listing showing every source line alongside the address and bytes generated for it
+///
+/// Data is shown two bytes to a line in the same columns as instructions, with any bytes after the first two on lines
+/// of their own beneath it. `output` is the unpadded generator output
+pub fn write_listing(
+    out: &mut dyn Write,
+    output: &[u8],
+    records: &[InstructionRecord],
+    data_records: &[DataRecord],
+    source_manager: &SourceManager,
+    options: &ListingOptions,
+) -> std::io::Result<()> {
+    let mut records_by_line = HashMap::new();
+    let mut data_records_by_line = HashMap::new();
+
+    for record in records {
+        if let Ok((_, line_number, _)) = source_manager.get_span_line(record.span) {
+            records_by_line.insert(line_number, record);
+        }
+    }
+
+    for record in data_records {
+        if let Ok((_, line_number, _)) = source_manager.get_span_line(record.span) {
+            data_records_by_line.insert(line_number, record);
+        }
+    }
+
+    let mut total_cycles = 0;
+    // Data takes no cycles, so its cycle columns are left blank
+    let no_cycles = if options.cycles.is_some() { " ".repeat(11) } else { String::new() };
+
+    for (line_index, line) in source_manager.lines().enumerate() {
+        let line_number = line_index as u32 + 1;
+        // Fixes tab rendering to be what we define
+        let line_fixed = line.replace('\t', "    ");
+
+        if let Some(record) = records_by_line.get(&line_number) {
+            let address = options.base_address as usize + record.address;
+            let cycles = if let Some(cycle_table) = options.cycles {
+                let cost = cycle_table.cost_of(record.opcode);
+                total_cycles += cost;
+
+                format!("{:>3} {:>5}  ", cost, total_cycles)
+            } else {
+                String::new()
+            };
+
+            writeln!(
+                out,
+                "{:>4}  {:04x}  {:02x} {:02x}  {}{}",
+                line_number,
+                address,
+                output[record.address],
+                output[record.address + 1],
+                cycles,
+                line_fixed
+            )?;
+        } else if let Some(record) = data_records_by_line.get(&line_number) {
+            let bytes = &output[record.address..record.address + record.len];
+
+            for (i, word) in bytes.chunks(2).enumerate() {
+                let address = options.base_address as usize + record.address + i * 2;
+                let word = match word {
+                    [first, second] => format!("{:02x} {:02x}", first, second),
+                    // Only a checksum can leave a byte on its own
+                    [single] => format!("{:02x}   ", single),
+                    _ => unreachable!(),
+                };
+
+                if i == 0 {
+                    writeln!(out, "{:>4}  {:04x}  {}  {}{}", line_number, address, word, no_cycles, line_fixed)?;
+                } else {
+                    writeln!(out, "{:4}  {:04x}  {}", "", address, word)?;
+                }
+            }
+        } else {
+            let padding = if options.cycles.is_some() { 26 } else { 15 };
+            let line = format!("{:>4}{:padding$}{}", line_number, "", line_fixed);
+
+            writeln!(out, "{}", line.trim_end())?;
+        }
+    }
+
+    if options.cycles.is_some() {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "estimated {} cycles for straight-line execution (branches are ignored)",
+            total_cycles
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The order of the lines of a symbol table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SymbolOrder {
+    /// The order the labels are first seen in the source, which may be where one is used before it is defined
+    #[default]
+    Source,
+    Name,
+    /// By address, with labels at the same address in source order
+    Address,
+}
+
+/// Writes the address of every symbol, one per line, in `order`
+///
+/// Displayed addresses are byte addresses offset by `base_address`
+pub fn write_symbols(
+    out: &mut dyn Write,
+    symbols: &[SymbolInfo],
+    base_address: u32,
+    order: SymbolOrder,
+) -> std::io::Result<()> {
+    let mut symbols: Vec<&SymbolInfo> = symbols.iter().collect();
+
+    match order {
+        SymbolOrder::Source => {}
+        SymbolOrder::Name => symbols.sort_by(|a, b| a.name.cmp(&b.name)),
+        SymbolOrder::Address => symbols.sort_by_key(|symbol| symbol.value),
+    }
+
+    for symbol in symbols {
+        // Label values are instruction indices, which are two bytes apart
+        let address = base_address as usize + symbol.value as usize * 2;
+
+        writeln!(out, "{:04x} {}", address, symbol.name)?;
+    }
+
+    Ok(())
+}
+
+/// What a 2-byte slot of instruction memory holds, as shown in an address map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotKind {
+    Code,
+    Data,
+    /// Padding, either after the program or before the start of its slot range
+    Free,
+}
+
+impl SlotKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Code => "code",
+            Self::Data => "data",
+            Self::Free => "free",
+        }
+    }
+}
+
+/// Writes a map of instruction memory, one line per region of code, data or free space, along with the labels at the
+/// start of each region
+///
+/// A region is split wherever a label points, so that every label is at the start of a line. Displayed addresses are
+/// byte addresses offset by `base_address`, and `byte_len` is the length of the unpadded output.
+pub fn write_map(
+    out: &mut dyn Write,
+    byte_len: usize,
+    records: &[InstructionRecord],
+    data_records: &[DataRecord],
+    symbols: &[SymbolInfo],
+    base_address: u32,
+) -> std::io::Result<()> {
+    let memory_size = (INSTRUCTION_MEMORY_SIZE_BYTES as usize).max(byte_len);
+    let slot_size = INSTRUCTION_SIZE_BYTES as usize;
+
+    let kind_at = |address: usize| {
+        if records.iter().any(|record| record.address == address) {
+            SlotKind::Code
+        } else if data_records
+            .iter()
+            .any(|record| (record.address..record.address + record.len).contains(&address))
+        {
+            SlotKind::Data
+        } else {
+            SlotKind::Free
+        }
+    };
+    // Label values are instruction indices, which are two bytes apart
+    let labels_at = |address: usize| {
+        symbols
+            .iter()
+            .filter(|symbol| symbol.value as usize * slot_size == address)
+            .map(|symbol| symbol.name.as_str())
+            .collect::<Vec<_>>()
+    };
+
+    writeln!(out, "start  end    bytes  kind  labels")?;
+
+    let mut start = 0;
+
+    while start < memory_size {
+        let kind = kind_at(start);
+        let mut end = start + slot_size;
+
+        while end < memory_size && kind_at(end) == kind && labels_at(end).is_empty() {
+            end += slot_size;
+        }
+
+        let line = format!(
+            "{:04x}   {:04x}   {:>5}  {:<4}  {}",
+            base_address as usize + start,
+            base_address as usize + end - 1,
+            end - start,
+            kind.as_str(),
+            labels_at(start).join(", ")
+        );
+        writeln!(out, "{}", line.trim_end())?;
+
+        start = end;
+    }
+
+    Ok(())
+}
+
+/// Writes how many times each register is read and written by the instructions in `items`, followed by how many
+/// distinct registers are used and which ones aren't
+///
+/// A register that is both read and replaced, such as the first operand of `add`, counts as one of each
+pub fn write_register_usage(out: &mut dyn Write, items: &[Item]) -> std::io::Result<()> {
+    let mut reads = [0usize; 16];
+    let mut writes = [0usize; 16];
+
+    for item in items {
+        if let Item::Instruction(instruction, _, _) = item {
+            for register in instruction.registers_read() {
+                reads[register.encode() as usize] += 1;
+            }
+
+            for register in instruction.registers_written() {
+                writes[register.encode() as usize] += 1;
+            }
+        }
+    }
+
+    writeln!(out, "register  reads  writes")?;
+
+    let mut unused = Vec::new();
+
+    for register in (0..16).map(Register::decode) {
+        let index = register.encode() as usize;
+
+        if reads[index] == 0 && writes[index] == 0 {
+            unused.push(register.name());
+        }
+
+        writeln!(out, "{:<8}  {:>5}  {:>6}", register.name(), reads[index], writes[index])?;
+    }
+
+    writeln!(out, "used: {}/16", 16 - unused.len())?;
+
+    if unused.is_empty() {
+        writeln!(out, "unused: none")
+    } else {
+        writeln!(out, "unused: {}", unused.join(", "))
+    }
+}
+
+/// Writes how many instructions run from the start of the program up to and including the first `j`, as if no
+/// conditional jump is taken, along with the estimated cycles they take from `cycle_table`
+///
+/// The path also ends at the first data, which would be run as if it were instructions, or at the end of the program
+pub fn write_straight_line_length(
+    out: &mut dyn Write,
+    items: &[Item],
+    cycle_table: &CycleTable,
+) -> std::io::Result<()> {
+    let mut instructions = 0;
+    let mut cycles = 0;
+
+    for item in items {
+        match item {
+            Item::Instruction(instruction, _, _) => {
+                instructions += 1;
+                cycles += cycle_table.cost_of(instruction.opcode());
+
+                if instruction.opcode() == Opcode::J {
+                    break;
+                }
+            }
+            Item::Data(..) | Item::Checksum(_) => break,
+            Item::Label(_) | Item::Assert(..) => {}
+        }
+    }
+
+    writeln!(out, "{}, {}", count_of(instructions, "instruction"), count_of(cycles, "cycle"))
+}
+
+/// `count` followed by `noun`, made plural unless there is exactly one
+fn count_of(count: u32, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+/// Writes every `.meta` entry as a JSON object from key to value, in source order
+pub fn write_metadata(out: &mut dyn Write, metadata: &[MetaEntry]) -> std::io::Result<()> {
+    let members: Vec<String> = metadata
+        .iter()
+        .map(|entry| format!("{}:{}", json::quote(&entry.key), json_meta_value(&entry.value)))
+        .collect();
+
+    writeln!(out, "{{{}}}", members.join(","))
+}
+
+/// A `.meta` value as a JSON number or string, which also reads naturally in a comment
+fn json_meta_value(value: &MetaValue) -> String {
+    match value {
+        MetaValue::Integer(value) => value.to_string(),
+        MetaValue::String(text) => json::quote(text),
+    }
+}
+
+/// Writes a JSON object on one line saying that a program was assembled, with how many instruction slots and bytes it
+/// takes up, as in [`crate::AssembleResult`], and where the output was written
+///
+/// A `hash` from [`fingerprint`] is included in hex, in the same form as `--print-hash` prints it
+pub fn write_json_summary(
+    out: &mut dyn Write,
+    instruction_count: usize,
+    byte_len: usize,
+    output_path: &str,
+    hash: Option<u64>,
+) -> std::io::Result<()> {
+    let hash = hash.map_or(String::new(), |hash| format!(",\"hash\":\"{hash:016x}\""));
+
+    writeln!(
+        out,
+        "{{\"ok\":true,\"instruction_count\":{},\"byte_len\":{},\"output_path\":{}{}}}",
+        instruction_count,
+        byte_len,
+        json::quote(output_path),
+        hash
+    )
+}
+
+/// Writes a Makefile rule saying that `target` depends on every file in `dependencies`, so a build system knows to
+/// assemble again when any of them changes
+pub fn write_depfile(out: &mut dyn Write, target: &str, dependencies: &[&str]) -> std::io::Result<()> {
+    write!(out, "{}:", escape_make_path(target))?;
+
+    for dependency in dependencies {
+        write!(out, " {}", escape_make_path(dependency))?;
+    }
+
+    writeln!(out)
+}
+
+/// Escapes the characters Make would otherwise treat specially in a file name
+fn escape_make_path(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+
+    for c in path.chars() {
+        match c {
+            ' ' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '$' => escaped.push_str("$$"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Writes the parsed program as a JSON array with an object for each label, instruction and piece of data, in source
+/// order
+///
+/// Every object has a `kind` of `label`, `instruction`, `data`, `checksum` or `assert`, and a `span` with the `index` and `length` in bytes
+/// of where it is in the source. Label addresses are byte addresses, or null if the label has no value.
+pub fn write_json_ast(
+    out: &mut dyn Write,
+    items: &[Item],
+    label_manager: &LabelManager,
+) -> std::io::Result<()> {
+    let objects: Vec<String> = items
+        .iter()
+        .map(|item| match item {
+            Item::Label(label_id) => format!(
+                "{{\"kind\":\"label\",{},\"span\":{}}}",
+                json_label(*label_id, label_manager),
+                json_optional_span(label_manager.get_span_of(*label_id))
+            ),
+            Item::Instruction(instruction, span, comment) => {
+                let operands: Vec<String> = instruction
+                    .operands()
+                    .iter()
+                    .map(|operand| json_operand(operand, label_manager))
+                    .collect();
+
+                format!(
+                    "{{\"kind\":\"instruction\",\"opcode\":{},\"operands\":[{}],\"span\":{},\"comment\":{}}}",
+                    json::quote(instruction.opcode().mnemonic()),
+                    operands.join(","),
+                    json_span(*span),
+                    json_optional_span(*comment)
+                )
+            }
+            Item::Checksum(span) => format!("{{\"kind\":\"checksum\",\"span\":{}}}", json_span(*span)),
+            Item::Assert(assertion, span) => format!(
+                "{{\"kind\":\"assert\",\"message\":{},\"span\":{}}}",
+                assertion
+                    .message
+                    .as_deref()
+                    .map_or(String::from("null"), json::quote),
+                json_span(*span)
+            ),
+            Item::Data(data, span) => {
+                // A value that refers to labels isn't known until generating
+                let values: Vec<String> = data
+                    .values
+                    .iter()
+                    .map(|value| match value {
+                        DataValue::Known(value) => value.to_string(),
+                        DataValue::Deferred(_) => String::from("null"),
+                    })
+                    .collect();
+                let width = match data.width {
+                    DataWidth::Byte => "byte",
+                    DataWidth::Word => "word",
+                };
+
+                format!(
+                    "{{\"kind\":\"data\",\"width\":\"{}\",\"values\":[{}],\"span\":{}}}",
+                    width,
+                    values.join(","),
+                    json_span(*span)
+                )
+            }
+        })
+        .collect();
+
+    writeln!(out, "[{}]", objects.join(","))
+}
+
+/// An operand as a JSON object with its `kind` of `register`, `integer` or `label`
+fn json_operand(operand: &Operand, label_manager: &LabelManager) -> String {
+    match operand {
+        Operand::Register { value, span } => format!(
+            "{{\"kind\":\"register\",\"value\":{},\"span\":{}}}",
+            json::quote(&value.name()),
+            json_span(*span)
+        ),
+        Operand::Integer { value, span } => format!(
+            "{{\"kind\":\"integer\",\"value\":{},\"span\":{}}}",
+            value,
+            json_span(*span)
+        ),
+        Operand::Label { value, span } => format!(
+            "{{\"kind\":\"label\",{},\"span\":{}}}",
+            json_label(*value, label_manager),
+            json_span(*span)
+        ),
+    }
+}
+
+/// The `id`, `name`, `address` and `global` fields of a label
+fn json_label(label_id: usize, label_manager: &LabelManager) -> String {
+    let address = label_manager
+        .get_value_of(label_id)
+        // Label values are instruction indices, which are two bytes apart
+        .map_or(String::from("null"), |value| (value as i32 * 2).to_string());
+
+    format!(
+        "\"id\":{},\"name\":{},\"address\":{},\"global\":{}",
+        label_id,
+        json::quote(label_manager.get_name_of(label_id).unwrap_or_default()),
+        address,
+        label_manager.get_visibility_of(label_id) == Visibility::Global
+    )
+}
+
+fn json_span(span: Span) -> String {
+    format!("{{\"index\":{},\"length\":{}}}", span.index, span.len)
+}
+
+fn json_optional_span(span: Option<Span>) -> String {
+    span.map_or(String::from("null"), json_span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_to_string(format: &dyn OutputFormat, bytes: &[u8]) -> String {
+        let mut out = Vec::new();
+        format.write(bytes, &mut out).unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn c_array() {
+        let format = CArray {
+            name: "blink",
+            metadata: &[],
+        };
+        let bytes = [0x20, 0x01, 0xf0, 0x01, 0xb1, 0x10, 0xf0, 0x03, 0x0a, 0xff];
+
+        assert_eq!(
+            write_to_string(&format, &bytes),
+            "\
+#include <stdint.h>
+
+#define BLINK_LEN 10
+
+const uint8_t blink[] = {
+    0x20, 0x01, 0xf0, 0x01, 0xb1, 0x10, 0xf0, 0x03,
+    0x0a, 0xff,
+};
+"
+        );
+    }
+
+    #[test]
+    fn c_array_of_nothing() {
+        let format = CArray {
+            name: "program",
+            metadata: &[],
+        };
+
+        let output = write_to_string(&format, &[]);
+
+        assert!(output.contains("#define PROGRAM_LEN 0\n"));
+        assert!(output.ends_with("const uint8_t program[] = {\n};\n"));
+    }
+
+    #[test]
+    fn checksums() {
+        let bytes = [0x20, 0x01, 0xf0, 0x01, 0xb1, 0x10, 0xf0, 0x03];
+
+        // 0x20 + 0x01 + 0xf0 + 0x01 + 0xb1 + 0x10 + 0xf0 + 0x03 = 0x2c6, which wraps to 0xc6
+        assert_eq!(Checksum::Sum.compute(&bytes), 0xc6);
+        assert_eq!(Checksum::Xor.compute(&bytes), 0x82);
+        assert_eq!(Checksum::Sum.compute(&[]), 0);
+        assert_eq!(Checksum::Xor.compute(&[0xff, 0xff]), 0);
+    }
+
+    #[test]
+    fn every_format_parses_from_its_name() {
+        use clap::ValueEnum;
+
+        let names: Vec<String> = Format::value_variants()
+            .iter()
+            .map(|format| format.to_possible_value().unwrap().get_name().to_string())
+            .collect();
+
+        assert_eq!(names, ["bin", "c-array", "hexlines", "grid", "json"]);
+
+        for (format, name) in Format::value_variants().iter().zip(names.iter()) {
+            assert_eq!(Format::from_str(name, false).as_ref(), Ok(format));
+        }
+    }
+
+    #[test]
+    fn depfile_escapes_make_characters() {
+        let mut out = Vec::new();
+        write_depfile(&mut out, "my prog.bin", &["my prog.basm", "#1.inc", "$cost.inc"]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "my\\ prog.bin: my\\ prog.basm \\#1.inc $$cost.inc\n"
+        );
+    }
+
+    #[test]
+    fn json_summary_quotes_the_output_path() {
+        let mut out = Vec::new();
+        write_json_summary(&mut out, 3, 6, "my \"prog\".bin", None).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"ok\":true,\"instruction_count\":3,\"byte_len\":6,\"output_path\":\"my \\\"prog\\\".bin\"}\n"
+        );
+    }
+
+    fn straight_line_length_of(source: &str, cycle_table: &CycleTable) -> String {
+        let source_manager = crate::sources::SourceManager::new(source, String::from("test.basm"));
+        let generator = crate::parse_program(&source_manager, &crate::AssembleOptions::default()).unwrap();
+        let mut out = Vec::new();
+        write_straight_line_length(&mut out, generator.items(), cycle_table).unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn straight_line_length_stops_at_first_j() {
+        // The `jz` isn't taken, and nothing after the early `j` is counted
+        let source = "\
+    ldi r0, 1
+    jz r0, skip
+    add r0, r0
+    j skip
+    sub r0, r0
+    inv r0
+skip:
+    j skip
+";
+        let mut cycle_table = CycleTable::new();
+
+        assert_eq!(straight_line_length_of(source, &cycle_table), "4 instructions, 4 cycles\n");
+
+        cycle_table.set_cost(Opcode::J, 3);
+        cycle_table.set_cost(Opcode::Jz, 2);
+        assert_eq!(straight_line_length_of(source, &cycle_table), "4 instructions, 7 cycles\n");
+    }
+
+    #[test]
+    fn straight_line_length_stops_at_data_or_the_end() {
+        let cycle_table = CycleTable::new();
+
+        assert_eq!(
+            straight_line_length_of("start:\n    nop\n    .byte 1\n    j start\n", &cycle_table),
+            "1 instruction, 1 cycle\n"
+        );
+        assert_eq!(straight_line_length_of("    nop\n    nop\n", &cycle_table), "2 instructions, 2 cycles\n");
+        assert_eq!(straight_line_length_of("", &cycle_table), "0 instructions, 0 cycles\n");
+    }
+
+    #[test]
+    fn fingerprint_is_fnv_1a() {
+        // Test vectors from the FNV reference
+        assert_eq!(fingerprint(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fingerprint(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fingerprint(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn json_summary_with_a_hash() {
+        let mut out = Vec::new();
+        write_json_summary(&mut out, 1, 2, "prog.bin", Some(0xab)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"ok\":true,\"instruction_count\":1,\"byte_len\":2,\"output_path\":\"prog.bin\",\"hash\":\"00000000000000ab\"}\n"
+        );
+    }
+
+    #[test]
+    fn straight_line_length_of_one_instruction() {
+        let mut cycle_table = CycleTable::new();
+
+        assert_eq!(straight_line_length_of("a: j a\n", &cycle_table), "1 instruction, 1 cycle\n");
+
+        cycle_table.set_cost(Opcode::J, 2);
+        assert_eq!(straight_line_length_of("a: j a\n", &cycle_table), "1 instruction, 2 cycles\n");
+    }
+}
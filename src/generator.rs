@@ -1,69 +1,351 @@
+use std::ops::Range;
+
 use crate::{
-    ast::{Instruction, Item, Opcode, Operand, Register},
+    ast::{DataValue, DataWidth, Instruction, Item, LabelId, Opcode, Operand, Overflow, Register},
+    instructions::IntegerRole,
     lexer::Span, parser::LabelManager,
 };
 
-pub(crate) const INSTRUCTION_MEMORY_SIZE_BYTES: i8 = 64;
-const INSTRUCTION_SIZE_BYTES: i8 = 2;
-pub(crate) const MAX_NUM_INSTRUCTIONS: i8 = INSTRUCTION_MEMORY_SIZE_BYTES / INSTRUCTION_SIZE_BYTES;
+pub const INSTRUCTION_MEMORY_SIZE_BYTES: i8 = 64;
+pub(crate) const INSTRUCTION_SIZE_BYTES: i8 = 2;
+pub const MAX_NUM_INSTRUCTIONS: i8 = INSTRUCTION_MEMORY_SIZE_BYTES / INSTRUCTION_SIZE_BYTES;
+pub const MAX_SOURCE_OR_SINK: u8 = 0b1111;
+/// Written into register fields the processor ignores, such as that of `j`, so that output is always the same
+/// and the disassembler can tell instructions from data
+const UNUSED_REGISTER: Register = Register::R0;
+/// Written as the second byte of instructions that don't use it, such as `inv` and `nop`, for the same reasons
+const UNUSED_IMMEDIATE: i8 = 0;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
-pub(crate) enum GeneratorError {
+pub enum GeneratorError {
     SourceOrSinkRangeError(Span),
     DanglingLabelError(Span),
-    MaximumInstructionsError,
+    /// The instruction or piece of data that doesn't fit in instruction memory
+    MaximumInstructionsError(Span),
     UndefinedLabelError(Span),
     JumpDestinationRangeError(Span),
+    /// Data, `.space` or a checksum that would run past the end of instruction memory, along with the number of bytes
+    /// the output would take up up to the end of it
+    MemorySizeError(Span, usize),
+    /// A label referred to before the line it is defined on, when forward references are forbidden
+    ForwardReferenceError(Span),
+    /// An instruction or piece of data that would be placed past the end of the slot range
+    SlotRangeError(Span, SlotRange),
+    /// An `.assert` whose condition was false, along with its message
+    AssertionFailed(Span, Option<String>),
+    /// A data value that refers to labels and works out to a value out of range for its width
+    DataOutOfRange(Span, i64, IntegerRole),
+    /// An instruction with more operands than any encoding has room for, which the parser only produces for rules
+    /// that have no encoding yet
+    TooManyOperands(Span),
+    /// An operation in an expression that refers to labels whose result doesn't fit in 64 bits
+    ExpressionOverflow(Span),
+}
+
+impl GeneratorError {
+    /// Where in the source the error is reported, the same place its diagnostic points to
+    ///
+    /// None for errors about the program as a whole, though every error so far points at the item that caused it
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::SourceOrSinkRangeError(span)
+            | Self::DanglingLabelError(span)
+            | Self::UndefinedLabelError(span)
+            | Self::JumpDestinationRangeError(span)
+            | Self::ForwardReferenceError(span)
+            | Self::SlotRangeError(span, _)
+            | Self::AssertionFailed(span, _)
+            | Self::DataOutOfRange(span, _, _)
+            | Self::MaximumInstructionsError(span)
+            | Self::TooManyOperands(span)
+            | Self::ExpressionOverflow(span)
+            | Self::MemorySizeError(span, _) => Some(*span),
+        }
+    }
+}
+
+impl From<Overflow> for GeneratorError {
+    fn from(overflow: Overflow) -> Self {
+        Self::ExpressionOverflow(overflow.0)
+    }
+}
+
+/// The instruction slots `start` up to but not including `end` that a program must be placed in, such as a routine
+/// loaded as an overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The order the two bytes of a `.word` or `dw` value are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Endianness {
+    /// Most significant byte first, the same as the layout of an instruction
+    #[default]
+    Big,
+    Little,
 }
 
-pub(crate) struct Generator {
+#[derive(Debug, Clone, Default)]
+pub struct GeneratorOptions {
+    /// Require every label to be defined before it is referred to, such as for teaching
+    pub forbid_forward_references: bool,
+    pub endianness: Endianness,
+    /// Place the program in these slots rather than from slot 0, padding the output up to the start
+    pub slot_range: Option<SlotRange>,
+}
+
+/// Ties an emitted instruction back to the source it was generated from
+#[derive(Debug, Clone, Copy)]
+pub struct InstructionRecord {
+    /// Byte offset of the instruction in the output
+    pub address: usize,
+    pub opcode: Opcode,
+    pub span: Span,
+    /// The comment at the end of the instruction's line, including the `;`
+    pub comment: Option<Span>,
+}
+
+/// Ties the bytes emitted for a data directive back to the directive
+#[derive(Debug, Clone, Copy)]
+pub struct DataRecord {
+    /// Byte offset of the first value in the output
+    pub address: usize,
+    /// Number of bytes emitted, including the padding byte after an odd number of them
+    pub len: usize,
+    pub span: Span,
+}
+
+/// An instruction with every operand given as the value the processor sees, for uses such as simulating a program
+/// without decoding its bytes
+#[derive(Debug, Clone)]
+pub struct ResolvedInstruction {
+    /// Byte offset of the instruction in the output
+    pub address: usize,
+    pub opcode: Opcode,
+    /// The operands in the order they were written
+    pub operands: Vec<ResolvedOperand>,
+    pub span: Span,
+}
+
+/// An operand of a [`ResolvedInstruction`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolvedOperand {
+    Register(Register),
+    /// An immediate, port or jump destination, with labels replaced by the instruction index they refer to and ports
+    /// by their number
+    Integer(i8),
+}
+
+pub struct Generator {
     items: Vec<Item>,
     label_manager: LabelManager,
+    records: Vec<InstructionRecord>,
+    data_records: Vec<DataRecord>,
+    /// Kept apart from `data_records` since the checksum isn't known until the end, so it isn't checked like data
+    checksum_records: Vec<DataRecord>,
+    /// What the last call to `generate` returned, or nothing if it failed
+    output: Vec<u8>,
+    /// Whether the last call to `generate` succeeded, so that every label it refers to has a value
+    generated_ok: bool,
+    options: GeneratorOptions,
 }
 
 impl Generator {
-    pub fn new(items: Vec<Item>, label_manager: LabelManager) -> Self {
+    pub fn new(items: Vec<Item>, label_manager: LabelManager, options: GeneratorOptions) -> Self {
         Self {
             items,
             label_manager,
+            records: Vec::new(),
+            data_records: Vec::new(),
+            checksum_records: Vec::new(),
+            output: Vec::new(),
+            generated_ok: false,
+            options,
         }
     }
 
+    /// One record per instruction emitted by the last call to `generate`, in output order
+    pub fn records(&self) -> &[InstructionRecord] {
+        &self.records
+    }
+
+    /// One record per data directive, including `.space`, emitted by the last call to `generate`, in output order
+    pub fn data_records(&self) -> &[DataRecord] {
+        &self.data_records
+    }
+
+    /// One record per `.checksum_here`, emitted by the last call to `generate`, in output order
+    pub fn checksum_records(&self) -> &[DataRecord] {
+        &self.checksum_records
+    }
+
+    /// The span of the instruction or directive that the byte at `byte_offset` in the output of the last call to
+    /// `generate` was emitted for
+    ///
+    /// Bytes of data, including the padding byte after an odd number of them, give the span of their directive. None
+    /// for padding that isn't part of anything, such as before the start of the slot range or after the program.
+    pub fn source_at(&self, byte_offset: usize) -> Option<Span> {
+        self.emitted_ranges()
+            .find(|(range, _)| range.contains(&byte_offset))
+            .map(|(_, span)| span)
+    }
+
+    /// The byte offset in the output of the last call to `generate` of the instruction or directive whose span
+    /// contains the byte at `source_index` in the source, the inverse of [`Generator::source_at`]
+    ///
+    /// None if nothing was emitted for that part of the source, such as a label, comment or blank line
+    pub fn address_at(&self, source_index: u32) -> Option<usize> {
+        self.emitted_ranges()
+            .find(|(_, span)| span.contains(source_index))
+            .map(|(range, _)| range.start)
+    }
+
+    /// The output bytes of each instruction and directive emitted by the last call to `generate`, and its span
+    fn emitted_ranges(&self) -> impl Iterator<Item = (Range<usize>, Span)> + '_ {
+        let instructions = self
+            .records
+            .iter()
+            .map(|record| (record.address..record.address + INSTRUCTION_SIZE_BYTES as usize, record.span));
+        let data = self
+            .data_records
+            .iter()
+            .chain(self.checksum_records.iter())
+            .map(|record| (record.address..record.address + record.len, record.span));
+
+        instructions.chain(data)
+    }
+
+    /// The two bytes of each instruction emitted by the last call to `generate`, in output order
+    ///
+    /// Data isn't included, so every word is a whole instruction. Empty if that call failed.
+    pub fn instruction_words(&self) -> Vec<[u8; 2]> {
+        self.records
+            .iter()
+            .filter_map(|record| self.output.get(record.address..record.address + 2))
+            .map(|word| [word[0], word[1]])
+            .collect()
+    }
+
+    /// Every instruction emitted by the last call to `generate`, in output order, with labels resolved
+    ///
+    /// Nothing is returned before `generate` has been called, or if the last call failed
+    pub fn instructions(&self) -> impl Iterator<Item = ResolvedInstruction> + '_ {
+        let instructions = self.items.iter().filter_map(|item| match item {
+            Item::Instruction(instruction, ..) => Some(instruction),
+            _ => None,
+        });
+        // A failed call can leave records for instructions whose labels were never given a value
+        let records: &[InstructionRecord] = if self.generated_ok { &self.records } else { &[] };
+
+        records
+            .iter()
+            .zip(instructions)
+            .map(|(record, instruction)| ResolvedInstruction {
+                address: record.address,
+                opcode: record.opcode,
+                operands: instruction
+                    .operands()
+                    .into_iter()
+                    .map(|operand| match operand {
+                        Operand::Register { value, .. } => ResolvedOperand::Register(value),
+                        Operand::Integer { value, .. } => ResolvedOperand::Integer(value),
+                        Operand::Label { value, .. } => ResolvedOperand::Integer(
+                            self.label_manager
+                                .get_value_of(value)
+                                .expect("Internal Assembler Error: Label left undefined by a successful generate"),
+                        ),
+                    })
+                    .collect(),
+                span: record.span,
+            })
+    }
+
+    pub fn label_manager(&self) -> &LabelManager {
+        &self.label_manager
+    }
+
+    /// Every label, instruction and piece of data in the program, in source order
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
     pub fn generate(&mut self) -> Result<Vec<u8>, GeneratorError> {
-        let mut output = Vec::new();
+        let start = self.options.slot_range.map_or(0, |range| range.start);
+        // Labels are given the slots the program is placed in, so the output has to actually put it there
+        let mut output = vec![0; start * INSTRUCTION_SIZE_BYTES as usize];
+        let mut checksum_addresses = Vec::new();
 
-        let mut instr_counter = 0;
-        let mut ended_on_label = None;
+        self.records.clear();
+        self.data_records.clear();
+        self.checksum_records.clear();
+        self.output.clear();
+        self.generated_ok = false;
+
+        self.assign_addresses()?;
 
         for item in self.items.iter() {
-            match *item {
-                Item::Label(label_id) => {
-                    ended_on_label = Some(label_id);
-                    self.label_manager
-                        .set_value_of(label_id, instr_counter as i8)
-                        .unwrap();
+            match item {
+                Item::Label(_) => {}
+                Item::Assert(assertion, span) => {
+                    let value = assertion
+                        .condition
+                        .evaluate(&mut |label_id, span| self.resolve_label(label_id, span).map(i64::from))?;
+
+                    if value == 0 {
+                        return Err(GeneratorError::AssertionFailed(*span, assertion.message.clone()));
+                    }
                 }
-                Item::Instruction(_) => {
-                    ended_on_label = None;
-                    instr_counter += 1;
+                Item::Data(data, span) => {
+                    let address = output.len();
+
+                    for value in data.values.iter() {
+                        let value = self.resolve_data_value(value, data.width.integer_role())?;
+
+                        match (data.width, self.options.endianness) {
+                            (DataWidth::Byte, _) => output.push(value as u8),
+                            (DataWidth::Word, Endianness::Big) => {
+                                output.extend_from_slice(&value.to_be_bytes())
+                            }
+                            (DataWidth::Word, Endianness::Little) => {
+                                output.extend_from_slice(&value.to_le_bytes())
+                            }
+                        }
+                    }
 
-                    if instr_counter > MAX_NUM_INSTRUCTIONS {
-                        return Err(GeneratorError::MaximumInstructionsError);
+                    // Keeps the instructions that follow aligned to a slot
+                    if data.size_bytes() % 2 != 0 {
+                        output.push(0);
                     }
+
+                    self.data_records.push(DataRecord {
+                        address,
+                        len: output.len() - address,
+                        span: *span,
+                    });
                 }
-            }
-        }
+                Item::Checksum(span) => {
+                    checksum_addresses.push(output.len());
+                    self.checksum_records.push(DataRecord {
+                        address: output.len(),
+                        len: INSTRUCTION_SIZE_BYTES as usize,
+                        span: *span,
+                    });
 
-        // If we ended on a label, this will be Some()
-        if let Some(label_id) = ended_on_label {
-            let span = self.label_manager.get_span_of(label_id).unwrap();
-            return Err(GeneratorError::DanglingLabelError(span));
-        }
+                    // A placeholder until everything before it is known, and then padding to fill the slot
+                    output.push(0);
+                    output.push(0);
+                }
+                Item::Instruction(instruction, instruction_span, comment) => {
+                    self.records.push(InstructionRecord {
+                        address: output.len(),
+                        opcode: instruction.opcode(),
+                        span: *instruction_span,
+                        comment: *comment,
+                    });
 
-        for item in self.items.iter() {
-            match item {
-                Item::Label(_) => {}
-                Item::Instruction(instruction) => {
                     match instruction {
                         Instruction::NoOperand(opcode) => {
                             if *opcode != Opcode::Nop {
@@ -86,11 +368,10 @@ impl Generator {
                             } else if *opcode == Opcode::J {
                                 match *operand {
                                     Operand::Integer { value, span: _ } => {
-                                        // R0 here is arbitrary, the value is never looked at
                                         Self::generate_immediate(
                                             &mut output,
                                             *opcode,
-                                            Register::R0,
+                                            UNUSED_REGISTER,
                                             value,
                                         );
                                     }
@@ -98,19 +379,14 @@ impl Generator {
                                         value: label_id,
                                         span,
                                     } => {
-                                        if let Some(value) =
-                                            self.label_manager.get_value_of(label_id)
-                                        {
-                                            // R0 here is arbitrary, the value is never looked at
-                                            Self::generate_immediate(
-                                                &mut output,
-                                                *opcode,
-                                                Register::R0,
-                                                value,
-                                            );
-                                        } else {
-                                            return Err(GeneratorError::UndefinedLabelError(span));
-                                        }
+                                        let value = self.resolve_label(label_id, span)?;
+
+                                        Self::generate_immediate(
+                                            &mut output,
+                                            *opcode,
+                                            UNUSED_REGISTER,
+                                            value,
+                                        );
                                     }
                                     _ => {
                                         panic!("Internal Assembler Error");
@@ -162,20 +438,14 @@ impl Generator {
                                             value: label_id,
                                             span,
                                         } => {
-                                            if let Some(value) =
-                                                self.label_manager.get_value_of(label_id)
-                                            {
-                                                Self::generate_immediate(
-                                                    &mut output,
-                                                    *opcode,
-                                                    register,
-                                                    value,
-                                                );
-                                            } else {
-                                                return Err(GeneratorError::UndefinedLabelError(
-                                                    span,
-                                                ));
-                                            }
+                                            let value = self.resolve_label(label_id, span)?;
+
+                                            Self::generate_immediate(
+                                                &mut output,
+                                                *opcode,
+                                                register,
+                                                value,
+                                            );
                                         }
                                         Operand::Integer { value, span } => {
                                             if value < MAX_NUM_INSTRUCTIONS {
@@ -246,14 +516,122 @@ impl Generator {
                                 panic!("Internal Assembler Error");
                             }
                         },
+                        Instruction::MultiOperand(_, _) => {
+                            return Err(GeneratorError::TooManyOperands(*instruction_span));
+                        }
                     }
                 }
             }
         }
 
+        // In order, so that a checksum includes the final value of any checksum before it
+        for address in checksum_addresses {
+            output[address] = output[..address]
+                .iter()
+                .fold(0, |sum: u8, byte| sum.wrapping_add(*byte));
+        }
+
+        self.output = output.clone();
+        self.generated_ok = true;
+
         Ok(output)
     }
 
+    /// Assigns every label the index of the instruction it points to, without generating any code
+    ///
+    /// The index is of the 2-byte slot, which is what a jump encodes and half of the label's byte address in the
+    /// output. Data and `.space` are padded to whole slots, so this holds for labels after them too, and a slot range
+    /// moves every label by the slots skipped before it.
+    ///
+    /// Returns the number of instruction slots used by the program, counting data padded to whole slots, but not the
+    /// slots skipped before the start of the slot range
+    pub fn assign_addresses(&mut self) -> Result<usize, GeneratorError> {
+        let start = self.options.slot_range.map_or(0, |range| range.start);
+        let mut instr_counter = start;
+        let mut ended_on_label = None;
+
+        for item in self.items.iter() {
+            let (num_slots, span, is_instruction) = match item {
+                Item::Label(label_id) => {
+                    ended_on_label = Some(*label_id);
+                    // Can't overflow, since the counter was checked against the maximum below
+                    self.label_manager
+                        .set_value_of(*label_id, instr_counter as i8)
+                        .unwrap();
+                    continue;
+                }
+                Item::Instruction(_, span, _) => (1, *span, true),
+                Item::Data(data, span) => (data.num_slots(), *span, false),
+                Item::Checksum(span) => (1, *span, false),
+                // Takes up no space, so a label in front of it refers to whatever comes next
+                Item::Assert(..) => continue,
+            };
+
+            ended_on_label = None;
+            instr_counter += num_slots;
+
+            if let Some(range) = self.options.slot_range {
+                if instr_counter > range.end {
+                    return Err(GeneratorError::SlotRangeError(span, range));
+                }
+            }
+
+            if instr_counter > MAX_NUM_INSTRUCTIONS as usize {
+                // Data is measured in bytes, so a long run of it is reported by how far past the end of memory it goes
+                return Err(if is_instruction {
+                    GeneratorError::MaximumInstructionsError(span)
+                } else {
+                    GeneratorError::MemorySizeError(span, instr_counter * INSTRUCTION_SIZE_BYTES as usize)
+                });
+            }
+        }
+
+        // If we ended on a label, this will be Some()
+        if let Some(label_id) = ended_on_label {
+            let span = self.label_manager.get_span_of(label_id).unwrap();
+            return Err(GeneratorError::DanglingLabelError(span));
+        }
+
+        Ok(instr_counter - start)
+    }
+
+    /// The bit pattern of a data value, working it out first if it refers to labels
+    fn resolve_data_value(&self, value: &DataValue, role: IntegerRole) -> Result<u16, GeneratorError> {
+        let expression = match value {
+            DataValue::Known(value) => return Ok(*value),
+            DataValue::Deferred(expression) => expression,
+        };
+
+        let value =
+            expression.evaluate(&mut |label_id, span| self.resolve_label(label_id, span).map(i64::from))?;
+
+        if role.range().contains(&value) {
+            // Negative values are stored as their two's complement bit pattern
+            Ok(value as u16)
+        } else {
+            Err(GeneratorError::DataOutOfRange(expression.span(), value, role))
+        }
+    }
+
+    /// Looks up the value of a label referred to at `span`
+    fn resolve_label(&self, label_id: LabelId, span: Span) -> Result<i8, GeneratorError> {
+        let value = self
+            .label_manager
+            .get_value_of(label_id)
+            .ok_or(GeneratorError::UndefinedLabelError(span))?;
+
+        if self.options.forbid_forward_references {
+            // Spans are byte offsets into the source, so comparing them compares source order
+            if let Some(definition_span) = self.label_manager.get_span_of(label_id) {
+                if definition_span.index > span.index {
+                    return Err(GeneratorError::ForwardReferenceError(span));
+                }
+            }
+        }
+
+        Ok(value)
+    }
+
     fn generate_immediate(buffer: &mut Vec<u8>, opcode: Opcode, register: Register, value: i8) {
         let first_byte = (opcode.encode() << 4) | (register.encode());
 
@@ -262,8 +640,8 @@ impl Generator {
     }
 
     fn generate_single_register(buffer: &mut Vec<u8>, opcode: Opcode, register: Register) {
-        // The 0 could be anything, but this is just the same as an immediate instruction, but the data field is ignored
-        Self::generate_immediate(buffer, opcode, register, 0);
+        // The same as an immediate instruction whose value is ignored
+        Self::generate_immediate(buffer, opcode, register, UNUSED_IMMEDIATE);
     }
 
     fn generate_double_register(
@@ -285,17 +663,207 @@ impl Generator {
         register: Register,
         source_or_sink: u8,
     ) -> Result<(), ()> {
-        if source_or_sink > 0b1111 {
+        if source_or_sink > MAX_SOURCE_OR_SINK {
             return Err(());
         }
 
-        Self::generate_immediate(buffer, opcode, register, (source_or_sink as i8) << 4);
+        Self::generate_immediate(
+            buffer,
+            opcode,
+            register,
+            (source_or_sink << 4) as i8,
+        );
 
         Ok(())
     }
 
     fn generate_no_operand(buffer: &mut Vec<u8>, opcode: Opcode) {
-        // The 0 could be anything, but this is just the same as an immediate instruction, but everything but the opcode is ignored
-        Self::generate_immediate(buffer, opcode, Register::R0, 0);
+        // The same as an immediate instruction where everything but the opcode is ignored
+        Self::generate_immediate(buffer, opcode, UNUSED_REGISTER, UNUSED_IMMEDIATE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, sources::SourceManager};
+
+    fn generator_of(source: &str, options: GeneratorOptions) -> Generator {
+        let tokens = Lexer::new(source).lex();
+        let source_manager = SourceManager::new(source, String::from("test.basm"));
+        let program = Parser::new(&tokens, &source_manager)
+            .parse()
+            .unwrap_or_else(|_| panic!("{source:?} should parse"));
+
+        Generator::new(program.items, program.label_manager, options)
+    }
+
+    fn generate_error(source: &str, options: GeneratorOptions) -> GeneratorError {
+        generator_of(source, options)
+            .generate()
+            .expect_err("the source should not generate")
+    }
+
+    #[test]
+    fn undefined_label_span() {
+        let error = generate_error("j nowhere\n", GeneratorOptions::default());
+
+        assert!(matches!(error, GeneratorError::UndefinedLabelError(_)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 2, len: 7 }));
+    }
+
+    #[test]
+    fn forward_reference_span() {
+        let options = GeneratorOptions {
+            forbid_forward_references: true,
+            ..Default::default()
+        };
+
+        let error = generate_error("    j end\nend:\n    j end\n", options);
+
+        assert!(matches!(error, GeneratorError::ForwardReferenceError(_)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 6, len: 3 }));
+    }
+
+    #[test]
+    fn failed_assertion_span() {
+        let error = generate_error("nop\n.assert 1 == 2\n", GeneratorOptions::default());
+
+        assert!(matches!(error, GeneratorError::AssertionFailed(..)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 4, len: 14 }));
+    }
+
+    #[test]
+    fn data_past_the_end_of_memory_span() {
+        let error = generate_error("nop\n.space 64\n", GeneratorOptions::default());
+
+        // The nop and the 64 bytes of space take up 66 bytes
+        assert!(matches!(error, GeneratorError::MemorySizeError(_, 66)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 4, len: 9 }));
+    }
+
+    #[test]
+    fn instructions_with_resolved_operands() {
+        let source = "start:\n    ldi r1, -2\n    jz r1, done\n    .byte 7, 8\n    j start\ndone:\n    out r1, 3\n";
+        let mut generator = generator_of(source, GeneratorOptions::default());
+        generator.generate().unwrap();
+
+        let instructions: Vec<ResolvedInstruction> = generator.instructions().collect();
+
+        let summary: Vec<(usize, Opcode, Vec<ResolvedOperand>)> = instructions
+            .iter()
+            .map(|instruction| (instruction.address, instruction.opcode, instruction.operands.clone()))
+            .collect();
+
+        // The data is skipped over, but still moves the instructions after it, and `done` is slot 4
+        assert_eq!(
+            summary,
+            [
+                (0, Opcode::Ldi, vec![ResolvedOperand::Register(Register::R1), ResolvedOperand::Integer(-2)]),
+                (2, Opcode::Jz, vec![ResolvedOperand::Register(Register::R1), ResolvedOperand::Integer(4)]),
+                (6, Opcode::J, vec![ResolvedOperand::Integer(0)]),
+                (8, Opcode::Out, vec![ResolvedOperand::Register(Register::R1), ResolvedOperand::Integer(3)]),
+            ]
+        );
+        assert_eq!(instructions[2].span.index as usize, source.find("j start").unwrap());
+        assert_eq!(instructions[2].span.len, 7);
+    }
+
+    #[test]
+    fn source_at_each_output_byte() {
+        let source = "start:\n    ldi r0, 1\nloop:\n    add r0, r0\n    .byte 1, 2, 3\n    j loop\n";
+        let options = GeneratorOptions {
+            slot_range: Some(SlotRange { start: 1, end: 16 }),
+            ..Default::default()
+        };
+        let mut generator = generator_of(source, options);
+        generator.generate().unwrap();
+
+        let text_at = |byte_offset| generator.source_at(byte_offset).map(|span: Span| {
+            &source[span.index as usize..(span.index + span.len) as usize]
+        });
+
+        // Padding before the start of the slot range
+        assert_eq!(text_at(0), None);
+        assert_eq!(text_at(1), None);
+        // Both bytes of the first instruction, at address 2
+        assert_eq!(text_at(2), Some("ldi r0, 1"));
+        assert_eq!(text_at(3), Some("ldi r0, 1"));
+        assert_eq!(text_at(4), Some("add r0, r0"));
+        // Data, including the padding byte after it
+        for byte_offset in 6..10 {
+            assert_eq!(text_at(byte_offset), Some(".byte 1, 2, 3"), "{byte_offset}");
+        }
+        assert_eq!(text_at(10), Some("j loop"));
+        // Past the end of the program
+        assert_eq!(text_at(12), None);
+    }
+
+    #[test]
+    fn address_at_each_source_index() {
+        let source = "start:\n    ldi r0, 1\nloop:\n    add r0, r0\n    .byte 1, 2, 3\n    j loop\n";
+        let mut generator = generator_of(source, GeneratorOptions::default());
+        generator.generate().unwrap();
+
+        let address_of = |text: &str| generator.address_at(source.find(text).unwrap() as u32);
+
+        assert_eq!(address_of("ldi"), Some(0));
+        // Anywhere in an instruction, not only its start
+        assert_eq!(address_of("r0, r0"), Some(2));
+        assert_eq!(address_of("3\n"), Some(4));
+        assert_eq!(address_of("j loop"), Some(8));
+        // Nothing is generated for a label
+        assert_eq!(address_of("loop:"), None);
+
+        // Going there and back again ends up at the same instruction
+        for address in [0, 2, 4, 8] {
+            let span = generator.source_at(address).unwrap();
+            assert_eq!(generator.address_at(span.index), Some(address));
+        }
+    }
+
+    #[test]
+    fn instruction_words_skip_data() {
+        let source = "    ldi r1, -2\n    .byte 7, 8, 9\n    out r1, 3\n";
+        let options = GeneratorOptions {
+            slot_range: Some(SlotRange { start: 2, end: 8 }),
+            ..Default::default()
+        };
+        let mut generator = generator_of(source, options);
+        generator.generate().unwrap();
+
+        // Placed from slot 2, and only the instructions either side of the data
+        assert_eq!(generator.instruction_words(), [[0x21, 0xfe], [0xc1, 0x30]]);
+    }
+
+    #[test]
+    fn no_instruction_words_after_a_failed_generate() {
+        let mut generator = generator_of("    nop\n    j nowhere\n", GeneratorOptions::default());
+
+        assert!(generator.generate().is_err());
+        assert!(generator.instruction_words().is_empty());
+    }
+
+    #[test]
+    fn no_instructions_after_a_failed_generate() {
+        // The `ldi` is recorded before generating stops at the undefined label, which never gets a value
+        let mut generator = generator_of("    ldi r0, 1\n    j nowhere\n", GeneratorOptions::default());
+
+        assert!(generator.generate().is_err());
+        assert_eq!(generator.instructions().count(), 0);
+    }
+
+    #[test]
+    fn instructions_after_generating_again() {
+        let mut generator = generator_of("start:\n    j start\n", GeneratorOptions::default());
+
+        assert_eq!(generator.instructions().count(), 0);
+
+        generator.generate().unwrap();
+        generator.generate().unwrap();
+        let destinations: Vec<Vec<ResolvedOperand>> =
+            generator.instructions().map(|instruction| instruction.operands).collect();
+
+        assert_eq!(destinations, [vec![ResolvedOperand::Integer(0)]]);
     }
 }
@@ -1,8 +1,17 @@
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use crate::{
+    ast::Opcode,
+    generator::{INSTRUCTION_MEMORY_SIZE_BYTES, MAX_NUM_INSTRUCTIONS, MAX_SOURCE_OR_SINK},
+};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OperandType {
     Register,
     Integer,
     Label,
+    /// A source or sink, given either as an integer or as a name defined with `.port`
+    Port,
 }
 
 impl OperandType {
@@ -11,12 +20,131 @@ impl OperandType {
             Self::Register => "register",
             Self::Integer => "integer",
             Self::Label => "label",
+            Self::Port => "port",
+        }
+    }
+
+    /// The name of the operand type with an article, for use in messages
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Register => "a register",
+            Self::Integer => "an integer",
+            Self::Label => "a label",
+            Self::Port => "a port",
+        }
+    }
+}
+
+/// What an integer operand is used for, which decides the range of values it may take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerRole {
+    /// An 8-bit signed value stored in the instruction
+    Immediate,
+    /// A source or sink for I/O
+    Port,
+    /// The index of the instruction to jump to
+    JumpDestination,
+    /// A value of a `.byte` or `db` directive, which may be given as signed or unsigned
+    Byte,
+    /// A value of a `.word` or `dw` directive, which may be given as signed or unsigned
+    Word,
+    /// The value of a `.set` constant, which is checked again for wherever it is used
+    Constant,
+    /// The number of bytes reserved by a `.space` directive
+    Size,
+}
+
+impl IntegerRole {
+    pub fn range(self) -> RangeInclusive<i64> {
+        match self {
+            Self::Immediate => i8::MIN as i64..=i8::MAX as i64,
+            Self::Port => 0..=MAX_SOURCE_OR_SINK as i64,
+            Self::JumpDestination => 0..=(MAX_NUM_INSTRUCTIONS - 1) as i64,
+            Self::Byte => i8::MIN as i64..=u8::MAX as i64,
+            Self::Word | Self::Constant => i16::MIN as i64..=u16::MAX as i64,
+            Self::Size => 1..=INSTRUCTION_MEMORY_SIZE_BYTES as i64,
+        }
+    }
+}
+
+/// How an instruction uses one of its operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandAccess {
+    /// A register whose value is used, but left unchanged
+    Read,
+    /// A register that is overwritten without its old value being used
+    Write,
+    /// A register whose value is used and then replaced with the result
+    ReadWrite,
+    /// A value encoded in the instruction itself, such as an immediate, port or jump destination
+    Constant,
+}
+
+impl OperandAccess {
+    pub fn reads(self) -> bool {
+        matches!(self, Self::Read | Self::ReadWrite)
+    }
+
+    pub fn writes(self) -> bool {
+        matches!(self, Self::Write | Self::ReadWrite)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodingForm {
+    /// Opcode and register in the first byte, an 8-bit value in the second
+    Immediate,
+    /// Opcode and first register in the first byte, second register in the high nibble of the second
+    DoubleRegister,
+    /// Like an immediate, but the value is ignored
+    SingleRegister,
+    /// Opcode and register in the first byte, source or sink in the high nibble of the second
+    Io,
+    /// Only the opcode is significant
+    NoOperand,
+}
+
+impl EncodingForm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Immediate => "immediate",
+            Self::DoubleRegister => "double-register",
+            Self::SingleRegister => "single-register",
+            Self::Io => "io",
+            Self::NoOperand => "no-operand",
         }
     }
 }
 
+/// The estimated number of cycles each opcode takes to execute
+///
+/// Every instruction takes 1 cycle unless it has been overridden
+#[derive(Debug, Clone, Default)]
+pub struct CycleTable {
+    overrides: HashMap<Opcode, u32>,
+}
+
+impl CycleTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_cost(&mut self, opcode: Opcode, cycles: u32) {
+        self.overrides.insert(opcode, cycles);
+    }
+
+    pub fn cost_of(&self, opcode: Opcode) -> u32 {
+        self.overrides.get(&opcode).copied().unwrap_or(1)
+    }
+}
+
 pub mod rules {
+    use std::collections::HashMap;
+
     use super::OperandType;
+    use crate::ast::Opcode;
+
+    pub type OperandRules = &'static [&'static [OperandType]];
 
     pub static NOP_RULES: &[&[OperandType]] = &[];
     pub static ADD_RULES: &[&[OperandType]] = &[&[OperandType::Register], &[OperandType::Register]];
@@ -28,8 +156,14 @@ pub mod rules {
     pub static XOR_RULES: &[&[OperandType]] = &[&[OperandType::Register], &[OperandType::Register]];
     pub static SR_RULES: &[&[OperandType]] = &[&[OperandType::Register], &[OperandType::Register]];
     pub static SL_RULES: &[&[OperandType]] = &[&[OperandType::Register], &[OperandType::Register]];
-    pub static IN_RULES: &[&[OperandType]] = &[&[OperandType::Register], &[OperandType::Integer]];
-    pub static OUT_RULES: &[&[OperandType]] = &[&[OperandType::Register], &[OperandType::Integer]];
+    pub static IN_RULES: &[&[OperandType]] = &[
+        &[OperandType::Register],
+        &[OperandType::Integer, OperandType::Port],
+    ];
+    pub static OUT_RULES: &[&[OperandType]] = &[
+        &[OperandType::Register],
+        &[OperandType::Integer, OperandType::Port],
+    ];
     pub static JZ_RULES: &[&[OperandType]] = &[
         &[OperandType::Register],
         &[OperandType::Integer, OperandType::Label],
@@ -39,4 +173,27 @@ pub mod rules {
         &[OperandType::Integer, OperandType::Label],
     ];
     pub static J_RULES: &[&[OperandType]] = &[&[OperandType::Integer, OperandType::Label]];
+
+    /// Builds the table of operand rules used by the parser, keyed by opcode
+    pub fn parse_rules() -> HashMap<Opcode, OperandRules> {
+        let mut parse_rules = HashMap::new();
+
+        parse_rules.insert(Opcode::Nop, NOP_RULES);
+        parse_rules.insert(Opcode::Add, ADD_RULES);
+        parse_rules.insert(Opcode::Ldi, LDI_RULES);
+        parse_rules.insert(Opcode::Sub, SUB_RULES);
+        parse_rules.insert(Opcode::And, AND_RULES);
+        parse_rules.insert(Opcode::Or, OR_RULES);
+        parse_rules.insert(Opcode::Inv, INV_RULES);
+        parse_rules.insert(Opcode::Xor, XOR_RULES);
+        parse_rules.insert(Opcode::Sr, SR_RULES);
+        parse_rules.insert(Opcode::Sl, SL_RULES);
+        parse_rules.insert(Opcode::In, IN_RULES);
+        parse_rules.insert(Opcode::Out, OUT_RULES);
+        parse_rules.insert(Opcode::Jz, JZ_RULES);
+        parse_rules.insert(Opcode::Jlt, JLT_RULES);
+        parse_rules.insert(Opcode::J, J_RULES);
+
+        parse_rules
+    }
 }
@@ -1,41 +1,186 @@
+use crate::sources::{Position, SourceManager};
+
 #[derive(Debug, Clone, Copy)]
 pub struct Token {
     pub tt: TokenType,
     pub span: Span,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Span {
     pub index: u32,
     pub len: u32,
 }
 
+impl Span {
+    /// Creates the smallest span covering both this span and `other`
+    pub fn merge(self, other: Span) -> Span {
+        let start = self.index.min(other.index);
+        let end = (self.index + self.len).max(other.index + other.len);
+
+        Span {
+            index: start,
+            len: end - start,
+        }
+    }
+
+    /// Whether the byte at `index` in the source is part of this span
+    pub fn contains(self, index: u32) -> bool {
+        (self.index..self.index + self.len).contains(&index)
+    }
+
+    /// A zero-length span just after the end of this one, for pointing at where something is missing
+    ///
+    /// Diagnostics show it as a single caret
+    pub fn point_at_end(self) -> Span {
+        Span {
+            index: self.index + self.len,
+            len: 0,
+        }
+    }
+}
+
+/// A span along with the macro invocation it was expanded from, if any
+///
+/// Only one level of expansion is tracked
+#[derive(Debug, Clone, Copy)]
+pub struct SpanContext {
+    pub span: Span,
+    pub expanded_from: Option<Span>,
+}
+
+impl SpanContext {
+    /// A span written directly in the source, not produced by an expansion
+    pub fn direct(span: Span) -> Self {
+        Self {
+            span,
+            expanded_from: None,
+        }
+    }
+
+    pub fn expanded(span: Span, invocation: Span) -> Self {
+        Self {
+            span,
+            expanded_from: Some(invocation),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenType {
     Identifier,
     Label,
+    Directive,
     Comma,
+    /// A `+`, which the parser decides the meaning of
+    Plus,
+    /// A `-`, which the parser decides the meaning of, such as the sign of a negative integer
+    Minus,
+    /// `|`
+    Pipe,
+    /// `&`
+    Ampersand,
+    /// `*`
+    Star,
+    LeftParen,
+    RightParen,
+    /// `==`
+    EqualEqual,
+    /// `!=`
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
     Integer,
     Newline,
     Comment,
+    /// A register written with a `$` in front, such as `$r0`, which is only lexed when a register sigil is asked for
+    Register,
+    /// Text between double quotes on a single line, where a `\` makes the character after it part of the text
+    String,
 
     InvalidTokenError,
     InvalidIntegerError,
+    /// Something like `1loop:`, which would be a label if it didn't start with a digit
+    InvalidLabelError,
+    /// An integer directly followed by a `:`, such as `5:`, since labels can't be numbers
+    NumericLabelError,
+    /// A `"` without a closing one before the end of its line
+    UnterminatedStringError,
+    /// Something like `my-label:`, which would be a label if identifiers could contain `-`
+    DashedLabelError,
+}
+
+/// The length in bytes of the longest source that can be lexed, since spans store byte offsets as `u32`
+pub const MAX_SOURCE_LEN: usize = u32::MAX as usize;
+
+/// Whether `c` can be given to [`Lexer::set_comment_chars`], which it can't if it is whitespace or already starts a
+/// token, such as a letter, digit, `,` or `.`
+///
+/// `$` is never allowed, even though it only starts a register with a sigil
+pub fn is_valid_comment_char(c: char) -> bool {
+    !(c.is_whitespace()
+        || c.is_alphanumeric()
+        || c.is_control()
+        || matches!(
+            c,
+            '_' | ',' | '+' | '-' | '|' | '&' | '*' | '(' | ')' | '=' | '!' | '<' | '>' | '"' | '$' | '.' | ':'
+        ))
 }
 
 pub struct Lexer<'a> {
     input: &'a str,
     current_index: usize,
+    /// Whether `$` starts a register
+    register_sigil: bool,
+    /// Whether a `-` can be part of an identifier after its first character
+    dash_identifiers: bool,
+    /// Characters that start a comment besides `;`
+    comment_chars: Vec<char>,
 }
 
 impl<'a> Lexer<'a> {
+    /// Panics if the source is longer than [`MAX_SOURCE_LEN`], rather than giving tokens with wrapped spans
     pub fn new(source: &'a str) -> Self {
+        assert!(
+            source.len() <= MAX_SOURCE_LEN,
+            "Source of {} bytes is too large to lex",
+            source.len()
+        );
+
         Self {
             input: source,
             current_index: 0,
+            register_sigil: false,
+            dash_identifiers: false,
+            comment_chars: Vec::new(),
         }
     }
 
+    /// Lexes a `$` followed by a name as a [`TokenType::Register`], rather than as an invalid token
+    pub fn set_register_sigil(&mut self, register_sigil: bool) {
+        self.register_sigil = register_sigil;
+    }
+
+    /// Lexes a `-` directly after a letter, digit, `_` or `-` of an identifier as part of it, so that `loop-1` is one
+    /// identifier rather than `loop`, `-` and `1`, as older versions did
+    pub fn set_dash_identifiers(&mut self, dash_identifiers: bool) {
+        self.dash_identifiers = dash_identifiers;
+    }
+
+    /// Lexes each of `comment_chars`, as well as `;`, as the start of a comment running to the end of the line
+    ///
+    /// Panics if one of them isn't allowed by [`is_valid_comment_char`]
+    pub fn set_comment_chars(&mut self, comment_chars: &[char]) {
+        if let Some(c) = comment_chars.iter().find(|&&c| !is_valid_comment_char(c)) {
+            panic!("`{c}` can't start a comment, since it already starts something else");
+        }
+
+        self.comment_chars = comment_chars.to_vec();
+    }
+
+    /// Splits the source into tokens. Spans are byte offsets into the source
     pub fn lex(&mut self) -> Vec<Token> {
         let mut tokens = Vec::new();
 
@@ -47,12 +192,27 @@ impl<'a> Lexer<'a> {
                     continue;
                 }
                 '\n' => self.consume_current_single_char_token(TokenType::Newline),
+                c if self.comment_chars.contains(&c) => self.lex_comment(),
                 ',' => self.consume_current_single_char_token(TokenType::Comma),
+                '+' => self.consume_current_single_char_token(TokenType::Plus),
+                '-' => self.consume_current_single_char_token(TokenType::Minus),
+                '|' => self.consume_current_single_char_token(TokenType::Pipe),
+                '&' => self.consume_current_single_char_token(TokenType::Ampersand),
+                '*' => self.consume_current_single_char_token(TokenType::Star),
+                '(' => self.consume_current_single_char_token(TokenType::LeftParen),
+                ')' => self.consume_current_single_char_token(TokenType::RightParen),
+                '=' => self.lex_comparison(TokenType::InvalidTokenError, TokenType::EqualEqual),
+                '!' => self.lex_comparison(TokenType::InvalidTokenError, TokenType::BangEqual),
+                '<' => self.lex_comparison(TokenType::Less, TokenType::LessEqual),
+                '>' => self.lex_comparison(TokenType::Greater, TokenType::GreaterEqual),
                 ';' => self.lex_comment(),
+                '"' => self.lex_string(),
+                '$' if self.register_sigil => self.lex_register(),
+                '.' => self.lex_directive(),
                 _ => {
-                    if c.is_digit(10) {
+                    if c.is_ascii_digit() {
                         self.lex_integer()
-                    } else if c.is_alphabetic() {
+                    } else if c.is_alphabetic() || c == '_' {
                         self.lex_identifier()
                     } else {
                         self.consume_current_single_char_token(TokenType::InvalidTokenError)
@@ -66,83 +226,165 @@ impl<'a> Lexer<'a> {
         tokens
     }
 
+    /// Lexes the source like [`Lexer::lex`], pairing each token with where it starts, such as for highlighting the source
+    /// without setting up a [`SourceManager`]
+    ///
+    /// Positions are worked out the same way as for diagnostics
+    pub fn tokenize_with_positions(&mut self) -> Vec<(Token, Position)> {
+        let source_manager = SourceManager::new(self.input, String::new());
+
+        self.lex()
+            .into_iter()
+            .map(|token| {
+                let position = source_manager
+                    .get_span_position(token.span)
+                    .expect("Internal Assembler Error: Every token is on a line");
+
+                (token, position)
+            })
+            .collect()
+    }
+
     fn lex_comment(&mut self) -> Token {
         let start_index = self.current_index;
-        let mut len = 1;
 
-        self.current_index += 1;
+        self.next_char();
 
         while let Some(c) = self.peek_char() {
-            if c == '\n' {
+            // The carriage return of a CRLF line ending is not part of the comment
+            if c == '\n' || (c == '\r' && self.peek_nth_char(1) == Some('\n')) {
                 break;
             }
 
-            len += 1;
-            self.current_index += 1;
+            self.next_char();
         }
 
-        Token {
-            tt: TokenType::Comment,
-            span: Span {
-                index: start_index as u32,
-                len,
-            },
+        self.token_from(TokenType::Comment, start_index)
+    }
+
+    fn lex_string(&mut self) -> Token {
+        let start_index = self.current_index;
+
+        self.next_char();
+
+        loop {
+            match self.peek_char() {
+                Some('"') => {
+                    self.next_char();
+                    return self.token_from(TokenType::String, start_index);
+                }
+                Some('\\') => {
+                    self.next_char();
+
+                    // An escaped newline would still end the line, so it isn't skipped over
+                    if self.peek_char() != Some('\n') {
+                        self.next_char();
+                    }
+                }
+                // The carriage return of a CRLF line ending is not part of the string
+                Some('\r') if self.peek_nth_char(1) == Some('\n') => break,
+                Some('\n') | None => break,
+                Some(_) => {
+                    self.next_char();
+                }
+            }
+        }
+
+        self.token_from(TokenType::UnterminatedStringError, start_index)
+    }
+
+    fn lex_register(&mut self) -> Token {
+        let start_index = self.current_index;
+
+        self.next_char();
+
+        if !self.peek_char().is_some_and(|c| c.is_alphabetic()) {
+            return self.token_from(TokenType::InvalidTokenError, start_index);
+        }
+
+        while let Some(c) = self.peek_char() {
+            if c.is_alphanumeric() || c == '_' {
+                self.next_char();
+            } else {
+                break;
+            }
+        }
+
+        self.token_from(TokenType::Register, start_index)
+    }
+
+    fn lex_directive(&mut self) -> Token {
+        let start_index = self.current_index;
+
+        self.next_char();
+
+        while let Some(c) = self.peek_char() {
+            if c.is_alphanumeric() || c == '_' {
+                self.next_char();
+            } else {
+                break;
+            }
         }
+
+        self.token_from(TokenType::Directive, start_index)
     }
 
     fn lex_integer(&mut self) -> Token {
         let start_index = self.current_index;
-        let mut len = 1;
-        let mut is_valid_int = true;
+        let is_hex = self.peek_char() == Some('0') && matches!(self.peek_nth_char(1), Some('x' | 'X'));
+        // A hex prefix must be followed by at least one digit
+        let mut is_valid_int = !is_hex;
 
-        self.current_index += 1;
+        self.next_char();
+
+        if is_hex {
+            self.next_char();
+        }
 
         while let Some(c) = self.peek_char() {
-            if c.is_digit(10) {
-                len += 1;
-                self.current_index += 1;
-            } else if c.is_alphabetic() {
+            if c.is_ascii_digit() || (is_hex && c.is_ascii_hexdigit()) {
+                is_valid_int |= is_hex;
+                self.next_char();
+            } else if c.is_alphabetic() || c == '_' {
                 is_valid_int = false;
-                len += 1;
-                self.current_index += 1;
+                self.next_char();
             } else {
                 break;
             }
         }
 
+        if self.peek_char() == Some(':') {
+            self.next_char();
+
+            return if is_valid_int {
+                self.token_from(TokenType::NumericLabelError, start_index)
+            } else {
+                self.token_from(TokenType::InvalidLabelError, start_index)
+            };
+        }
+
         if is_valid_int {
-            Token {
-                tt: TokenType::Integer,
-                span: Span {
-                    index: start_index as u32,
-                    len,
-                },
-            }
+            self.token_from(TokenType::Integer, start_index)
         } else {
-            Token {
-                tt: TokenType::InvalidIntegerError,
-                span: Span {
-                    index: start_index as u32,
-                    len,
-                },
-            }
+            self.token_from(TokenType::InvalidIntegerError, start_index)
         }
     }
 
     fn lex_identifier(&mut self) -> Token {
         let start_index = self.current_index;
-        let mut len = 1;
         let mut is_label = false;
 
-        self.current_index += 1;
+        self.next_char();
 
         while let Some(c) = self.peek_char() {
-            if c.is_alphanumeric() || c == '_' || c == '-' {
-                len += 1;
-                self.current_index += 1;
+            if c.is_alphanumeric() || c == '_' || (c == '-' && self.dash_identifiers) {
+                self.next_char();
+            } else if c == '-' && self.is_dashed_label_ahead() {
+                while self.next_char() != Some(':') {}
+
+                return self.token_from(TokenType::DashedLabelError, start_index);
             } else if c == ':' {
-                len += 1;
-                self.current_index += 1;
+                self.next_char();
                 is_label = true;
                 break;
             } else {
@@ -151,48 +393,288 @@ impl<'a> Lexer<'a> {
         }
 
         if is_label {
-            Token {
-                tt: TokenType::Label,
-                span: Span {
-                    index: start_index as u32,
-                    len,
-                },
-            }
+            self.token_from(TokenType::Label, start_index)
         } else {
-            Token {
-                tt: TokenType::Identifier,
-                span: Span {
-                    index: start_index as u32,
-                    len,
-                },
-            }
+            self.token_from(TokenType::Identifier, start_index)
+        }
+    }
+
+    /// Lexes the current character as `alone`, or as `with_equal` if it is directly followed by `=`
+    fn lex_comparison(&mut self, alone: TokenType, with_equal: TokenType) -> Token {
+        let start_index = self.current_index;
+
+        self.next_char();
+
+        if self.peek_char() == Some('=') {
+            self.next_char();
+            self.token_from(with_equal, start_index)
+        } else {
+            self.token_from(alone, start_index)
         }
     }
 
     fn consume_current_single_char_token(&mut self, tt: TokenType) -> Token {
-        let token = Token {
+        let start_index = self.current_index;
+
+        self.next_char();
+
+        self.token_from(tt, start_index)
+    }
+
+    /// Creates a token spanning from `start_index` up to the current index
+    fn token_from(&self, tt: TokenType, start_index: usize) -> Token {
+        Token {
             tt,
             span: Span {
-                index: self.current_index as u32,
-                len: 1,
+                index: start_index as u32,
+                len: (self.current_index - start_index) as u32,
             },
-        };
+        }
+    }
 
-        self.current_index += 1;
+    /// Whether the rest of an identifier, starting at a `-`, is only letters, digits, `_` and `-` up to a `:`, so that
+    /// it was most likely meant as a label from before `-` ended identifiers
+    fn is_dashed_label_ahead(&mut self) -> bool {
+        let rest = self.input[self.current_index..].chars().skip(1);
 
-        token
+        for c in rest {
+            if c == ':' {
+                return true;
+            } else if !(c.is_alphanumeric() || c == '_' || c == '-') {
+                return false;
+            }
+        }
+
+        false
     }
 
     fn peek_char(&mut self) -> Option<char> {
-        self.input.chars().skip(self.current_index).next()
+        self.input[self.current_index..].chars().next()
+    }
+
+    fn peek_nth_char(&mut self, n: usize) -> Option<char> {
+        self.input[self.current_index..].chars().nth(n)
     }
 
-    #[allow(dead_code)]
     fn next_char(&mut self) -> Option<char> {
-        let c = self.input.chars().skip(self.current_index).next();
+        let c = self.peek_char();
 
-        self.current_index += 1;
+        if let Some(c) = c {
+            self.current_index += c.len_utf8();
+        }
 
         c
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The type and text of every token in `source`
+    fn tokens_of(source: &str) -> Vec<(TokenType, &str)> {
+        Lexer::new(source)
+            .lex()
+            .into_iter()
+            .map(|token| {
+                let start = token.span.index as usize;
+
+                (token.tt, &source[start..start + token.span.len as usize])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn label_starting_with_a_digit() {
+        assert_eq!(
+            tokens_of("1loop: nop\n"),
+            [
+                (TokenType::InvalidLabelError, "1loop:"),
+                (TokenType::Identifier, "nop"),
+                (TokenType::Newline, "\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn point_at_end_of_span() {
+        assert_eq!(Span { index: 4, len: 3 }.point_at_end(), Span { index: 7, len: 0 });
+        assert_eq!(Span { index: 4, len: 0 }.point_at_end(), Span { index: 4, len: 0 });
+    }
+
+    #[test]
+    fn dash_ends_an_identifier_by_default() {
+        assert_eq!(
+            tokens_of("loop-1"),
+            [
+                (TokenType::Identifier, "loop"),
+                (TokenType::Minus, "-"),
+                (TokenType::Integer, "1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn dash_in_identifiers_when_allowed() {
+        let source = "j loop-1";
+        let mut lexer = Lexer::new(source);
+        lexer.set_dash_identifiers(true);
+
+        let texts: Vec<&str> = lexer
+            .lex()
+            .into_iter()
+            .map(|token| &source[token.span.index as usize..(token.span.index + token.span.len) as usize])
+            .collect();
+
+        assert_eq!(texts, ["j", "loop-1"]);
+    }
+
+    #[test]
+    fn identifier_starting_with_underscore() {
+        assert_eq!(
+            tokens_of("_start: j _start"),
+            [
+                (TokenType::Label, "_start:"),
+                (TokenType::Identifier, "j"),
+                (TokenType::Identifier, "_start"),
+            ]
+        );
+    }
+
+    #[test]
+    fn dashed_label_is_one_error() {
+        assert_eq!(
+            tokens_of("my-label: nop"),
+            [(TokenType::DashedLabelError, "my-label:"), (TokenType::Identifier, "nop")]
+        );
+    }
+
+    #[test]
+    fn numeric_label_is_one_error() {
+        assert_eq!(
+            tokens_of("5: nop"),
+            [(TokenType::NumericLabelError, "5:"), (TokenType::Identifier, "nop")]
+        );
+        assert_eq!(tokens_of("0x1f:"), [(TokenType::NumericLabelError, "0x1f:")]);
+    }
+
+    fn tokens_with_comment_chars<'a>(source: &'a str, comment_chars: &[char]) -> Vec<(TokenType, &'a str)> {
+        let mut lexer = Lexer::new(source);
+        lexer.set_comment_chars(comment_chars);
+
+        lexer
+            .lex()
+            .into_iter()
+            .map(|token| (token.tt, &source[token.span.index as usize..(token.span.index + token.span.len) as usize]))
+            .collect()
+    }
+
+    #[test]
+    fn extra_comment_char() {
+        assert_eq!(
+            tokens_with_comment_chars("nop # wait\nnop ; wait\n", &['#']),
+            [
+                (TokenType::Identifier, "nop"),
+                (TokenType::Comment, "# wait"),
+                (TokenType::Newline, "\n"),
+                (TokenType::Identifier, "nop"),
+                (TokenType::Comment, "; wait"),
+                (TokenType::Newline, "\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_byte_comment_char() {
+        // `§` takes up 2 bytes, which the comment's span includes, and the tokens after it still line up
+        assert_eq!(
+            tokens_with_comment_chars("nop §é\nj x", &['§']),
+            [
+                (TokenType::Identifier, "nop"),
+                (TokenType::Comment, "§é"),
+                (TokenType::Newline, "\n"),
+                (TokenType::Identifier, "j"),
+                (TokenType::Identifier, "x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_char_not_given_is_invalid() {
+        assert_eq!(
+            tokens_of("nop # wait"),
+            [
+                (TokenType::Identifier, "nop"),
+                (TokenType::InvalidTokenError, "#"),
+                (TokenType::Identifier, "wait"),
+            ]
+        );
+    }
+
+    #[test]
+    fn valid_comment_chars() {
+        for c in ['#', '/', '@', '§', '%'] {
+            assert!(is_valid_comment_char(c), "{c:?}");
+        }
+
+        for c in ['a', 'Z', '7', '_', ',', '.', '-', '+', '(', ':', '$', '"', ' ', '\t', '\n'] {
+            assert!(!is_valid_comment_char(c), "{c:?}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`,` can't start a comment")]
+    fn invalid_comment_char_panics() {
+        Lexer::new("").set_comment_chars(&['#', ',']);
+    }
+
+    #[test]
+    fn positions_across_lines_with_tabs() {
+        let source = "start:\n\tldi r0, 1\n\t\tj\tstart ; back\n";
+
+        let positions: Vec<(TokenType, u32, u32, u32)> = Lexer::new(source)
+            .tokenize_with_positions()
+            .into_iter()
+            .map(|(token, position)| (token.tt, position.line, position.column, position.visual_column))
+            .collect();
+
+        // Each tab is one character, but takes up 4 columns when shown
+        assert_eq!(
+            positions,
+            [
+                (TokenType::Label, 1, 0, 0),
+                (TokenType::Newline, 1, 6, 6),
+                (TokenType::Identifier, 2, 1, 4),
+                (TokenType::Identifier, 2, 5, 8),
+                (TokenType::Comma, 2, 7, 10),
+                (TokenType::Integer, 2, 9, 12),
+                (TokenType::Newline, 2, 10, 13),
+                (TokenType::Identifier, 3, 2, 8),
+                (TokenType::Identifier, 3, 4, 13),
+                (TokenType::Comment, 3, 10, 19),
+                (TokenType::Newline, 3, 16, 25),
+            ]
+        );
+    }
+
+    #[test]
+    fn positions_with_crlf_line_endings() {
+        let positions: Vec<(TokenType, u32, u32)> = Lexer::new("nop\r\nj 0\r\n")
+            .tokenize_with_positions()
+            .into_iter()
+            .map(|(token, position)| (token.tt, position.line, position.column))
+            .collect();
+
+        // The `\n` of each `\r\n` is on the line it ends, just after its last character
+        assert_eq!(
+            positions,
+            [
+                (TokenType::Identifier, 1, 0),
+                (TokenType::Newline, 1, 3),
+                (TokenType::Identifier, 2, 0),
+                (TokenType::Integer, 2, 2),
+                (TokenType::Newline, 2, 3),
+            ]
+        );
+    }
+}
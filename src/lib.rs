@@ -0,0 +1,380 @@
+//! An assembler for the BRISC assembly language
+
+// Lookups that can only fail in one way report it with `()`
+#![allow(clippy::result_unit_err)]
+
+use ast::MetaEntry;
+use errors::{
+    dedup_diagnostics, generator_error_into_diagnostic, lexer_error_into_diagnostic, parse_error_into_diagnostic,
+    parse_warning_into_diagnostic, Diagnostic, Emitter,
+};
+use generator::{DataRecord, Endianness, Generator, GeneratorOptions, InstructionRecord, SlotRange};
+use lexer::{Lexer, Span, Token, TokenType, MAX_SOURCE_LEN};
+use parser::{ConstantDefinitions, Parser, Visibility};
+use sources::SourceManager;
+
+pub mod ast;
+pub mod disassembler;
+pub mod errors;
+pub mod formats;
+pub mod generator;
+pub mod instructions;
+mod json;
+pub mod lexer;
+pub mod lints;
+pub mod parser;
+pub mod sources;
+pub mod web;
+
+/// Settings that change what the assembler accepts
+#[derive(Debug, Clone, Default)]
+pub struct AssembleOptions {
+    /// Report an error for any label referred to before it is defined, rather than resolving it
+    pub forbid_forward_references: bool,
+    /// Byte order of `.word` and `dw` values
+    pub endianness: Endianness,
+    /// Labels given a value up front, such as routines at fixed addresses outside the program
+    ///
+    /// Values are instruction indices, and must be valid jump destinations
+    pub seeded_labels: Vec<(String, i8)>,
+    /// Constants and ports defined before the source, such as with [`parse_constants`]
+    pub constants: ConstantDefinitions,
+    /// Accept immediates of 128 to 255 as the signed byte with the same bits, with a warning, rather than an error
+    pub relax: bool,
+    /// Only accept registers written with a `$`, such as `$r0`, so that `r0` on its own is a label
+    pub strict_registers: bool,
+    /// Let identifiers contain `-`, so that `loop-1` is a name rather than `loop` minus 1
+    pub allow_dash_identifiers: bool,
+    /// Characters that start a comment as well as `;`, each of which must be allowed by
+    /// [`lexer::is_valid_comment_char`]
+    pub comment_chars: Vec<char>,
+    /// The instruction slots the program must fit in, which labels are resolved relative to the start of
+    pub slot_range: Option<SlotRange>,
+    /// Which lints are run over the generated program
+    pub lints: lints::LintOptions,
+    /// Log each line, instruction and operand parsed, and what it was parsed into, to stderr
+    pub trace_parse: bool,
+}
+
+/// A label that was given a value, either by being defined or by being seeded
+#[derive(Debug, Clone)]
+pub struct SymbolInfo {
+    pub name: String,
+    /// The instruction index the label refers to, which is half of its byte address
+    pub value: i8,
+    /// Where the label is defined, or None if it was seeded
+    pub span: Option<Span>,
+    /// Whether the label was exported with `.global`
+    pub visibility: Visibility,
+}
+
+/// Everything produced by successfully assembling a source
+#[derive(Debug, Clone)]
+pub struct AssembleResult {
+    /// The output, without padding after the program
+    pub bytes: Vec<u8>,
+    /// Every label with a value, in the order the labels were first seen
+    pub symbols: Vec<SymbolInfo>,
+    /// Where each instruction was generated from, in output order
+    pub records: Vec<InstructionRecord>,
+    /// Where each piece of data, including `.space` and checksums, was generated from, in output order
+    pub data_records: Vec<DataRecord>,
+    /// The number of instruction memory slots used, including those taken up by data, but not those skipped before the
+    /// start of the slot range
+    pub instruction_count: usize,
+    /// The length of `bytes`, including the padding before the start of the slot range
+    pub byte_len: usize,
+    /// Every warning, including those found by [`lints::check`] that weren't allowed
+    pub warnings: Vec<Diagnostic>,
+    /// Every `.meta` entry, in source order
+    pub metadata: Vec<MetaEntry>,
+}
+
+/// Lexes, parses and generates code for a source, and runs the lints over it, without touching the filesystem or the
+/// terminal
+///
+/// The result has everything needed to write the output and any listing or symbol table, so a source only has to be
+/// assembled once however many of them are written
+pub fn assemble(
+    source_manager: &SourceManager,
+    options: &AssembleOptions,
+) -> Result<AssembleResult, Vec<Diagnostic>> {
+    let (mut generator, metadata, mut warnings) = parse(source_manager, options)?;
+
+    let bytes = generator
+        .generate()
+        .map_err(|e| vec![generator_error_into_diagnostic(e, source_manager)])?;
+
+    warnings.extend(lints::check(&generator, &bytes, &options.lints));
+    dedup_diagnostics(&mut warnings);
+
+    let label_manager = generator.label_manager();
+    let symbols = label_manager
+        .iter()
+        .enumerate()
+        .filter_map(|(label_id, (name, value, span))| {
+            value.map(|value| SymbolInfo {
+                name: name.to_string(),
+                value,
+                span,
+                visibility: label_manager.get_visibility_of(label_id),
+            })
+        })
+        .collect();
+
+    let mut data_records = generator.data_records().to_vec();
+    data_records.extend_from_slice(generator.checksum_records());
+    data_records.sort_by_key(|record| record.address);
+
+    let start = options.slot_range.map_or(0, |range| range.start);
+
+    Ok(AssembleResult {
+        symbols,
+        records: generator.records().to_vec(),
+        data_records,
+        // Every instruction and every piece of data takes up whole 2 byte slots
+        instruction_count: bytes.len() / 2 - start,
+        byte_len: bytes.len(),
+        bytes,
+        warnings,
+        metadata,
+    })
+}
+
+/// Like [`assemble`], but reports every error and warning to `emitter`
+///
+/// Returns the result if there were no errors
+pub fn assemble_with_emitter(
+    source_manager: &SourceManager,
+    options: &AssembleOptions,
+    emitter: &mut dyn Emitter,
+) -> Option<AssembleResult> {
+    match assemble(source_manager, options) {
+        Ok(result) => {
+            for warning in result.warnings.iter() {
+                emitter.emit(warning, source_manager);
+            }
+
+            Some(result)
+        }
+        Err(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                emitter.emit(diagnostic, source_manager);
+            }
+
+            None
+        }
+    }
+}
+
+/// Lexes and parses a source and counts its instructions, without generating any code
+///
+/// This stops as soon as the program is known not to fit, but note that errors only found while generating code,
+/// such as undefined labels, are not reported
+pub fn count_instructions(
+    source_manager: &SourceManager,
+    options: &AssembleOptions,
+) -> Result<usize, Vec<Diagnostic>> {
+    let (mut generator, _, _) = parse(source_manager, options)?;
+
+    generator
+        .assign_addresses()
+        .map_err(|e| vec![generator_error_into_diagnostic(e, source_manager)])
+}
+
+/// Lexes and parses a source and gives every label its value, without generating any code
+///
+/// The returned generator can be asked about the parsed program, such as with [`Generator::items`]
+pub fn parse_program(
+    source_manager: &SourceManager,
+    options: &AssembleOptions,
+) -> Result<Generator, Vec<Diagnostic>> {
+    let (mut generator, _, _) = parse(source_manager, options)?;
+
+    generator
+        .assign_addresses()
+        .map_err(|e| vec![generator_error_into_diagnostic(e, source_manager)])?;
+
+    Ok(generator)
+}
+
+/// Lexes and parses a source of only `.set` and `.port` directives, returning what it defines along with everything
+/// in `options.constants`, so that it can be used by other sources
+///
+/// Diagnostics refer to this source, rather than to any program using the definitions
+pub fn parse_constants(
+    source_manager: &SourceManager,
+    options: &AssembleOptions,
+) -> Result<ConstantDefinitions, Vec<Diagnostic>> {
+    let tokens = lex(source_manager, options)?;
+
+    let mut parser = Parser::new(&tokens, source_manager);
+    parser.set_strict_registers(options.strict_registers);
+    parser.seed_constants(&options.constants);
+
+    parser
+        .parse_constants()
+        .map_err(|e| vec![parse_error_into_diagnostic(e, source_manager)])
+}
+
+/// Errors for a source too long for its spans to be stored, which is checked before lexing since the lexer panics on
+/// one
+fn check_source_len(source_len: usize) -> Result<(), Diagnostic> {
+    if source_len > MAX_SOURCE_LEN {
+        Err(Diagnostic::error(format!(
+            "Source is {source_len} bytes, more than the {MAX_SOURCE_LEN} bytes that can be assembled"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Lexes a source, returning every token other than invalid ones, or an error for each invalid one
+fn lex(source_manager: &SourceManager, options: &AssembleOptions) -> Result<Vec<Token>, Vec<Diagnostic>> {
+    check_source_len(source_manager.source().len()).map_err(|diagnostic| vec![diagnostic])?;
+
+    let mut lexer = Lexer::new(source_manager.source());
+    lexer.set_register_sigil(options.strict_registers);
+    lexer.set_dash_identifiers(options.allow_dash_identifiers);
+    lexer.set_comment_chars(&options.comment_chars);
+
+    let tokens = lexer.lex();
+    let mut valid_tokens = Vec::with_capacity(tokens.len());
+    let mut diagnostics = Vec::new();
+
+    for token in tokens {
+        match token.tt {
+            TokenType::InvalidTokenError
+            | TokenType::InvalidIntegerError
+            | TokenType::InvalidLabelError
+            | TokenType::NumericLabelError
+            | TokenType::UnterminatedStringError
+            | TokenType::DashedLabelError => {
+                diagnostics.push(lexer_error_into_diagnostic(token, source_manager));
+            }
+            _ => valid_tokens.push(token),
+        }
+    }
+
+    // Every invalid token is reported at once, since they don't depend on each other
+    if !diagnostics.is_empty() {
+        dedup_diagnostics(&mut diagnostics);
+        return Err(diagnostics);
+    }
+
+    Ok(valid_tokens)
+}
+
+/// Lexes and parses a source, returning a generator ready to generate code for it along with its `.meta` entries and
+/// any warnings
+#[allow(clippy::type_complexity)]
+fn parse(
+    source_manager: &SourceManager,
+    options: &AssembleOptions,
+) -> Result<(Generator, Vec<MetaEntry>, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let tokens = lex(source_manager, options)?;
+
+    let mut parser = Parser::new(&tokens, source_manager);
+    parser.set_relax(options.relax);
+    parser.set_strict_registers(options.strict_registers);
+    parser.set_trace(options.trace_parse);
+
+    for (name, value) in options.seeded_labels.iter() {
+        parser.seed_label(name, *value);
+    }
+
+    parser.seed_constants(&options.constants);
+
+    let program = parser
+        .parse()
+        .map_err(|e| vec![parse_error_into_diagnostic(e, source_manager)])?;
+
+    let generator_options = GeneratorOptions {
+        forbid_forward_references: options.forbid_forward_references,
+        endianness: options.endianness,
+        slot_range: options.slot_range,
+    };
+
+    let warnings = program
+        .warnings
+        .into_iter()
+        .filter(|w| w.lint().is_none_or(|lint| options.lints.is_enabled(lint)))
+        .map(|w| parse_warning_into_diagnostic(w, source_manager))
+        .collect();
+
+    Ok((
+        Generator::new(program.items, program.label_manager, generator_options),
+        program.metadata,
+        warnings,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_source_that_can_be_assembled() {
+        assert!(check_source_len(MAX_SOURCE_LEN).is_ok());
+
+        let diagnostic = check_source_len(MAX_SOURCE_LEN + 1).unwrap_err();
+
+        assert_eq!(
+            *diagnostic.label(),
+            format!(
+                "Source is {} bytes, more than the {} bytes that can be assembled",
+                MAX_SOURCE_LEN + 1,
+                MAX_SOURCE_LEN
+            )
+        );
+        assert_eq!(diagnostic.label_span(), None);
+    }
+
+    #[test]
+    fn bytes_records_and_symbols_agree() {
+        let source = "\
+start:
+    ldi r0, 1
+loop:
+    add r0, r0
+    jz r0, done
+    j loop
+table:
+    .byte 1, 2, 3
+done:
+    j done
+";
+        let source_manager = SourceManager::new(source, String::from("test.basm"));
+        let result = assemble(&source_manager, &AssembleOptions::default()).unwrap();
+
+        assert_eq!(result.byte_len, result.bytes.len());
+        // Slots taken up by data count too
+        let data_slots: usize = result.data_records.iter().map(|record| record.len / 2).sum();
+        assert_eq!(result.instruction_count, result.records.len() + data_slots);
+
+        // Every instruction's opcode is in the high nibble of the byte at its address
+        for record in result.records.iter() {
+            assert_eq!(result.bytes[record.address] >> 4, record.opcode.encode(), "{record:?}");
+        }
+
+        let data = &result.data_records[0];
+        assert_eq!(&result.bytes[data.address..data.address + data.len], [1, 2, 3, 0]);
+
+        // Every label is at the address of whatever follows it in the source
+        let address_of = |name: &str| {
+            let symbol = result.symbols.iter().find(|symbol| symbol.name == name).unwrap();
+            symbol.value as usize * 2
+        };
+        let address_after = |label: &str| {
+            let end = (source.find(label).unwrap() + label.len()) as u32;
+            let instructions = result.records.iter().map(|record| (record.span.index, record.address));
+            let data = result.data_records.iter().map(|record| (record.span.index, record.address));
+
+            instructions.chain(data).filter(|&(index, _)| index > end).min().unwrap().1
+        };
+
+        for name in ["start", "loop", "table", "done"] {
+            assert_eq!(address_of(name), address_after(&format!("{name}:")), "{name}");
+        }
+    }
+}
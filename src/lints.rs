@@ -0,0 +1,204 @@
+//! Checks for programs that assemble, but probably don't do what was intended
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Opcode, Register},
+    errors::Diagnostic,
+    generator::{DataRecord, Generator, InstructionRecord, ResolvedInstruction, ResolvedOperand},
+    parser::{LabelManager, Visibility},
+};
+
+/// A check that can be turned off when what it warns about is intended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Lint {
+    /// A label that is defined but never referred to
+    UnusedLabel,
+    /// A program whose last instruction isn't a `j`, so execution continues past its end
+    Unterminated,
+    /// Two labels with the same address, which is off unless asked for
+    DuplicateAddress,
+    /// A `.set` constant that is never used
+    UnusedConstant,
+    /// A `.port` that is never used
+    UnusedPort,
+    /// Data whose bytes start with the opcode `0x4`, which no instruction has, so it would be garbage if run, which is
+    /// off unless asked for
+    ReservedOpcode,
+    /// An instruction that may leave a nonzero value in the register reserved to always hold zero, which is only
+    /// checked when there is such a register
+    ZeroRegister,
+}
+
+impl Lint {
+    /// Whether the lint is run without being asked for
+    pub fn is_default(self) -> bool {
+        !matches!(self, Self::DuplicateAddress | Self::ReservedOpcode)
+    }
+}
+
+/// Which lints are run, beyond the default ones
+#[derive(Debug, Clone, Default)]
+pub struct LintOptions {
+    /// Lints that aren't run, even if they are also in `warned`
+    pub allowed: Vec<Lint>,
+    /// Lints that are run even though they are off by default
+    pub warned: Vec<Lint>,
+    /// The register the program keeps at zero by convention, if any, which the `zero-register` lint checks writes to
+    pub zero_register: Option<Register>,
+}
+
+impl LintOptions {
+    pub fn is_enabled(&self, lint: Lint) -> bool {
+        !self.allowed.contains(&lint) && (lint.is_default() || self.warned.contains(&lint))
+    }
+}
+
+/// Runs every enabled lint over a generated program, returning a warning for each problem found
+///
+/// `output` must be what the generator's last call to `generate` returned
+pub fn check(generator: &Generator, output: &[u8], options: &LintOptions) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+
+    if options.is_enabled(Lint::UnusedLabel) {
+        check_unused_labels(generator.label_manager(), &mut warnings);
+    }
+
+    if options.is_enabled(Lint::Unterminated) {
+        check_unterminated(generator.records(), &mut warnings);
+    }
+
+    if options.is_enabled(Lint::DuplicateAddress) {
+        check_duplicate_addresses(generator.label_manager(), &mut warnings);
+    }
+
+    if options.is_enabled(Lint::ReservedOpcode) {
+        check_reserved_opcodes(generator.data_records(), output, &mut warnings);
+    }
+
+    if let Some(register) = options.zero_register {
+        if options.is_enabled(Lint::ZeroRegister) {
+            check_zero_register(generator.instructions(), register, &mut warnings);
+        }
+    }
+
+    warnings
+}
+
+/// Warns about labels that are defined but never referred to, other than those exported to be used from elsewhere
+fn check_unused_labels(label_manager: &LabelManager, warnings: &mut Vec<Diagnostic>) {
+    for (label_id, (name, _, span)) in label_manager.iter().enumerate() {
+        if let Some(span) = span {
+            if !label_manager.is_referenced(label_id)
+                && label_manager.get_visibility_of(label_id) != Visibility::Global
+            {
+                warnings.push(Diagnostic::warning_with_span(
+                    format!("Label `{}` is never used", name),
+                    span,
+                ));
+            }
+        }
+    }
+}
+
+/// Warns when the last instruction could be followed by whatever comes after the program, usually padding, because
+/// it isn't an unconditional jump
+fn check_unterminated(records: &[InstructionRecord], warnings: &mut Vec<Diagnostic>) {
+    let Some(last) = records.iter().max_by_key(|record| record.address) else {
+        return;
+    };
+
+    if last.opcode != Opcode::J {
+        warnings.push(Diagnostic::warning_with_span(
+            "Execution continues past the end of the program after this instruction, end it with a loop such as `end: j end`",
+            last.span,
+        ));
+    }
+}
+
+/// Warns about each label that has the same address as one seen before it, which can mean an instruction is missing,
+/// or that a seeded label overlaps the program
+fn check_duplicate_addresses(label_manager: &LabelManager, warnings: &mut Vec<Diagnostic>) {
+    let mut first_at_value: HashMap<i8, (&str, _)> = HashMap::new();
+
+    for (name, value, span) in label_manager.iter() {
+        let Some(value) = value else {
+            continue;
+        };
+
+        if let Some(&(first_name, first_span)) = first_at_value.get(&value) {
+            // Label values are instruction indices, which are two bytes apart
+            let label = format!(
+                "Labels `{}` and `{}` both refer to address 0x{:02X}",
+                first_name,
+                name,
+                value as i32 * 2
+            );
+
+            // Seeded labels have nowhere to point to
+            warnings.push(match span.or(first_span) {
+                Some(span) => Diagnostic::warning_with_span(label, span),
+                None => Diagnostic::warning(label),
+            });
+        } else {
+            first_at_value.insert(value, (name, span));
+        }
+    }
+}
+
+/// Warns about each instruction that writes to `register` and may leave something other than zero in it, assuming it
+/// held zero before
+fn check_zero_register(
+    instructions: impl Iterator<Item = ResolvedInstruction>,
+    register: Register,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    let zero = ResolvedOperand::Register(register);
+
+    for instruction in instructions {
+        let writes_register = instruction
+            .operands
+            .iter()
+            .zip(instruction.opcode.operand_access())
+            .any(|(operand, access)| access.writes() && *operand == zero);
+
+        let keeps_zero = match (instruction.opcode, instruction.operands.as_slice()) {
+            (Opcode::Ldi, [_, ResolvedOperand::Integer(0)]) => true,
+            // Zero anded with or shifted by anything stays zero
+            (Opcode::And | Opcode::Sr | Opcode::Sl, _) => true,
+            // As do zero plus, minus, or or xor zero
+            (Opcode::Add | Opcode::Sub | Opcode::Or | Opcode::Xor, [_, other]) => *other == zero,
+            _ => false,
+        };
+
+        if writes_register && !keeps_zero {
+            warnings.push(Diagnostic::warning_with_span(
+                format!(
+                    "`{}` is reserved to always hold zero, but this instruction may change it",
+                    register.name()
+                ),
+                instruction.span,
+            ));
+        }
+    }
+}
+
+/// Warns about each slot of data that starts with an opcode no instruction has, such as a mistake in a hand-written
+/// table that happens to be placed where it could be run
+fn check_reserved_opcodes(records: &[DataRecord], output: &[u8], warnings: &mut Vec<Diagnostic>) {
+    for record in records {
+        for address in (record.address..record.address + record.len).step_by(2) {
+            let value = output[address] >> 4;
+
+            if Opcode::decode(value).is_none() {
+                warnings.push(Diagnostic::warning_with_span(
+                    format!(
+                        "Data at address 0x{:02X} starts with 0x{:X}, which isn't the opcode of any instruction",
+                        address, value
+                    ),
+                    record.span,
+                ));
+            }
+        }
+    }
+}
@@ -1,32 +1,32 @@
-use std::{
-    fs::File,
-    io::{Read, Write},
-    path::PathBuf,
-};
-
-use clap::Parser as ClapParser;
+use std::{fs::File, io::Write, path::PathBuf, process::ExitCode};
 
-use errors::{generator_error_into_diagnostic, parse_error_into_diagnostic, TerminalEmitter};
-use generator::{Generator, INSTRUCTION_MEMORY_SIZE_BYTES};
-use lexer::Lexer;
-use parser::Parser;
-use sources::SourceManager;
+use clap::{Parser as ClapParser, ValueEnum};
 
-use crate::lexer::TokenType;
-
-mod ast;
-mod errors;
-mod generator;
-mod instructions;
-mod lexer;
-mod parser;
-mod sources;
+use brisc_assembler::{
+    assemble,
+    ast::{Opcode, Register},
+    count_instructions,
+    disassembler::{self, DisassemblyImmediates, DisassemblyOptions, DisassemblyStop},
+    errors::{Charset, ColorChoice, Diagnostic, Emitter, ErrorFormat, TerminalEmitter, TerminalEmitterOptions},
+    formats::{self, Checksum, Format, ListingOptions, OutputFormat, SymbolOrder},
+    generator::{Endianness, SlotRange, INSTRUCTION_MEMORY_SIZE_BYTES, MAX_NUM_INSTRUCTIONS},
+    instructions::{rules, CycleTable, IntegerRole},
+    lexer,
+    lints::{Lint, LintOptions},
+    parse_constants, parse_program,
+    parser::ConstantDefinitions,
+    sources::SourceManager,
+    AssembleOptions, AssembleResult,
+};
 
 #[derive(ClapParser, Debug)]
 #[command(author, version, about)]
 struct Args {
-    #[arg(help = "Input assembly language file")]
-    file: String,
+    #[arg(
+        help = "Input assembly language files, each assembled as its own program, or assembled binaries with --disassemble",
+        required_unless_present_any = ["print_isa", "list_unused_opcodes", "self_test"]
+    )]
+    files: Vec<String>,
 
     #[arg(
         long,
@@ -36,102 +36,961 @@ struct Args {
     output_path: Option<String>,
 
     #[arg(long, short, help = "Helpful for debugging the assembler itself")]
-    debug: bool
+    debug: bool,
+
+    #[arg(
+        long,
+        help = "Log each line, instruction and operand as it is parsed, along with what it was parsed into, to stderr"
+    )]
+    trace_parse: bool,
+
+    #[arg(long, help = "Also write a listing of the source with generated addresses and bytes")]
+    listing: Option<String>,
+
+    #[arg(
+        long,
+        requires = "listing",
+        help = "Annotate the listing with the estimated cycles each instruction takes"
+    )]
+    cycles: bool,
+
+    #[arg(
+        long,
+        value_parser = parse_cycle_cost,
+        value_name = "MNEMONIC=CYCLES",
+        help = "Override the cycle cost of an instruction used by --cycles and --straight-line-length, which is 1 by default"
+    )]
+    cycle_cost: Vec<(Opcode, u32)>,
+
+    #[arg(long, help = "Also write a table of every label and its address")]
+    symbols: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = SymbolOrder::Source,
+        help = "Order of the symbol table written by --symbols"
+    )]
+    symbols_sort: SymbolOrder,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Also write a map of instruction memory, with the address range of each region of code, data and free space"
+    )]
+    map: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Also write a Makefile rule making the output depend on every source file that was read"
+    )]
+    emit_depfile: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Also write every .meta entry as a JSON object"
+    )]
+    emit_meta: Option<String>,
+
+    #[arg(
+        long,
+        value_parser = parse_address,
+        default_value = "0",
+        help = "Address the instruction memory is mapped at, used only for displayed addresses"
+    )]
+    base_address: u32,
+
+    #[arg(
+        long,
+        help = "Report an error for any label used before the line it is defined on"
+    )]
+    no_forward_references: bool,
+
+    #[arg(
+        long = "label",
+        value_parser = parse_label_seed,
+        value_name = "NAME=INDEX",
+        help = "Give a label the index of an instruction outside the program, so it can be jumped to without being defined"
+    )]
+    labels: Vec<(String, i8)>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Define the constants and ports in a file of only .set and .port directives before assembling (repeatable)"
+    )]
+    include_constants: Vec<String>,
+
+    #[arg(
+        long,
+        value_parser = parse_slot_range,
+        value_name = "START:END",
+        help = "Place the program in instruction slots START up to END, padding the output before it, and report an error if it doesn't fit"
+    )]
+    slot_range: Option<SlotRange>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Endianness::Big,
+        help = "Byte order of the values of .word and dw directives"
+    )]
+    endianness: Endianness,
+
+    #[arg(
+        long,
+        help = "Accept immediates of 128 to 255 as the signed byte with the same bits, with a warning"
+    )]
+    relax: bool,
+
+    #[arg(
+        long,
+        help = "Only accept registers written with a $, such as $r0, so that r0 on its own is always a label"
+    )]
+    strict_registers: bool,
+
+    #[arg(
+        long = "allow-dash-idents",
+        help = "Let labels and other names contain a -, such as loop-1, as older versions did, rather than it being a minus"
+    )]
+    allow_dash_identifiers: bool,
+
+    #[arg(
+        long = "comment-char",
+        value_parser = parse_comment_char,
+        value_name = "CHAR",
+        help = "Also start a comment with this character, such as #, as well as with ; (repeatable)"
+    )]
+    comment_chars: Vec<char>,
+
+    #[arg(
+        long,
+        help = "Treat warnings as errors, so that any warning stops the output from being written"
+    )]
+    deny_warnings: bool,
+
+    #[arg(
+        short = 'W',
+        value_enum,
+        value_name = "LEVEL",
+        help = "Set to `error` to treat warnings as errors, the same as --deny-warnings"
+    )]
+    warnings: Option<WarningLevel>,
+
+    #[arg(
+        short = 'A',
+        long = "allow",
+        value_enum,
+        value_name = "LINT",
+        help = "Don't warn about this lint (repeatable)"
+    )]
+    allowed_lints: Vec<Lint>,
+
+    #[arg(long, help = "Warn when two labels refer to the same address")]
+    warn_duplicate_addresses: bool,
+
+    #[arg(
+        long,
+        help = "Warn about data whose bytes start with an opcode that no instruction has, so it would be garbage if run"
+    )]
+    warn_reserved_opcodes: bool,
+
+    #[arg(
+        long,
+        value_parser = parse_convention,
+        value_name = "CONVENTION",
+        help = "Warn about code that breaks a register convention, such as zero-reg=r0 for a register that always holds zero (repeatable)"
+    )]
+    convention: Vec<Convention>,
+
+    #[arg(
+        long,
+        help = "Only check that the program assembles and fits in memory, printing `FITS: <used>/<total>` or the first error"
+    )]
+    count_only: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "count_only",
+        help = "Only parse the program, printing how often each register is read and written, and which are unused"
+    )]
+    count_registers: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["count_only", "count_registers"],
+        help = "Only parse the program, printing how many instructions and estimated cycles run from the start up to the first j"
+    )]
+    straight_line_length: bool,
+
+    #[arg(
+        long,
+        help = "Only parse the program, writing it as JSON to the output path, or to standard output if none is given"
+    )]
+    json_ast: bool,
+
+    #[arg(
+        long,
+        help = "Once the output is written, print a 64-bit FNV-1a hash of the program, without padding, in hex to standard output"
+    )]
+    print_hash: bool,
+
+    #[arg(
+        long,
+        requires = "print_hash",
+        help = "Hash the output as it is written, with padding and any checksum byte, rather than only the program"
+    )]
+    hash_padded: bool,
+
+    #[arg(
+        long,
+        value_name = "CHARACTERS",
+        help = "Shorten long source lines in error messages to this many characters around the error"
+    )]
+    error_context_width: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ErrorFormat::Rich,
+        help = "Print error messages with the source line they point to, or as a single line of `file:line:column: kind: message`"
+    )]
+    error_format: ErrorFormat,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ColorChoice::Auto,
+        help = "When to color error messages"
+    )]
+    color: ColorChoice,
+
+    #[arg(long, conflicts_with = "color", help = "Never color error messages, same as --color never")]
+    no_color: bool,
+
+    #[arg(
+        long,
+        help = "Draw error messages with plain ASCII, rather than Unicode when the terminal supports it"
+    )]
+    ascii: bool,
+
+    #[arg(
+        long,
+        help = "Print the assembly language for an assembled binary, to the output path if one is given"
+    )]
+    disassemble: bool,
+
+    #[arg(
+        long,
+        requires = "disassemble",
+        help = "Label every jump destination, and write the disassembly so that it can be assembled again"
+    )]
+    disasm_labels: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DisassemblyStop::Auto,
+        help = "Whether to stop the disassembly after the last word that isn't a nop, or show every word including padding"
+    )]
+    disasm_stop: DisassemblyStop,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DisassemblyImmediates::Signed,
+        help = "How to write the immediate of an ldi in the disassembly, as a signed byte, an unsigned byte or hex"
+    )]
+    disasm_immediates: DisassemblyImmediates,
+
+    #[arg(
+        long,
+        help = "Print every instruction the assembler accepts, along with its encoding, as text or with --format json, and exit"
+    )]
+    print_isa: bool,
+
+    #[arg(
+        long,
+        help = "Print every opcode from 0x0 to 0xf that no instruction uses, one per line, and exit"
+    )]
+    list_unused_opcodes: bool,
+
+    #[arg(
+        long,
+        help = "Assemble a built-in program that uses every instruction, print PASS if it gives the expected bytes or FAIL if not, and exit"
+    )]
+    self_test: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Format::Bin,
+        help = "Format of the output, and with --print-isa, json prints the instruction table as JSON"
+    )]
+    format: Format,
+
+    #[arg(long, help = "Show the mnemonic of each instruction under its bytes in --format grid")]
+    disasm_grid: bool,
+
+    #[arg(long, help = "Don't pad the output to the size of instruction memory")]
+    no_pad: bool,
+
+    #[arg(
+        long,
+        value_parser = parse_address,
+        value_name = "BYTES",
+        conflicts_with = "no_pad",
+        help = "Pad the output to this many bytes rather than the size of instruction memory, reporting an error if the program doesn't fit"
+    )]
+    image_size: Option<u32>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Append a checksum of the program, not including padding, after the padded output"
+    )]
+    checksum: Option<Checksum>,
+
+    #[arg(
+        long,
+        value_parser = parse_c_identifier,
+        default_value = "program",
+        help = "Name of the array written by --format c-array"
+    )]
+    array_name: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum WarningLevel {
+    Error,
+}
+
+/// How `--print-isa` prints the instruction table, chosen with `--format`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IsaFormat {
+    Text,
+    Json,
+}
+
+/// A rule about how registers are used that a program has agreed to follow
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Convention {
+    /// The register always holds zero
+    ZeroRegister(Register),
 }
 
-fn main() {
+fn main() -> ExitCode {
     let args = Args::parse();
 
-    let source = match File::open(&args.file) {
-        Ok(mut file) => {
-            let mut contents = String::new();
+    if args.print_isa {
+        return print_isa(args.format);
+    }
+
+    if args.list_unused_opcodes {
+        list_unused_opcodes();
+        return ExitCode::SUCCESS;
+    }
+
+    let mut emitter = TerminalEmitter::new(TerminalEmitterOptions {
+        context_width: args.error_context_width,
+        color: if args.no_color {
+            ColorChoice::Never
+        } else {
+            args.color
+        }
+        .should_color(),
+        format: args.error_format,
+        charset: if args.ascii {
+            Charset::Ascii
+        } else {
+            Charset::detect()
+        },
+    });
+
+    if args.self_test {
+        return self_test(&mut emitter);
+    }
+
+    if args.format == Format::Json && args.debug {
+        eprintln!("--debug can't be used with --format json, which prints its summary to standard output");
+        return ExitCode::FAILURE;
+    }
+
+    // Clap guarantees there is at least one unless an informational flag was given
+    let files = &args.files;
 
-            if let Err(e) = file.read_to_string(&mut contents) {
-                eprintln!("File read error: {e}");
-                return;
+    if files.len() > 1
+        && (args.output_path.is_some()
+            || args.listing.is_some()
+            || args.symbols.is_some()
+            || args.map.is_some()
+            || args.emit_depfile.is_some()
+            || args.emit_meta.is_some())
+    {
+        eprintln!(
+            "--output-path, --listing, --symbols, --map, --emit-depfile and --emit-meta can only be used with a single input file"
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let mut constants = ConstantDefinitions::default();
+
+    // Each constants file can use what the ones before it define
+    for path in args.include_constants.iter() {
+        let source_manager = match SourceManager::from_file(path) {
+            Ok(source_manager) => source_manager,
+            Err(e) => {
+                eprintln!("File read error: {path}: {e}");
+                return ExitCode::FAILURE;
             }
+        };
+
+        let options = AssembleOptions {
+            strict_registers: args.strict_registers,
+            allow_dash_identifiers: args.allow_dash_identifiers,
+            comment_chars: args.comment_chars.clone(),
+            constants,
+            ..AssembleOptions::default()
+        };
 
-            contents
+        constants = match parse_constants(&source_manager, &options) {
+            Ok(constants) => constants,
+            Err(diagnostics) => {
+                for diagnostic in diagnostics.iter() {
+                    emitter.emit(diagnostic, &source_manager);
+                }
+
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+
+    // Each file is its own program, so one failing doesn't stop the others from being assembled
+    for file in files {
+        let file_exit_code = if args.disassemble {
+            let options = DisassemblyOptions {
+                labels: args.disasm_labels,
+                stop: args.disasm_stop,
+                immediates: args.disasm_immediates,
+            };
+
+            disassemble(file, args.output_path.as_deref(), &options)
+        } else {
+            assemble_file(&args, file, &constants, &mut emitter)
+        };
+
+        if file_exit_code != ExitCode::SUCCESS {
+            exit_code = file_exit_code;
         }
+    }
+
+    exit_code
+}
+
+/// Assembles a single input file and writes everything asked for by the arguments
+fn assemble_file(
+    args: &Args,
+    file: &str,
+    constants: &ConstantDefinitions,
+    emitter: &mut dyn Emitter,
+) -> ExitCode {
+    let source_manager = match SourceManager::from_file(file) {
+        Ok(source_manager) => source_manager,
         Err(e) => {
-            eprintln!("File read error: {e}");
-            return;
+            eprintln!("File read error: {file}: {e}");
+            return ExitCode::FAILURE;
         }
     };
 
-    let source_manager = SourceManager::new(&source, args.file.clone());
+    let options = AssembleOptions {
+        forbid_forward_references: args.no_forward_references,
+        endianness: args.endianness,
+        seeded_labels: args.labels.clone(),
+        constants: constants.clone(),
+        relax: args.relax,
+        strict_registers: args.strict_registers,
+        allow_dash_identifiers: args.allow_dash_identifiers,
+        comment_chars: args.comment_chars.clone(),
+        slot_range: args.slot_range,
+        trace_parse: args.trace_parse,
+        lints: LintOptions {
+            allowed: args.allowed_lints.clone(),
+            warned: [
+                (args.warn_duplicate_addresses, Lint::DuplicateAddress),
+                (args.warn_reserved_opcodes, Lint::ReservedOpcode),
+            ]
+            .into_iter()
+            .filter_map(|(warned, lint)| warned.then_some(lint))
+            .collect(),
+            // The last one given wins
+            zero_register: args
+                .convention
+                .iter()
+                .rev()
+                .map(|convention| match convention {
+                    Convention::ZeroRegister(register) => *register,
+                })
+                .next(),
+        },
+    };
+
+    if args.count_only {
+        return match count_instructions(&source_manager, &options) {
+            Ok(count) => {
+                if args.files.len() > 1 {
+                    print!("{file}: ");
+                }
+
+                println!("FITS: {}/{}", count, MAX_NUM_INSTRUCTIONS);
+                ExitCode::SUCCESS
+            }
+            Err(diagnostics) => {
+                if let Some(diagnostic) = diagnostics.into_iter().next() {
+                    emitter.emit(&diagnostic, &source_manager);
+                }
+
+                ExitCode::FAILURE
+            }
+        };
+    }
 
-    let mut lexer = Lexer::new(&source);
+    if args.straight_line_length {
+        let generator = match parse_program(&source_manager, &options) {
+            Ok(generator) => generator,
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    emitter.emit(&diagnostic, &source_manager);
+                }
 
-    let tokens = lexer.lex();
-    let mut valid_tokens = Vec::with_capacity(tokens.capacity());
+                return ExitCode::FAILURE;
+            }
+        };
 
-    for token in tokens {
-        if token.tt == TokenType::InvalidTokenError {
-            let text = source_manager.get_span(token.span).unwrap();
-            eprintln!("Invalid token found `{}`", text);
-        } else if token.tt == TokenType::InvalidIntegerError {
-            let text = source_manager.get_span(token.span).unwrap();
-            eprintln!("Invalid integer value `{}`", text);
-        } else if token.tt != TokenType::Comment {
-            valid_tokens.push(token);
+        if args.files.len() > 1 {
+            print!("{file}: ");
         }
+
+        let cycle_table = cycle_table(args);
+
+        return match formats::write_straight_line_length(&mut std::io::stdout(), generator.items(), &cycle_table) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("File write error: {e}");
+                ExitCode::FAILURE
+            }
+        };
     }
 
-    let parser = Parser::new(&valid_tokens, &source_manager);
+    if args.count_registers {
+        let generator = match parse_program(&source_manager, &options) {
+            Ok(generator) => generator,
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    emitter.emit(&diagnostic, &source_manager);
+                }
 
-    let (items, label_manager) = match parser.parse() {
-        Ok((items, label_manager)) => (items, label_manager),
-        Err(e) => {
-            TerminalEmitter::emit(
-                parse_error_into_diagnostic(e, &source_manager),
-                &source_manager,
-            );
-            return;
+                return ExitCode::FAILURE;
+            }
+        };
+
+        if args.files.len() > 1 {
+            println!("{file}:");
+        }
+
+        return match formats::write_register_usage(&mut std::io::stdout(), generator.items()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("File write error: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    if args.json_ast {
+        let generator = match parse_program(&source_manager, &options) {
+            Ok(generator) => generator,
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    emitter.emit(&diagnostic, &source_manager);
+                }
+
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let write = |out: &mut dyn Write| {
+            formats::write_json_ast(out, generator.items(), generator.label_manager())
+        };
+
+        let result = match &args.output_path {
+            Some(path) => File::create(path).and_then(|mut file| write(&mut file)),
+            None => write(&mut std::io::stdout()),
+        };
+
+        return match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("File write error: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let AssembleResult {
+        bytes: mut output,
+        symbols,
+        records,
+        data_records,
+        instruction_count,
+        byte_len,
+        warnings,
+        metadata,
+    } = match assemble(&source_manager, &options) {
+        Ok(result) => result,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                emitter.emit(&diagnostic, &source_manager);
+            }
+
+            return ExitCode::FAILURE;
         }
     };
 
-    let mut generator = Generator::new(items, label_manager);
-    let mut output = match generator.generate() {
-        Ok(output) => output,
-        Err(e) => {
-            TerminalEmitter::emit(
-                generator_error_into_diagnostic(e, &source_manager),
+    if args.deny_warnings || args.warnings == Some(WarningLevel::Error) {
+        if !warnings.is_empty() {
+            for warning in warnings {
+                emitter.emit(&warning.into_error(), &source_manager);
+            }
+
+            return ExitCode::FAILURE;
+        }
+    } else {
+        for warning in warnings {
+            emitter.emit(&warning, &source_manager);
+        }
+    }
+
+    // The image only decides how much padding there is, and is separate from the size of instruction memory, which
+    // the program has already been checked against
+    let image_size = args
+        .image_size
+        .map_or(INSTRUCTION_MEMORY_SIZE_BYTES as usize, |size| size as usize);
+
+    if output.len() > image_size {
+        let label = format!(
+            "Output of {} bytes doesn't fit in an image of {} bytes",
+            output.len(),
+            image_size
+        );
+        emitter.emit(&Diagnostic::error(label), &source_manager);
+
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(listing_path) = &args.listing {
+        let cycle_table = cycle_table(args);
+
+        let options = ListingOptions {
+            base_address: args.base_address,
+            cycles: args.cycles.then_some(&cycle_table),
+        };
+
+        let result = write_aux_file(listing_path, |out| {
+            formats::write_listing(
+                out,
+                &output,
+                &records,
+                &data_records,
                 &source_manager,
-            );
-            return;
+                &options,
+            )
+        });
+
+        if result.is_err() {
+            return ExitCode::FAILURE;
         }
-    };
+    }
+
+    if let Some(symbols_path) = &args.symbols {
+        let result = write_aux_file(symbols_path, |out| {
+            formats::write_symbols(out, &symbols, args.base_address, args.symbols_sort)
+        });
 
-    let num_null_bytes = INSTRUCTION_MEMORY_SIZE_BYTES as usize - output.len();
+        if result.is_err() {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(map_path) = &args.map {
+        let result = write_aux_file(map_path, |out| {
+            formats::write_map(out, byte_len, &records, &data_records, &symbols, args.base_address)
+        });
 
-    for _ in 0..num_null_bytes {
-        output.push(0);
+        if result.is_err() {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(meta_path) = &args.emit_meta {
+        if write_aux_file(meta_path, |out| formats::write_metadata(out, &metadata)).is_err() {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // Computed before padding so the checksum doesn't depend on whether the output is padded
+    let checksum = args.checksum.map(|checksum| checksum.compute(&output));
+
+    if !args.no_pad {
+        let num_null_bytes = image_size.saturating_sub(output.len());
+
+        output.resize(output.len() + num_null_bytes, 0);
+    }
+
+    if let Some(checksum) = checksum {
+        output.push(checksum);
     }
 
     if args.debug {
         debug_print_output(&output);
     }
 
-    let output_path = args.output_path.unwrap_or_else(|| {
-        let mut output_file = PathBuf::from(args.file);
-        output_file.set_extension("bin");
+    let writer: Box<dyn OutputFormat> = match args.format {
+        Format::Bin | Format::Json => Box::new(formats::Bin),
+        Format::CArray => Box::new(formats::CArray {
+            name: &args.array_name,
+            metadata: &metadata,
+        }),
+        Format::Hexlines => Box::new(formats::Hexlines {
+            records: &records,
+            source_manager: &source_manager,
+            metadata: &metadata,
+        }),
+        Format::Grid => Box::new(formats::Grid {
+            records: &records,
+            base_address: args.base_address,
+            mnemonics: args.disasm_grid,
+        }),
+    };
+
+    let output_path = args.output_path.clone().unwrap_or_else(|| {
+        let mut output_file = PathBuf::from(file);
+        output_file.set_extension(writer.extension());
         String::from(output_file.to_str().unwrap())
     });
 
-    match File::create(output_path) {
+    match File::create(&output_path) {
         Ok(mut file) => {
-            if let Err(e) = file.write_all(&output) {
+            if let Err(e) = writer.write(&output, &mut file) {
                 eprintln!("File write error: {e}");
+                return ExitCode::FAILURE;
             }
         }
         Err(e) => {
             eprintln!("File write error: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(depfile_path) = &args.emit_depfile {
+        let mut deps = vec![source_manager.file_name().as_str()];
+        deps.extend(args.include_constants.iter().map(String::as_str));
+
+        if write_aux_file(depfile_path, |out| formats::write_depfile(out, &output_path, &deps)).is_err() {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let hash = args.print_hash.then(|| {
+        // Padding is only ever added after the program, so the first bytes are still the program on its own
+        let hashed = if args.hash_padded { &output[..] } else { &output[..byte_len] };
+
+        formats::fingerprint(hashed)
+    });
+
+    if args.format == Format::Json {
+        // The hash goes in the summary, so that standard output is only JSON
+        let result =
+            formats::write_json_summary(&mut std::io::stdout(), instruction_count, byte_len, &output_path, hash);
+
+        if let Err(e) = result {
+            eprintln!("File write error: {e}");
+            return ExitCode::FAILURE;
+        }
+    } else if let Some(hash) = hash {
+        if args.files.len() > 1 {
+            print!("{file}: ");
+        }
+
+        println!("{hash:016x}");
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn disassemble(file: &str, output_path: Option<&str>, options: &DisassemblyOptions) -> ExitCode {
+    let bytes = match std::fs::read(file) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("File read error: {e}");
+            return ExitCode::FAILURE;
         }
+    };
+
+    let words = disassembler::disassemble(&bytes);
+
+    let result = match output_path {
+        Some(path) => File::create(path)
+            .and_then(|mut file| disassembler::write_disassembly(&mut file, &words, options)),
+        None => disassembler::write_disassembly(&mut std::io::stdout(), &words, options),
+    };
+
+    if let Err(e) = result {
+        eprintln!("File write error: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Parses an address given in decimal, or in hexadecimal with a `0x` prefix
+fn parse_address(text: &str) -> Result<u32, String> {
+    let result = if let Some(hex) = text.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else {
+        text.parse::<u32>()
+    };
+
+    result.map_err(|e| format!("invalid address `{text}`: {e}"))
+}
+
+/// Parses a character that starts a comment, which has to be a single character that doesn't already start a token
+fn parse_comment_char(text: &str) -> Result<char, String> {
+    let mut chars = text.chars();
+
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return Err(format!("expected a single character, found `{text}`"));
+    };
+
+    if !lexer::is_valid_comment_char(c) {
+        return Err(format!("`{c}` can't start a comment, since it already starts something else"));
+    }
+
+    Ok(c)
+}
+
+/// Parses an instruction cost override of the form `mnemonic=cycles`
+fn parse_cycle_cost(text: &str) -> Result<(Opcode, u32), String> {
+    let (mnemonic, cycles) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected `mnemonic=cycles`, found `{text}`"))?;
+
+    let opcode = Opcode::try_from(mnemonic.to_lowercase().as_str())
+        .map_err(|_| format!("`{mnemonic}` is not a valid instruction"))?;
+    let cycles = cycles
+        .parse::<u32>()
+        .map_err(|e| format!("invalid cycle count `{cycles}`: {e}"))?;
+
+    Ok((opcode, cycles))
+}
+
+/// Parses a label given a value on the command line, of the form `name=index`
+fn parse_label_seed(text: &str) -> Result<(String, i8), String> {
+    let (name, index) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=index`, found `{text}`"))?;
+
+    if name.is_empty() {
+        return Err(format!("missing label name in `{text}`"));
+    }
+
+    let index = parse_address(index)?;
+    let range = IntegerRole::JumpDestination.range();
+
+    if !range.contains(&(index as i64)) {
+        return Err(format!(
+            "label `{name}` must be given an instruction index in the range of {}-{}, found {index}",
+            range.start(),
+            range.end()
+        ));
+    }
+
+    Ok((name.to_string(), index as i8))
+}
+
+/// Parses a range of instruction slots of the form `start:end`, where `end` is excluded
+fn parse_slot_range(text: &str) -> Result<SlotRange, String> {
+    let (start, end) = text
+        .split_once(':')
+        .ok_or_else(|| format!("expected `start:end`, found `{text}`"))?;
+
+    let start = parse_address(start)? as usize;
+    let end = parse_address(end)? as usize;
+
+    if start >= end || end > MAX_NUM_INSTRUCTIONS as usize {
+        return Err(format!(
+            "slot range must be non-empty and end at or before slot {MAX_NUM_INSTRUCTIONS}, found `{text}`"
+        ));
+    }
+
+    Ok(SlotRange { start, end })
+}
+
+/// Parses a convention given as `NAME=VALUE`
+fn parse_convention(text: &str) -> Result<Convention, String> {
+    let (name, value) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=value`, found `{text}`"))?;
+
+    match name {
+        "zero-reg" => Register::try_from(value)
+            .map(Convention::ZeroRegister)
+            .map_err(|_| format!("expected a register from r0 to r15, found `{value}`")),
+        _ => Err(format!("unknown convention `{name}`, expected `zero-reg`")),
+    }
+}
+
+/// Checks that a name can be used as a C identifier
+fn parse_c_identifier(text: &str) -> Result<String, String> {
+    let mut chars = text.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if valid {
+        Ok(text.to_string())
+    } else {
+        Err(format!("`{text}` is not a valid C identifier"))
     }
 }
 
-fn debug_print_output(output: &Vec<u8>) {
+/// The cost of each instruction, with the overrides from `--cycle-cost`
+fn cycle_table(args: &Args) -> CycleTable {
+    let mut cycle_table = CycleTable::new();
+
+    for &(opcode, cycles) in args.cycle_cost.iter() {
+        cycle_table.set_cost(opcode, cycles);
+    }
+
+    cycle_table
+}
+
+/// Writes one of the files asked for alongside the output, reporting which one failed if it can't be written
+fn write_aux_file(path: &str, write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>) -> std::io::Result<()> {
+    let result = File::create(path).and_then(|mut file| write(&mut file));
+
+    if let Err(e) = &result {
+        eprintln!("File write error: {path}: {e}");
+    }
+
+    result
+}
+
+fn debug_print_output(output: &[u8]) {
     let mut col = 1;
 
     for b in output.iter() {
@@ -144,4 +1003,184 @@ fn debug_print_output(output: &Vec<u8>) {
 
         col += 1;
     }
-}
\ No newline at end of file
+}
+
+/// Prints the instruction table as text, or as JSON with `--format json`
+fn print_isa(format: Format) -> ExitCode {
+    let format = match format {
+        Format::Bin => IsaFormat::Text,
+        Format::Json => IsaFormat::Json,
+        Format::CArray | Format::Hexlines | Format::Grid => {
+            eprintln!("--print-isa can only be printed as text, the default, or with --format json");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let parse_rules = rules::parse_rules();
+    let mut entries = Vec::new();
+
+    for opcode in Opcode::all() {
+        if let Some(&operand_rules) = parse_rules.get(&opcode) {
+            entries.push((opcode, operand_rules));
+        } else {
+            eprintln!(
+                "Internal Assembler Error: No operand rules registered for `{}`",
+                opcode.mnemonic()
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match format {
+        IsaFormat::Text => {
+            println!("{:<10}{:<8}{:<18}operands", "mnemonic", "opcode", "encoding");
+
+            for (opcode, operand_rules) in entries {
+                let operands: Vec<String> = operand_rules
+                    .iter()
+                    .map(|rule| {
+                        let types: Vec<&str> = rule.iter().map(|ot| ot.as_str()).collect();
+                        types.join("|")
+                    })
+                    .collect();
+
+                let line = format!(
+                    "{:<10}{:<8}{:<18}{}",
+                    opcode.mnemonic(),
+                    format!("0x{:x}", opcode.encode()),
+                    opcode.encoding_form().as_str(),
+                    operands.join(", ")
+                );
+
+                println!("{}", line.trim_end());
+            }
+        }
+        IsaFormat::Json => {
+            let objects: Vec<String> = entries
+                .iter()
+                .map(|(opcode, operand_rules)| {
+                    let operands: Vec<String> = operand_rules
+                        .iter()
+                        .map(|rule| {
+                            let types: Vec<String> =
+                                rule.iter().map(|ot| format!("\"{}\"", ot.as_str())).collect();
+                            format!("[{}]", types.join(","))
+                        })
+                        .collect();
+
+                    format!(
+                        "{{\"mnemonic\":\"{}\",\"opcode\":{},\"encoding\":\"{}\",\"operands\":[{}]}}",
+                        opcode.mnemonic(),
+                        opcode.encode(),
+                        opcode.encoding_form().as_str(),
+                        operands.join(",")
+                    )
+                })
+                .collect();
+
+            println!("[{}]", objects.join(","));
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Prints each opcode that isn't the encoding of any instruction, which are the ones free for new instructions
+fn list_unused_opcodes() {
+    let used: Vec<u8> = Opcode::all().map(Opcode::encode).collect();
+
+    for opcode in (0..16).filter(|opcode| !used.contains(opcode)) {
+        println!("0x{:x}", opcode);
+    }
+}
+
+/// The program assembled by `--self-test`, which uses every instruction, constants, ports, labels before and after
+/// they are defined, and data worked out from an expression
+const SELF_TEST_SOURCE: &str = "\
+; Counts down from the value read from port 1, writing each step to port 2
+.set STEP, 1
+.port switches, 1
+.port leds, 2
+    in r1, switches
+    ldi r2, STEP
+    ldi r3, 0x0f
+loop:
+    and r1, r3
+    out r1, leds
+    jz r1, done
+    sub r1, r2
+    jlt r1, done
+    j loop
+done:
+    ldi r4, 0
+    add r4, r2
+    or r4, r3
+    xor r4, r2
+    inv r4
+    sl r4, r2
+    sr r4, r2
+    nop
+end:
+    j end
+    .byte STEP, loop * 2
+";
+
+/// What [`SELF_TEST_SOURCE`] assembles to, worked out by hand from the encoding of each instruction
+const SELF_TEST_EXPECTED: &[u8] = &[
+    0xb1, 0x10, // in r1, 1
+    0x22, 0x01, // ldi r2, 1
+    0x23, 0x0f, // ldi r3, 15
+    0x51, 0x30, // and r1, r3
+    0xc1, 0x20, // out r1, 2
+    0xd1, 0x09, // jz r1, 9
+    0x31, 0x20, // sub r1, r2
+    0xe1, 0x09, // jlt r1, 9
+    0xf0, 0x03, // j 3
+    0x24, 0x00, // ldi r4, 0
+    0x14, 0x20, // add r4, r2
+    0x64, 0x30, // or r4, r3
+    0x84, 0x20, // xor r4, r2
+    0x74, 0x00, // inv r4
+    0xa4, 0x20, // sl r4, r2
+    0x94, 0x20, // sr r4, r2
+    0x00, 0x00, // nop
+    0xf0, 0x11, // j 17
+    0x01, 0x06, // .byte 1, 6
+];
+
+/// Assembles [`SELF_TEST_SOURCE`] with the default options and compares it against [`SELF_TEST_EXPECTED`], without
+/// touching the filesystem
+fn self_test(emitter: &mut TerminalEmitter) -> ExitCode {
+    let source_manager = SourceManager::new(SELF_TEST_SOURCE, String::from("<self-test>"));
+
+    let bytes = match assemble(&source_manager, &AssembleOptions::default()) {
+        Ok(result) => result.bytes,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                emitter.emit(diagnostic, &source_manager);
+            }
+
+            println!("FAIL: the built-in program did not assemble");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if bytes == SELF_TEST_EXPECTED {
+        println!("PASS");
+        return ExitCode::SUCCESS;
+    }
+
+    match bytes.iter().zip(SELF_TEST_EXPECTED).position(|(actual, expected)| actual != expected) {
+        Some(index) => println!(
+            "FAIL: byte 0x{:02x} is 0x{:02x}, expected 0x{:02x}",
+            index, bytes[index], SELF_TEST_EXPECTED[index]
+        ),
+        None => println!(
+            "FAIL: the output is {} bytes long, expected {}",
+            bytes.len(),
+            SELF_TEST_EXPECTED.len()
+        ),
+    }
+
+    ExitCode::FAILURE
+}
@@ -1,71 +1,475 @@
-use std::{collections::HashMap, iter::Peekable, slice::Iter};
+use std::{
+    collections::HashMap,
+    iter::{Filter, Peekable},
+    slice::Iter,
+};
 
 use crate::{
-    ast::{Instruction, Item, Opcode, Operand, Register, LabelId},
-    instructions::{rules::*, OperandType},
+    ast::{
+        Assertion, BinaryOperator, Data, DataValue, DataWidth, Expression, Instruction, Item, ItemComments, MetaEntry, MetaValue, Opcode,
+        Operand, Overflow, Register, LabelId,
+    },
+    instructions::{
+        rules::{self, OperandRules},
+        IntegerRole, OperandType,
+    },
     lexer::{Token, TokenType, Span},
+    lints::Lint,
     sources::SourceManager,
 };
 
+#[derive(Debug)]
 pub enum ParseError {
     UnexpectedToken(TokenType, Token),
-    MissingToken(TokenType),
+    /// The end of the file where a token was expected, along with the empty span just past the last token
+    MissingToken(TokenType, Span),
     InvalidInstruction(Token),
     ExpectedInstructionBeforeLabel(Token),
     DuplicateLabel(Token),
+    /// A label defined in the source that was already given a value with [`Parser::seed_label`]
+    SeededLabelDefined(Token),
     ExpectedInstruction(Token),
+    /// A comma where a statement should start, such as one left behind when an operand was moved to the line above
+    CommaAtStatementStart(Token),
     ExpectedNoOperands(Token),
-    ExpectedOperandFoundEOF(Token),
-    ExpectedOperand(Token, String),
+    /// The instruction, the index of the missing operand, and where it should have been
+    MissingOperand(Token, usize, Span),
+    /// The instruction, the index of the operand that was expected, and the comma found in its place
+    UnexpectedComma(Token, usize, Token),
+    /// The instruction, the index of the operand, what was found instead, and a description of what was expected
+    ExpectedOperand(Token, usize, Token, String),
+    /// Like [`ParseError::ExpectedOperand`], for a register name where a register isn't allowed, when no label has
+    /// that name
+    RegisterAsLabel(Token, usize, Token, String),
+    /// Like [`ParseError::ExpectedOperand`], for a name that isn't a constant where an integer is expected but a label
+    /// isn't allowed, such as `ldi r0, loop`
+    LabelNotAllowed(Token, usize, Token, String),
     ExpectedRegister(Token),
-    IntegerOutOfRange(Token),
+    /// An identifier shaped like a register, such as `r99`, that isn't one
+    NoSuchRegister(Token),
+    /// The instruction and the integer given as the amount to shift by, which has to be in a register
+    ImmediateShift(Token, Token),
+    IntegerOutOfRange(Token, IntegerRole),
+    /// A constant used where its value is out of range, along with that value
+    ConstantOutOfRange(Token, i64, IntegerRole),
+    /// Anything other than a `.set` or `.port` in a constants file
+    NotAConstantDefinition(Token),
+    /// An expression, such as `BASE + 1`, whose value is out of range
+    ExpressionOutOfRange(Span, i64, IntegerRole),
+    InvalidDirective(Token),
+    DuplicatePort(Token),
+    UnknownPort(Token),
+    /// A `.set` whose name is, or looks like, a register
+    InvalidConstantName(Token),
+    /// An identifier used where only an integer or constant is allowed, that isn't a constant
+    UnknownConstant(Token),
+    /// A label given to both `.global` and `.local`, at the second of them
+    ConflictingVisibility(Token),
+    /// A `+` or `-` that isn't directly followed by an integer
+    SignWithoutInteger(Token),
+    /// A `.meta` key given a value a second time
+    DuplicateMetaKey(Token),
+    /// Something other than an integer, constant or label where an expression needs a value
+    ExpectedValue(Token),
+    /// A `(` in an expression that isn't closed before the end of the expression
+    UnclosedParen(Token),
+    /// A `)` at the end of an expression with no `(` before it
+    UnmatchedParen(Token),
+    /// An operation in an expression whose result doesn't fit in 64 bits
+    ExpressionOverflow(Span),
+}
+
+impl ParseError {
+    /// Where in the source the error is reported, the same place its diagnostic points to
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::MissingToken(_, span)
+            | Self::MissingOperand(_, _, span)
+            | Self::ExpressionOutOfRange(span, _, _)
+            | Self::ExpressionOverflow(span) => Some(*span),
+            Self::UnexpectedComma(_, _, t)
+            | Self::ExpectedOperand(_, _, t, _)
+            | Self::RegisterAsLabel(_, _, t, _)
+            | Self::LabelNotAllowed(_, _, t, _)
+            | Self::ImmediateShift(_, t)
+            | Self::UnexpectedToken(_, t)
+            | Self::IntegerOutOfRange(t, _)
+            | Self::ConstantOutOfRange(t, _, _)
+            | Self::InvalidInstruction(t)
+            | Self::ExpectedInstructionBeforeLabel(t)
+            | Self::DuplicateLabel(t)
+            | Self::SeededLabelDefined(t)
+            | Self::ExpectedInstruction(t)
+            | Self::CommaAtStatementStart(t)
+            | Self::ExpectedNoOperands(t)
+            | Self::ExpectedRegister(t)
+            | Self::NoSuchRegister(t)
+            | Self::InvalidDirective(t)
+            | Self::DuplicatePort(t)
+            | Self::UnknownPort(t)
+            | Self::InvalidConstantName(t)
+            | Self::UnknownConstant(t)
+            | Self::ConflictingVisibility(t)
+            | Self::SignWithoutInteger(t)
+            | Self::DuplicateMetaKey(t)
+            | Self::ExpectedValue(t)
+            | Self::UnclosedParen(t)
+            | Self::UnmatchedParen(t)
+            | Self::NotAConstantDefinition(t) => Some(t.span),
+        }
+    }
+}
+
+impl From<Overflow> for ParseError {
+    fn from(overflow: Overflow) -> Self {
+        Self::ExpressionOverflow(overflow.0)
+    }
+}
+
+type TokensWithoutComments<'a> = Filter<Iter<'a, Token>, fn(&&Token) -> bool>;
+
+/// Something the parser accepted, but that may not be what was meant
+#[derive(Debug, Clone)]
+pub enum ParseWarning {
+    /// An immediate of 128 to 255 that was taken as the given negative value, when relaxing
+    RelaxedImmediate(Token, i8),
+    /// A `.set` constant that is never used, at the name in its first `.set`
+    UnusedConstant(Token),
+    /// A `.port` that is never used, at the name in its definition
+    UnusedPort(Token),
+    /// A label given a visibility that is never defined, at the name in the first `.global` or `.local` of it
+    UndefinedVisibility(Token, Visibility),
+}
+
+impl ParseWarning {
+    /// Where in the source the warning is reported
+    pub fn span(&self) -> Span {
+        match self {
+            Self::RelaxedImmediate(t, _)
+            | Self::UnusedConstant(t)
+            | Self::UnusedPort(t)
+            | Self::UndefinedVisibility(t, _) => t.span,
+        }
+    }
+
+    /// The lint that decides whether the warning is reported, if it can be turned off
+    pub fn lint(&self) -> Option<Lint> {
+        match self {
+            Self::RelaxedImmediate(..) | Self::UndefinedVisibility(..) => None,
+            Self::UnusedConstant(_) => Some(Lint::UnusedConstant),
+            Self::UnusedPort(_) => Some(Lint::UnusedPort),
+        }
+    }
 }
 
-pub(crate) struct Parser<'a, 'b, 'c> {
-    tokens_iter: Peekable<Iter<'a, Token>>,
+pub struct Parser<'a, 'b, 'c> {
+    /// Every token but comments, which are kept separately since they can only end a line
+    tokens_iter: Peekable<TokensWithoutComments<'a>>,
+    /// The span of every comment, in source order
+    comments: Vec<Span>,
     source_manager: &'b SourceManager<'c>,
-    parse_rules: HashMap<Opcode, &'static [&'static [OperandType]]>,
+    parse_rules: HashMap<Opcode, OperandRules>,
     label_manager: LabelManager,
+    ports: HashMap<String, NamedValue>,
+    /// The current value of every `.set` constant, which changes as parsing moves through the source
+    constants: HashMap<String, NamedValue>,
+    /// Whether to accept some out of range values that have a clear meaning, warning about them
+    relax: bool,
+    /// Whether registers must be written with a `$`, so that a bare identifier is never a register
+    strict_registers: bool,
+    /// Whether to log each rule applied, and what it produced, to stderr
+    trace: bool,
+    /// Whether to attach every comment to an item when parsing
+    keep_comments: bool,
+    /// How many traced rules are being applied, for indenting the trace
+    trace_depth: usize,
+    warnings: Vec<ParseWarning>,
+    /// Every `.meta` entry, in source order
+    metadata: Vec<MetaEntry>,
+    /// Every reference to a label named like a register, which is an error if no such label is ever defined
+    register_named_references: Vec<(LabelId, ParseError)>,
+    /// The name in every `.global` and `.local`, which should be a label defined somewhere in the source
+    visibility_names: Vec<(LabelId, Token, Visibility)>,
     just_saw_label: bool,
+    /// The span of the most recently consumed token
+    last_span: Span,
 }
 
 impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
-    pub fn new(tokens: &'a Vec<Token>, source_manager: &'b SourceManager<'c>) -> Self {
-        let mut parse_rules = HashMap::new();
-
-        parse_rules.insert(Opcode::Nop, NOP_RULES);
-        parse_rules.insert(Opcode::Add, ADD_RULES);
-        parse_rules.insert(Opcode::Ldi, LDI_RULES);
-        parse_rules.insert(Opcode::Sub, SUB_RULES);
-        parse_rules.insert(Opcode::And, AND_RULES);
-        parse_rules.insert(Opcode::Or, OR_RULES);
-        parse_rules.insert(Opcode::Inv, INV_RULES);
-        parse_rules.insert(Opcode::Xor, XOR_RULES);
-        parse_rules.insert(Opcode::Sr, SR_RULES);
-        parse_rules.insert(Opcode::Sl, SL_RULES);
-        parse_rules.insert(Opcode::In, IN_RULES);
-        parse_rules.insert(Opcode::Out, OUT_RULES);
-        parse_rules.insert(Opcode::Jz, JZ_RULES);
-        parse_rules.insert(Opcode::Jlt, JLT_RULES);
-        parse_rules.insert(Opcode::J, J_RULES);
+    pub fn new(tokens: &'a [Token], source_manager: &'b SourceManager<'c>) -> Self {
+        let parse_rules = rules::parse_rules();
+
+        // Catch an opcode that was added without rules here, rather than when someone first uses it
+        debug_assert!(
+            Opcode::all().all(|opcode| parse_rules.contains_key(&opcode)),
+            "Internal Assembler Error: Every opcode must have operand rules registered"
+        );
+        debug_assert!(
+            Opcode::all().all(|opcode| parse_rules[&opcode].len() == opcode.operand_access().len()),
+            "Internal Assembler Error: Every operand must have its access described"
+        );
 
         Self {
-            tokens_iter: tokens.iter().peekable(),
+            tokens_iter: tokens
+                .iter()
+                .filter((|t| t.tt != TokenType::Comment) as fn(&&Token) -> bool)
+                .peekable(),
+            comments: tokens
+                .iter()
+                .filter(|t| t.tt == TokenType::Comment)
+                .map(|t| t.span)
+                .collect(),
             source_manager,
             parse_rules,
             label_manager: LabelManager::new(),
+            ports: HashMap::new(),
+            constants: HashMap::new(),
+            relax: false,
+            strict_registers: false,
+            trace: false,
+            trace_depth: 0,
+            keep_comments: false,
+            warnings: Vec::new(),
+            metadata: Vec::new(),
+            register_named_references: Vec::new(),
+            visibility_names: Vec::new(),
             just_saw_label: false,
+            last_span: Span { index: 0, len: 0 },
         }
     }
 
-    pub fn parse(mut self) -> Result<(Vec<Item>, LabelManager), ParseError> {
+    /// Gives a label a value before parsing, so it can be jumped to without being defined in the source
+    ///
+    /// The value is an instruction index, and must be a valid jump destination
+    pub fn seed_label(&mut self, name: &str, value: i8) {
+        let label_id = self.label_manager.get_or_insert_reference(name);
+
+        self.label_manager.set_value_of(label_id, value).unwrap();
+    }
+
+    /// Defines constants and ports before parsing, such as those from a constants file
+    ///
+    /// The source may set the constants again, but defining one of the ports again is an error
+    pub fn seed_constants(&mut self, definitions: &ConstantDefinitions) {
+        for (name, value) in definitions.constants.iter() {
+            self.constants.insert(name.clone(), NamedValue::new(*value, None));
+        }
+
+        for (name, value) in definitions.ports.iter() {
+            self.ports.insert(name.clone(), NamedValue::new(*value, None));
+        }
+    }
+
+    /// Accepts immediates of 128 to 255 as the signed byte with the same bits, with a warning, rather than an error
+    pub fn set_relax(&mut self, relax: bool) {
+        self.relax = relax;
+    }
+
+    /// Only takes [`TokenType::Register`] tokens as registers, so that names like `r0` are always labels, ports or
+    /// constants
+    ///
+    /// The lexer must have been asked to lex the register sigil for registers to be usable at all
+    pub fn set_strict_registers(&mut self, strict_registers: bool) {
+        self.strict_registers = strict_registers;
+    }
+
+    /// Logs the start and result of parsing each line, instruction and operand to stderr, for debugging the parser
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Attaches every comment to the item it belongs with when parsing, rather than only the comment at the end of an
+    /// instruction's line
+    pub fn set_keep_comments(&mut self, keep_comments: bool) {
+        self.keep_comments = keep_comments;
+    }
+
+    /// Parses the whole source
+    pub fn parse(mut self) -> Result<ParsedProgram, ParseError> {
         let mut items = Vec::new();
 
         while self.tokens_iter.peek().is_some() {
-            items.extend(self.parse_line()?);
+            items.extend(self.traced("parse_line", Self::parse_line)?);
+        }
+
+        let (comments, end_comments) = if self.keep_comments {
+            self.attach_comments(&items)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        // A label can share a name with a register, so this can only be told apart from a mistake once every label
+        // has been seen
+        if let Some((_, error)) = self.register_named_references.into_iter().find(|(label_id, _)| {
+            self.label_manager.get_span_of(*label_id).is_none()
+                && self.label_manager.get_value_of(*label_id).is_none()
+        }) {
+            return Err(error);
+        }
+
+        // Without a definition, `.global` or `.local` only makes up a label that nothing can use
+        let mut warned_labels = Vec::new();
+
+        for (label_id, token, visibility) in self.visibility_names.iter() {
+            if self.label_manager.get_span_of(*label_id).is_none()
+                && self.label_manager.get_value_of(*label_id).is_none()
+                && !warned_labels.contains(label_id)
+            {
+                warned_labels.push(*label_id);
+                self.warnings.push(ParseWarning::UndefinedVisibility(*token, *visibility));
+            }
+        }
+
+        let mut unused: Vec<ParseWarning> = self
+            .constants
+            .values()
+            .filter(|constant| !constant.used)
+            .filter_map(|constant| constant.definition.map(ParseWarning::UnusedConstant))
+            .chain(
+                self.ports
+                    .values()
+                    .filter(|port| !port.used)
+                    .filter_map(|port| port.definition.map(ParseWarning::UnusedPort)),
+            )
+            .collect();
+
+        // In source order, rather than the order of the maps
+        unused.sort_by_key(|warning| warning.span().index);
+        self.warnings.extend(unused);
+
+        Ok(ParsedProgram {
+            items,
+            label_manager: self.label_manager,
+            warnings: self.warnings,
+            metadata: self.metadata,
+            comments,
+            end_comments,
+        })
+    }
+
+    /// The comments around each item, and those after the last item that aren't on its line
+    ///
+    /// A comment is trailing if it is on the line the item before it ends on, and otherwise leads the item after it
+    fn attach_comments(&self, items: &[Item]) -> (Vec<ItemComments>, Vec<Span>) {
+        let mut attached = vec![ItemComments::default(); items.len()];
+        let mut comments = self.comments.iter().copied().peekable();
+        let mut before: Vec<Span> = Vec::new();
+        let mut previous: Option<(usize, Span)> = None;
+
+        let is_on_line_of = |span: Span, comment: Span| {
+            let end = span.index + span.len;
+            let between = Span {
+                index: end,
+                len: comment.index.saturating_sub(end),
+            };
+
+            !self.source_manager.get_span(between).unwrap().contains('\n')
+        };
+
+        for (item_index, item) in items.iter().enumerate() {
+            let span = match item {
+                Item::Label(label_id) => self
+                    .label_manager
+                    .get_span_of(*label_id)
+                    .expect("Internal Assembler Error: Label item without a definition"),
+                Item::Instruction(_, span, _)
+                | Item::Data(_, span)
+                | Item::Checksum(span)
+                | Item::Assert(_, span) => *span,
+            };
+
+            before.clear();
+
+            while let Some(comment) = comments.next_if(|comment| comment.index < span.index) {
+                before.push(comment);
+            }
+
+            let mut leading = before.as_slice();
+
+            if let (Some((previous_index, previous_span)), Some(&first)) = (previous, leading.first()) {
+                if is_on_line_of(previous_span, first) {
+                    attached[previous_index].trailing = Some(first);
+                    leading = &leading[1..];
+                }
+            }
+
+            attached[item_index].leading = leading.to_vec();
+            previous = Some((item_index, span));
+        }
+
+        let mut end_comments: Vec<Span> = comments.collect();
+
+        if let (Some((previous_index, previous_span)), Some(&first)) = (previous, end_comments.first()) {
+            if is_on_line_of(previous_span, first) {
+                attached[previous_index].trailing = Some(first);
+                end_comments.remove(0);
+            }
         }
 
-        Ok((items, self.label_manager))
+        (attached, end_comments)
+    }
+
+    /// Applies `rule`, logging the token it starts at and its result if tracing is enabled
+    fn traced<T: std::fmt::Debug>(
+        &mut self,
+        name: &str,
+        rule: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if !self.trace {
+            return rule(self);
+        }
+
+        let indent = "  ".repeat(self.trace_depth);
+        let start = match self.tokens_iter.peek() {
+            Some(token) => {
+                let text = self.source_manager.get_span(token.span).unwrap();
+                let (_, line, column) = self.source_manager.get_span_line(token.span).unwrap();
+
+                format!(
+                    "{:?} {:?} at {}:{} (bytes {}+{})",
+                    token.tt, text, line, column, token.span.index, token.span.len
+                )
+            }
+            None => String::from("end of file"),
+        };
+        eprintln!("trace: {indent}> {name} {start}");
+
+        self.trace_depth += 1;
+        let result = rule(self);
+        self.trace_depth -= 1;
+
+        eprintln!("trace: {indent}< {name} -> {result:?}");
+
+        result
+    }
+
+    /// Parses a source made of only `.set` and `.port` directives, returning every constant and port defined, including
+    /// any seeded ones
+    pub fn parse_constants(mut self) -> Result<ConstantDefinitions, ParseError> {
+        while let Some(&&next_token) = self.tokens_iter.peek() {
+            if next_token.tt == TokenType::Newline {
+                self.next_token();
+                continue;
+            }
+
+            if !self.is_peek_directive(".set") && !self.is_peek_directive(".port") {
+                return Err(ParseError::NotAConstantDefinition(next_token));
+            }
+
+            self.parse_directive()?;
+            self.consume_or_eof(TokenType::Newline)?;
+        }
+
+        let values_of = |map: HashMap<String, NamedValue>| {
+            let mut values: Vec<(String, i64)> = map.into_iter().map(|(name, named)| (name, named.value)).collect();
+            values.sort();
+            values
+        };
+
+        Ok(ConstantDefinitions {
+            constants: values_of(self.constants),
+            ports: values_of(self.ports),
+        })
     }
 
     fn parse_line(&mut self) -> Result<Vec<Item>, ParseError> {
@@ -75,10 +479,23 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
             let mut should_parse_instruction = true;
 
             if next_token.tt == TokenType::Newline {
-                self.tokens_iter.next();
+                self.next_token();
                 return Ok(Vec::new());
             }
 
+            if next_token.tt == TokenType::Directive && !self.is_peek_memory_directive() {
+                // An assertion is kept in the program so it can be checked once labels have values
+                if self.is_peek_directive(".assert") {
+                    items.push(self.parse_assert_directive()?);
+                } else {
+                    self.parse_directive()?;
+                }
+
+                self.consume_or_eof(TokenType::Newline)?;
+
+                return Ok(items);
+            }
+
             if next_token.tt == TokenType::Label {
                 if self.just_saw_label {
                     return Err(ParseError::ExpectedInstructionBeforeLabel(next_token));
@@ -91,6 +508,11 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
 
                 let label_id = self.label_manager.get_id_of(label_text);
 
+                // Only seeded labels have a value before code is generated
+                if label_id.is_some_and(|id| self.label_manager.get_value_of(id).is_some()) {
+                    return Err(ParseError::SeededLabelDefined(next_token));
+                }
+
                 if label_id.is_some() && self.label_manager.get_span_of(label_id.unwrap()).is_some() {
                     return Err(ParseError::DuplicateLabel(next_token));
                 } else if let Some(label_id) = self.label_manager.get_id_of(label_text) {
@@ -108,13 +530,27 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
                 }
 
                 // Consume the label token, we don't need it anymore
-                self.tokens_iter.next().unwrap();
+                self.next_token().unwrap();
                 should_parse_instruction =
                     self.tokens_iter.peek().is_some() && !self.is_peek_token(TokenType::Newline);
             }
 
             if should_parse_instruction {
-                items.push(Item::Instruction(self.parse_instruction()?));
+                if let Some(width) = self.peek_data_width() {
+                    let (data, span) = self.parse_data(width)?;
+                    items.push(Item::Data(data, span));
+                } else if self.is_peek_directive(".space") {
+                    let (data, span) = self.parse_space_directive()?;
+                    items.push(Item::Data(data, span));
+                } else if self.is_peek_directive(".checksum_here") {
+                    let directive_token = self.next_token().unwrap();
+                    items.push(Item::Checksum(directive_token.span));
+                } else {
+                    let (instruction, span) = self.traced("parse_instruction", Self::parse_instruction)?;
+                    let comment = self.trailing_comment(span);
+                    items.push(Item::Instruction(instruction, span, comment));
+                }
+
                 self.just_saw_label = false;
             }
 
@@ -126,8 +562,486 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
         }
     }
 
-    fn parse_instruction(&mut self) -> Result<Instruction, ParseError> {
-        if let Some(&next_token) = self.tokens_iter.next() {
+    fn parse_directive(&mut self) -> Result<(), ParseError> {
+        let directive_token = self.next_token().unwrap();
+        let text = self
+            .source_manager
+            .get_span(directive_token.span)
+            .unwrap()
+            .to_lowercase();
+
+        match text.as_str() {
+            ".port" => self.parse_port_directive(),
+            ".set" => self.parse_set_directive(),
+            ".global" => self.parse_visibility_directive(Visibility::Global),
+            ".local" => self.parse_visibility_directive(Visibility::Local),
+            ".meta" => self.parse_meta_directive(directive_token),
+            _ => Err(ParseError::InvalidDirective(directive_token)),
+        }
+    }
+
+    /// Whether the next token starts a data directive, and if so, how wide its values are
+    ///
+    /// `db` and `dw` are accepted as aliases of `.byte` and `.word` for those used to other assemblers
+    fn peek_data_width(&mut self) -> Option<DataWidth> {
+        let next_token = **self.tokens_iter.peek()?;
+
+        if next_token.tt != TokenType::Directive && next_token.tt != TokenType::Identifier {
+            return None;
+        }
+
+        let text = self.source_manager.get_span(next_token.span).unwrap();
+
+        match text.to_lowercase().as_str() {
+            ".byte" | "db" => Some(DataWidth::Byte),
+            ".word" | "dw" => Some(DataWidth::Word),
+            _ => None,
+        }
+    }
+
+    /// Parses a data directive and its comma-separated integer values
+    fn parse_data(&mut self, width: DataWidth) -> Result<(Data, Span), ParseError> {
+        let directive_token = self.next_token().unwrap();
+        let mut values = Vec::new();
+
+        loop {
+            let expression = self.parse_expression()?;
+            values.push(self.data_value_of(expression, width.integer_role())?);
+
+            if self.is_peek_token(TokenType::Comma) {
+                self.next_token();
+            } else {
+                break;
+            }
+        }
+
+        let span = directive_token.span.merge(self.last_span);
+
+        Ok((Data { width, values }, span))
+    }
+
+    /// The value of an element of a data directive, if it is in range for `role`
+    ///
+    /// Labels don't have values until the whole program has been parsed, so an element that refers to one is left to
+    /// be worked out when generating
+    fn data_value_of(&self, expression: Expression, role: IntegerRole) -> Result<DataValue, ParseError> {
+        // Labels are told apart from an overflow by giving no error of their own
+        let value = match expression.evaluate(&mut |_, _| Err(None)) {
+            Ok(value) => value,
+            Err(None) => return Ok(DataValue::Deferred(expression)),
+            Err(Some(Overflow(span))) => return Err(ParseError::ExpressionOverflow(span)),
+        };
+
+        if role.range().contains(&value) {
+            // Negative values are stored as their two's complement bit pattern
+            return Ok(DataValue::Known(value as u16));
+        }
+
+        let span = expression.span();
+        let text = self.source_manager.get_span(span).unwrap();
+
+        // A number written out is reported as it is, and anything else along with the value it works out to
+        if Self::parse_integer_literal(text).is_some() {
+            Err(ParseError::IntegerOutOfRange(
+                Token {
+                    tt: TokenType::Integer,
+                    span,
+                },
+                role,
+            ))
+        } else {
+            Err(ParseError::ExpressionOutOfRange(span, value, role))
+        }
+    }
+
+    /// Whether the next token is the directive `name`
+    fn is_peek_directive(&mut self, name: &str) -> bool {
+        self.tokens_iter.peek().is_some_and(|token| {
+            token.tt == TokenType::Directive
+                && self
+                    .source_manager
+                    .get_span(token.span)
+                    .unwrap()
+                    .eq_ignore_ascii_case(name)
+        })
+    }
+
+    /// Whether the next token is a directive that takes up memory, like an instruction, rather than changing how the
+    /// rest of the program is parsed
+    fn is_peek_memory_directive(&mut self) -> bool {
+        self.peek_data_width().is_some()
+            || self.is_peek_directive(".space")
+            || self.is_peek_directive(".checksum_here")
+    }
+
+    /// Parses `.space <integer>`, which is the same as that many zero bytes of data
+    fn parse_space_directive(&mut self) -> Result<(Data, Span), ParseError> {
+        let directive_token = self.next_token().unwrap();
+        let size = self.expect_integer(IntegerRole::Size)?;
+
+        let span = directive_token.span.merge(self.last_span);
+
+        Ok((
+            Data {
+                width: DataWidth::Byte,
+                values: vec![DataValue::Known(0); size as usize],
+            },
+            span,
+        ))
+    }
+
+    /// Parses the operands of `.port <name>, <integer>`, which names a source or sink
+    ///
+    /// Ports must be defined before they are used
+    fn parse_port_directive(&mut self) -> Result<(), ParseError> {
+        let name_token = self.expect_token(TokenType::Identifier)?;
+        self.expect_token(TokenType::Comma)?;
+        let value = self.expect_integer(IntegerRole::Port)?;
+
+        let name = self.source_manager.get_span(name_token.span).unwrap();
+
+        if self.ports.contains_key(name) {
+            return Err(ParseError::DuplicatePort(name_token));
+        }
+
+        self.ports.insert(String::from(name), NamedValue::new(value, Some(name_token)));
+
+        Ok(())
+    }
+
+    /// Parses the operands of `.set <name>, <integer>`, which names a constant
+    ///
+    /// Unlike a label, a constant may be set again, and each use takes the value of the last `.set` before it in the
+    /// source, so constants must be set before they are used
+    fn parse_set_directive(&mut self) -> Result<(), ParseError> {
+        let name_token = self.expect_token(TokenType::Identifier)?;
+        self.expect_token(TokenType::Comma)?;
+        let value = self.expect_integer(IntegerRole::Constant)?;
+
+        let name = self.source_manager.get_span(name_token.span).unwrap();
+
+        if Self::looks_like_register(name) {
+            return Err(ParseError::InvalidConstantName(name_token));
+        }
+
+        // Setting a constant again doesn't use it, so a constant that is only ever set is still unused
+        self.constants
+            .entry(String::from(name))
+            .and_modify(|constant| constant.value = value)
+            .or_insert_with(|| NamedValue::new(value, Some(name_token)));
+
+        Ok(())
+    }
+
+    /// Parses the operands of `.meta <key> <value>`, where the value is an integer, a constant, or a string
+    ///
+    /// Giving the same key twice is an error, rather than the last value winning, since it is almost certainly a
+    /// copy-paste mistake
+    fn parse_meta_directive(&mut self, directive_token: Token) -> Result<(), ParseError> {
+        let key_token = self.expect_token(TokenType::Identifier)?;
+        let key = self.source_manager.get_span(key_token.span).unwrap();
+
+        if self.metadata.iter().any(|entry| entry.key == key) {
+            return Err(ParseError::DuplicateMetaKey(key_token));
+        }
+
+        let value = if self.is_peek_token(TokenType::String) {
+            let string_token = self.next_token().unwrap();
+            let text = self.source_manager.get_span(string_token.span).unwrap();
+
+            MetaValue::String(Self::unescape_string(text))
+        } else {
+            MetaValue::Integer(self.expect_integer(IntegerRole::Constant)?)
+        };
+
+        self.metadata.push(MetaEntry {
+            key: String::from(key),
+            value,
+            span: directive_token.span.merge(self.last_span),
+        });
+
+        Ok(())
+    }
+
+    /// The text of a string token without its quotes, with each `\` replaced by the character after it
+    fn unescape_string(text: &str) -> String {
+        let mut unescaped = String::with_capacity(text.len());
+        let mut chars = text[1..text.len() - 1].chars();
+
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                unescaped.extend(chars.next());
+            } else {
+                unescaped.push(c);
+            }
+        }
+
+        unescaped
+    }
+
+    /// Parses `.assert <expression>` with an optional `, "<message>"` after it
+    fn parse_assert_directive(&mut self) -> Result<Item, ParseError> {
+        let directive_token = self.next_token().unwrap();
+        let condition = self.parse_expression()?;
+
+        let message = if self.is_peek_token(TokenType::Comma) {
+            self.next_token();
+
+            let string_token = self.expect_token(TokenType::String)?;
+            let text = self.source_manager.get_span(string_token.span).unwrap();
+
+            Some(Self::unescape_string(text))
+        } else {
+            None
+        };
+
+        let span = directive_token.span.merge(self.last_span);
+
+        Ok(Item::Assert(Assertion { condition, message }, span))
+    }
+
+    /// Parses values joined by operators, such as `end - start == 4`
+    fn parse_expression(&mut self) -> Result<Expression, ParseError> {
+        let expression = self.parse_binary(0)?;
+
+        // Every `(` inside has been matched, so a `)` here has nothing to close
+        if let Some(&&token) = self.tokens_iter.peek() {
+            if token.tt == TokenType::RightParen {
+                return Err(ParseError::UnmatchedParen(token));
+            }
+        }
+
+        Ok(expression)
+    }
+
+    /// Parses operands joined by operators of `precedence`, from left to right, where each operand is made of
+    /// operators that bind more tightly
+    fn parse_binary(&mut self, precedence: u8) -> Result<Expression, ParseError> {
+        if precedence > BinaryOperator::MAX_PRECEDENCE {
+            return self.parse_value();
+        }
+
+        let mut expression = self.parse_binary(precedence + 1)?;
+
+        while let Some(operator) = self
+            .peek_binary_operator()
+            .filter(|operator| operator.precedence() == precedence)
+        {
+            self.next_token();
+
+            let right = self.parse_binary(precedence + 1)?;
+
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(right));
+        }
+
+        Ok(expression)
+    }
+
+    fn peek_binary_operator(&mut self) -> Option<BinaryOperator> {
+        match self.tokens_iter.peek()?.tt {
+            TokenType::Plus => Some(BinaryOperator::Add),
+            TokenType::Minus => Some(BinaryOperator::Subtract),
+            TokenType::Star => Some(BinaryOperator::Multiply),
+            TokenType::Ampersand => Some(BinaryOperator::BitAnd),
+            TokenType::Pipe => Some(BinaryOperator::BitOr),
+            TokenType::EqualEqual => Some(BinaryOperator::Equal),
+            TokenType::BangEqual => Some(BinaryOperator::NotEqual),
+            TokenType::Less => Some(BinaryOperator::Less),
+            TokenType::LessEqual => Some(BinaryOperator::LessEqual),
+            TokenType::Greater => Some(BinaryOperator::Greater),
+            TokenType::GreaterEqual => Some(BinaryOperator::GreaterEqual),
+            _ => None,
+        }
+    }
+
+    /// Parses an integer, a constant, which is replaced by its value, a label, or an expression in parentheses
+    fn parse_value(&mut self) -> Result<Expression, ParseError> {
+        let token = self
+            .next_token()
+            .ok_or(ParseError::MissingToken(TokenType::Integer, self.span_after_last_token()))?;
+
+        match token.tt {
+            TokenType::LeftParen => {
+                let expression = self.parse_binary(0)?;
+
+                match self.next_token() {
+                    Some(close) if close.tt == TokenType::RightParen => Ok(expression),
+                    _ => Err(ParseError::UnclosedParen(token)),
+                }
+            }
+            TokenType::Integer | TokenType::Plus | TokenType::Minus => {
+                let token = if token.tt == TokenType::Integer {
+                    token
+                } else {
+                    self.expect_signed_integer(token)?
+                };
+
+                // Only the result is range-checked, by whatever it is used for
+                let text = self.source_manager.get_span(token.span).unwrap();
+                let value = Self::parse_integer_literal(text)
+                    .ok_or(ParseError::IntegerOutOfRange(token, IntegerRole::Constant))?;
+
+                Ok(Expression::Integer(value, token.span))
+            }
+            TokenType::Identifier => {
+                let text = self.source_manager.get_span(token.span).unwrap();
+
+                if self.constants.contains_key(text) {
+                    let value = self.integer_value_of(token, IntegerRole::Constant)?;
+
+                    Ok(Expression::Integer(value, token.span))
+                } else {
+                    let label_id = self.label_manager.get_or_insert_reference(text);
+
+                    Ok(Expression::Label(label_id, token.span))
+                }
+            }
+            _ => Err(ParseError::ExpectedValue(token)),
+        }
+    }
+
+    /// Parses the label name of `.global <name>` or `.local <name>`, which may come before or after the label is
+    /// defined
+    fn parse_visibility_directive(&mut self, visibility: Visibility) -> Result<(), ParseError> {
+        let name_token = self.expect_token(TokenType::Identifier)?;
+        let name = self.source_manager.get_span(name_token.span).unwrap();
+
+        let label_id = self.label_manager.get_or_insert(name);
+
+        self.label_manager
+            .set_visibility_of(label_id, visibility)
+            .map_err(|_| ParseError::ConflictingVisibility(name_token))?;
+        self.visibility_names.push((label_id, name_token, visibility));
+
+        Ok(())
+    }
+
+    /// Takes the next token as an integer, written out or as a constant, if it is in range for `role`
+    fn expect_integer(&mut self, role: IntegerRole) -> Result<i64, ParseError> {
+        let token = if self.is_peek_token(TokenType::Identifier) {
+            self.next_token().unwrap()
+        } else if self.is_peek_token(TokenType::Plus) || self.is_peek_token(TokenType::Minus) {
+            let sign = self.next_token().unwrap();
+            self.expect_signed_integer(sign)?
+        } else {
+            self.expect_token(TokenType::Integer)?
+        };
+
+        self.integer_value_of(token, role)
+    }
+
+    /// Takes the integer directly after `sign`, returning a single integer token covering both
+    fn expect_signed_integer(&mut self, sign: Token) -> Result<Token, ParseError> {
+        match self.tokens_iter.peek() {
+            Some(&&token)
+                if token.tt == TokenType::Integer && token.span.index == sign.span.index + sign.span.len =>
+            {
+                self.next_token();
+
+                Ok(Token {
+                    tt: TokenType::Integer,
+                    span: sign.span.merge(token.span),
+                })
+            }
+            _ => Err(ParseError::SignWithoutInteger(sign)),
+        }
+    }
+
+    /// Whether the next token starts an expression with an integer value, rather than being a label or register
+    fn is_peek_integer_expression(&mut self) -> bool {
+        match self.tokens_iter.peek() {
+            Some(token) => match token.tt {
+                TokenType::Integer | TokenType::Plus | TokenType::Minus | TokenType::LeftParen => true,
+                TokenType::Identifier => self
+                    .constants
+                    .contains_key(self.source_manager.get_span(token.span).unwrap()),
+                _ => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Checks the value of an operand worked out from the expression at `span` is in range for `role`
+    ///
+    /// When relaxing, an immediate of 128 to 255 is taken as the signed byte with the same bits, with a warning
+    fn operand_value_in_range(&mut self, value: i64, span: Span, role: IntegerRole) -> Result<i64, ParseError> {
+        let text = self.source_manager.get_span(span).unwrap();
+
+        if role.range().contains(&value) {
+            Ok(value)
+        } else if self.relax && role == IntegerRole::Immediate && (128..=255).contains(&value) {
+            let relaxed = value as u8 as i8;
+
+            self.warnings.push(ParseWarning::RelaxedImmediate(
+                Token {
+                    tt: TokenType::Integer,
+                    span,
+                },
+                relaxed,
+            ));
+
+            Ok(relaxed as i64)
+        } else if Self::parse_integer_literal(text).is_some() {
+            // A number written out is reported as it is, a constant by name, and anything else along with the value it
+            // works out to
+            Err(ParseError::IntegerOutOfRange(
+                Token {
+                    tt: TokenType::Integer,
+                    span,
+                },
+                role,
+            ))
+        } else if self.constants.contains_key(text) {
+            Err(ParseError::ConstantOutOfRange(
+                Token {
+                    tt: TokenType::Identifier,
+                    span,
+                },
+                value,
+                role,
+            ))
+        } else {
+            Err(ParseError::ExpressionOutOfRange(span, value, role))
+        }
+    }
+
+    /// The value of an integer or constant token, if it is in range for `role`
+    ///
+    /// When relaxing, an immediate of 128 to 255 is taken as the signed byte with the same bits, with a warning
+    fn integer_value_of(&mut self, token: Token, role: IntegerRole) -> Result<i64, ParseError> {
+        let text = self.source_manager.get_span(token.span).unwrap();
+
+        let value = if token.tt == TokenType::Identifier {
+            self.constants
+                .get_mut(text)
+                .ok_or(ParseError::UnknownConstant(token))?
+                .take()
+        } else {
+            Self::parse_integer_literal(text).ok_or(ParseError::IntegerOutOfRange(token, role))?
+        };
+
+        if role.range().contains(&value) {
+            Ok(value)
+        } else if self.relax && role == IntegerRole::Immediate && (128..=255).contains(&value) {
+            let relaxed = value as u8 as i8;
+
+            self.warnings
+                .push(ParseWarning::RelaxedImmediate(token, relaxed));
+
+            Ok(relaxed as i64)
+        } else if token.tt == TokenType::Identifier {
+            Err(ParseError::ConstantOutOfRange(token, value, role))
+        } else {
+            Err(ParseError::IntegerOutOfRange(token, role))
+        }
+    }
+
+    fn parse_instruction(&mut self) -> Result<(Instruction, Span), ParseError> {
+        if let Some(next_token) = self.next_token() {
+            if next_token.tt == TokenType::Comma {
+                return Err(ParseError::CommaAtStatementStart(next_token));
+            }
+
             if next_token.tt != TokenType::Identifier {
                 return Err(ParseError::ExpectedInstruction(next_token));
             }
@@ -144,26 +1058,44 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
                 if rules.is_empty() {
                     if self.tokens_iter.peek().is_none() || self.is_peek_token(TokenType::Newline) {
                         // All good
-                        Ok(Instruction::NoOperand(opcode))
+                        Ok((Instruction::NoOperand(opcode), next_token.span))
                     } else {
                         Err(ParseError::ExpectedNoOperands(
-                            *self.tokens_iter.next().unwrap(),
+                            self.next_token().unwrap(),
                         ))
                     }
-                } else if rules.len() == 1 {
-                    let operand = self.parse_operand(next_token, rules[0])?;
+                } else {
+                    let mut operands = Vec::with_capacity(rules.len());
 
-                    Ok(Instruction::SingleOperand(opcode, operand))
-                } else if rules.len() == 2 {
-                    let operand1 = self.parse_operand(next_token, rules[0])?;
+                    for (operand_index, operand_rule) in rules.iter().enumerate() {
+                        if operand_index > 0 {
+                            // The line ending before the comma means the rest of the operands were left out, which
+                            // is the same whether it ends with a newline or with the end of the file
+                            if self.tokens_iter.peek().is_none() || self.is_peek_token(TokenType::Newline) {
+                                return Err(ParseError::MissingOperand(
+                                    next_token,
+                                    operand_index,
+                                    self.span_after_last_token(),
+                                ));
+                            }
 
-                    self.expect_token(TokenType::Comma)?;
+                            self.expect_token(TokenType::Comma)?;
+                        }
 
-                    let operand2 = self.parse_operand(next_token, rules[1])?;
+                        operands.push(self.traced("parse_operand", |parser| {
+                            parser.parse_operand(next_token, opcode, operand_index, operand_rule)
+                        })?);
+                    }
 
-                    Ok(Instruction::DoubleOperand(opcode, operand1, operand2))
-                } else {
-                    panic!("Internal Assembler Error: Instructions with more than 2 operands are currently not supported");
+                    let span = next_token.span.merge(operands[operands.len() - 1].span());
+
+                    let instruction = match operands[..] {
+                        [operand] => Instruction::SingleOperand(opcode, operand),
+                        [operand1, operand2] => Instruction::DoubleOperand(opcode, operand1, operand2),
+                        _ => Instruction::MultiOperand(opcode, operands),
+                    };
+
+                    Ok((instruction, span))
                 }
             } else {
                 Err(ParseError::InvalidInstruction(next_token))
@@ -175,29 +1107,109 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
         }
     }
 
+    /// Whether an identifier is an `r` followed only by digits
+    fn looks_like_register(text: &str) -> bool {
+        let mut chars = text.chars();
+
+        matches!(chars.next(), Some('r' | 'R'))
+            && !chars.as_str().is_empty()
+            && chars.all(|c| c.is_ascii_digit())
+    }
+
     fn parse_operand(
         &mut self,
         instruction_token: Token,
+        opcode: Opcode,
+        operand_index: usize,
         operand_rule: &[OperandType],
     ) -> Result<Operand, ParseError> {
         let expected_token_types: Vec<TokenType> = operand_rule
             .iter()
             .map(|ot| match ot {
                 OperandType::Integer => TokenType::Integer,
-                OperandType::Label | OperandType::Register => TokenType::Identifier,
+                OperandType::Register if self.strict_registers => TokenType::Register,
+                OperandType::Label | OperandType::Register | OperandType::Port => {
+                    TokenType::Identifier
+                }
             })
             .collect();
 
-        if let Some(&next_token) = self.tokens_iter.next() {
-            if expected_token_types.iter().any(|&t| t == next_token.tt) {
+        if self.tokens_iter.peek().is_none() || self.is_peek_token(TokenType::Newline) {
+            return Err(ParseError::MissingOperand(
+                instruction_token,
+                operand_index,
+                self.span_after_last_token(),
+            ));
+        }
+
+        let allows_integer = operand_rule.contains(&OperandType::Integer);
+
+        // An integer may be worked out from an expression, such as `BASE - 3`, where a sign directly before a number is
+        // part of it, and any other `+` or `-` is an operator
+        if allows_integer && self.is_peek_integer_expression() {
+            let role = opcode
+                .integer_role()
+                .expect("Internal Assembler Error: Integer operand for an opcode without an integer role");
+            let expression = self.parse_expression()?;
+            let span = expression.span();
+
+            let value = expression.evaluate(&mut |_, span| {
+                Err(ParseError::LabelNotAllowed(
+                    instruction_token,
+                    operand_index,
+                    Token {
+                        tt: TokenType::Identifier,
+                        span,
+                    },
+                    Self::describe_operand_rule(operand_rule),
+                ))
+            })?;
+
+            return Ok(Operand::Integer {
+                value: self.operand_value_in_range(value, span, role)? as i8,
+                span,
+            });
+        }
+
+        if let Some(next_token) = self.next_token() {
+            if next_token.tt == TokenType::Comma {
+                // Commas only go between operands, so one here means an operand was left out
+                return Err(ParseError::UnexpectedComma(
+                    instruction_token,
+                    operand_index,
+                    next_token,
+                ));
+            }
+
+            if expected_token_types.contains(&next_token.tt) {
                 // The token was the one that was expected
                 let text = self
                     .source_manager
                     .get_span(next_token.span)
                     .unwrap();
 
-                if next_token.tt == TokenType::Identifier {
-                    if operand_rule.contains(&OperandType::Register) {
+                if next_token.tt == TokenType::Register {
+                    // Without the `$`
+                    let name = &text[1..];
+
+                    Register::try_from(name.to_lowercase().as_str())
+                        .map(|register| Operand::Register {
+                            value: register,
+                            span: next_token.span,
+                        })
+                        .map_err(|_| ParseError::NoSuchRegister(next_token))
+                } else if next_token.tt == TokenType::Identifier {
+                    // Something like `r99` was almost certainly meant as a register, even where labels are allowed
+                    if !self.strict_registers
+                        && Self::looks_like_register(text)
+                        && Register::try_from(text.to_lowercase().as_str()).is_err()
+                    {
+                        return Err(ParseError::NoSuchRegister(next_token));
+                    }
+
+                    // Registers win over ports and labels in slots that accept several, so a label named like a
+                    // register can only be referred to where a register is not allowed
+                    if operand_rule.contains(&OperandType::Register) && !self.strict_registers {
                         // See if it is is a register
                         if let Ok(register) = Register::try_from(text.to_lowercase().as_str()) {
                             return Ok(Operand::Register {
@@ -207,10 +1219,33 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
                         }
                     }
 
+                    if operand_rule.contains(&OperandType::Port) {
+                        return if let Some(port) = self.ports.get_mut(text) {
+                            Ok(Operand::Integer {
+                                value: port.take() as i8,
+                                span: next_token.span,
+                            })
+                        } else {
+                            Err(ParseError::UnknownPort(next_token))
+                        };
+                    }
+
                     if operand_rule.contains(&OperandType::Label) {
                         // It's a label, we can't do much about checking it's validity until later
                         let label_id = self.label_manager.get_or_insert_reference(text);
 
+                        if !self.strict_registers && Register::try_from(text.to_lowercase().as_str()).is_ok() {
+                            self.register_named_references.push((
+                                label_id,
+                                ParseError::RegisterAsLabel(
+                                    instruction_token,
+                                    operand_index,
+                                    next_token,
+                                    Self::describe_operand_rule(operand_rule),
+                                ),
+                            ));
+                        }
+
                         Ok(Operand::Label {
                             value: label_id,
                             span: next_token.span,
@@ -221,40 +1256,100 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
                     } else {
                         panic!("Internal Assembler Error");
                     }
-                } else if next_token.tt == TokenType::Integer {
-                    if let Ok(parsed_value) = text.parse::<i8>() {
-                        Ok(Operand::Integer {
-                            value: parsed_value,
-                            span: next_token.span,
-                        })
-                    } else {
-                        Err(ParseError::IntegerOutOfRange(next_token))
-                    }
                 } else {
                     panic!("Internal Assembler Error");
                 }
+            } else if next_token.tt == TokenType::Integer
+                && matches!(opcode, Opcode::Sr | Opcode::Sl)
+                && operand_index == 1
+            {
+                // The shift amount only has room for a register in the encoding, and there is no immediate shift
+                Err(ParseError::ImmediateShift(instruction_token, next_token))
+            } else if next_token.tt == TokenType::Identifier
+                && allows_integer
+                && (self.strict_registers
+                    || !Self::looks_like_register(self.source_manager.get_span(next_token.span).unwrap()))
+            {
+                // Constants were handled above, so this is most likely a label, which can't be encoded here
+                Err(ParseError::LabelNotAllowed(
+                    instruction_token,
+                    operand_index,
+                    next_token,
+                    Self::describe_operand_rule(operand_rule),
+                ))
             } else {
-                let expected = match operand_rule.len() {
-                    1 => format!("{}", operand_rule[0].as_str()),
-                    2 => format!(
-                        "{} or {}",
-                        operand_rule[0].as_str(),
-                        operand_rule[0].as_str()
-                    ),
-                    3 => format!(
-                        "{}, {} or {}",
-                        operand_rule[0].as_str(),
-                        operand_rule[1].as_str(),
-                        operand_rule[2].as_str()
-                    ),
-                    _ => panic!("Internal Assembler Error"),
-                };
-
-                Err(ParseError::ExpectedOperand(next_token, expected))
+                Err(ParseError::ExpectedOperand(
+                    instruction_token,
+                    operand_index,
+                    next_token,
+                    Self::describe_operand_rule(operand_rule),
+                ))
             }
         } else {
-            Err(ParseError::ExpectedOperandFoundEOF(instruction_token))
+            panic!("Internal Assembler Error: End of file should have been handled above");
+        }
+    }
+
+    /// What an operand rule accepts, such as "a label or an integer", for use in messages
+    fn describe_operand_rule(operand_rule: &[OperandType]) -> String {
+        let descriptions: Vec<&str> = operand_rule.iter().map(|ot| ot.description()).collect();
+
+        match descriptions.split_last() {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => panic!("Internal Assembler Error: Operand rule without any operand types"),
+        }
+    }
+
+    /// Parses a decimal or `0x` prefixed hexadecimal integer with an optional sign, returning None if it doesn't even
+    /// fit in an `i64`
+    fn parse_integer_literal(text: &str) -> Option<i64> {
+        let (negative, digits) = match text.as_bytes().first() {
+            Some(b'-') => (true, &text[1..]),
+            Some(b'+') => (false, &text[1..]),
+            _ => (false, text),
+        };
+
+        let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+            i64::from_str_radix(hex, 16)
+        } else {
+            digits.parse::<i64>()
+        };
+
+        value.ok().map(|value| if negative { -value } else { value })
+    }
+
+    /// The comment after `span` on the same line, if any, which must be called before the end of the line is consumed
+    fn trailing_comment(&mut self, span: Span) -> Option<Span> {
+        let line_end = match self.tokens_iter.peek() {
+            Some(token) => token.span.index,
+            None => u32::MAX,
+        };
+
+        let after_span = self
+            .comments
+            .partition_point(|comment| comment.index < span.index + span.len);
+
+        self.comments
+            .get(after_span)
+            .filter(|comment| comment.index < line_end)
+            .copied()
+    }
+
+    /// An empty span just past the most recently consumed token, for pointing at something missing, since a newline
+    /// is invisible and the end of the file has no token at all
+    fn span_after_last_token(&self) -> Span {
+        self.last_span.point_at_end()
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens_iter.next().copied();
+
+        if let Some(token) = token {
+            self.last_span = token.span;
         }
+
+        token
     }
 
     fn is_peek_token(&mut self, tt: TokenType) -> bool {
@@ -262,9 +1357,9 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
     }
 
     fn consume_or_eof(&mut self, tt: TokenType) -> Result<(), ParseError> {
-        if let Some(next_token) = self.tokens_iter.next() {
+        if let Some(next_token) = self.next_token() {
             if next_token.tt != tt {
-                Err(ParseError::UnexpectedToken(tt, *next_token))
+                Err(ParseError::UnexpectedToken(tt, next_token))
             } else {
                 Ok(())
             }
@@ -273,21 +1368,102 @@ impl<'a, 'b, 'c> Parser<'a, 'b, 'c> {
         }
     }
 
-    fn expect_token(&mut self, tt: TokenType) -> Result<(), ParseError> {
-        if let Some(next_token) = self.tokens_iter.next() {
+    fn expect_token(&mut self, tt: TokenType) -> Result<Token, ParseError> {
+        if let Some(next_token) = self.next_token() {
             if next_token.tt != tt {
-                Err(ParseError::UnexpectedToken(tt, *next_token))
+                Err(ParseError::UnexpectedToken(tt, next_token))
             } else {
-                Ok(())
+                Ok(next_token)
             }
         } else {
-            Err(ParseError::MissingToken(tt))
+            Err(ParseError::MissingToken(tt, self.span_after_last_token()))
+        }
+    }
+}
+
+/// Everything parsed from a source
+pub struct ParsedProgram {
+    pub items: Vec<Item>,
+    pub label_manager: LabelManager,
+    pub warnings: Vec<ParseWarning>,
+    /// Every `.meta` entry, in source order, kept apart from the items since they aren't part of the program
+    pub metadata: Vec<MetaEntry>,
+    /// The comments around each item, in the same order as `items`, if the parser was asked to keep them
+    pub comments: Vec<ItemComments>,
+    /// Comments after the last item that aren't on its line, if the parser was asked to keep them
+    pub end_comments: Vec<Span>,
+}
+
+/// The constants and ports defined by files of only `.set` and `.port` directives, to be used by a program
+#[derive(Debug, Clone, Default)]
+pub struct ConstantDefinitions {
+    /// Each constant's name and value, sorted by name
+    pub constants: Vec<(String, i64)>,
+    /// Each port's name and number, sorted by name
+    pub ports: Vec<(String, i64)>,
+}
+
+/// Whether a label is exported from the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Visibility {
+    /// Only used within the program, which is what labels are unless marked with `.global`
+    #[default]
+    Local,
+    /// Exported with `.global`, so that it may be used from outside the program
+    Global,
+}
+
+/// The value of a `.set` constant or a `.port`, along with the name in its first definition and whether it has been
+/// used
+struct NamedValue {
+    value: i64,
+    /// None for one defined before parsing, such as in a constants file, which isn't warned about if unused
+    definition: Option<Token>,
+    used: bool,
+}
+
+impl NamedValue {
+    fn new(value: i64, definition: Option<Token>) -> Self {
+        Self {
+            value,
+            definition,
+            used: false,
+        }
+    }
+
+    /// The value, marking it as used
+    fn take(&mut self) -> i64 {
+        self.used = true;
+        self.value
+    }
+}
+
+struct LabelEntry {
+    name: String,
+    value: Option<i8>,
+    /// Where the label is defined
+    span: Option<Span>,
+    /// Whether the label has been referred to
+    referenced: bool,
+    /// The visibility given by a `.global` or `.local` directive, if any
+    visibility: Option<Visibility>,
+}
+
+impl LabelEntry {
+    fn new(name: &str, span: Option<Span>) -> Self {
+        Self {
+            name: String::from(name),
+            value: None,
+            span,
+            referenced: false,
+            visibility: None,
         }
     }
 }
 
+#[derive(Default)]
 pub struct LabelManager {
-    map: Vec<(String, Option<i8>, Option<Span>)>,
+    map: Vec<LabelEntry>,
 }
 
 impl LabelManager {
@@ -296,50 +1472,357 @@ impl LabelManager {
     }
 
     pub fn get_id_of(&self, label: &str) -> Option<LabelId> {
-        self.map.iter().position(|l| l.0 == label)
+        self.map.iter().position(|l| l.name == label)
     }
 
     pub fn insert_unique(&mut self, label: &str, label_span: Span) -> Result<LabelId, ()> {
-        let exists = self.map.iter().any(|l| l.0 == label);
+        let exists = self.map.iter().any(|l| l.name == label);
 
         if exists {
             Err(())
         } else {
-            self.map.push((String::from(label), None, Some(label_span)));
+            self.map.push(LabelEntry::new(label, Some(label_span)));
             Ok(self.map.len() - 1)
         }
     }
 
+    /// Gets the id of a label, adding it without a value or span if it hasn't been seen yet
+    pub fn get_or_insert(&mut self, label: &str) -> LabelId {
+        self.get_id_of(label).unwrap_or_else(|| {
+            self.map.push(LabelEntry::new(label, None));
+            self.map.len() - 1
+        })
+    }
+
+    /// Like [`LabelManager::get_or_insert`], but also marks the label as referred to
     pub fn get_or_insert_reference(&mut self, label: &str) -> LabelId {
-        let exists = self.map.iter().any(|l| l.0 == label);
+        let id = self.get_or_insert(label);
 
-        if exists {
-            self.get_id_of(label).unwrap()
-        } else {
-            self.map.push((String::from(label), None, None));
-            self.map.len() - 1
-        }
+        self.map[id].referenced = true;
+
+        id
     }
 
     /// Sets the value of a label (the byte index that it refers to)
     ///
     /// Returns Err(()) when the label specified does not exist
     pub fn set_value_of(&mut self, id: LabelId, value: i8) -> Result<(), ()> {
-        self.map.get_mut(id).map(|l| l.1 = Some(value)).ok_or(())
+        self.map.get_mut(id).map(|l| l.value = Some(value)).ok_or(())
     }
 
     /// Sets the span of a label (the place where it is defined in the source)
     ///
     /// Returns Err(()) when the label specified does not exist
     pub fn set_span_of(&mut self, id: LabelId, span: Span) -> Result<(), ()> {
-        self.map.get_mut(id).map(|l| l.2 = Some(span)).ok_or(())
+        self.map.get_mut(id).map(|l| l.span = Some(span)).ok_or(())
+    }
+
+    /// Marks a label as exported or not
+    ///
+    /// Returns Err(()) when the label specified does not exist, or was already given the other visibility
+    pub fn set_visibility_of(&mut self, id: LabelId, visibility: Visibility) -> Result<(), ()> {
+        let label = self.map.get_mut(id).ok_or(())?;
+
+        match label.visibility {
+            Some(existing) if existing != visibility => Err(()),
+            _ => {
+                label.visibility = Some(visibility);
+                Ok(())
+            }
+        }
     }
 
     pub fn get_value_of(&self, id: LabelId) -> Option<i8> {
-        self.map.get(id).map(|l| l.1).flatten()
+        self.map.get(id).and_then(|l| l.value)
+    }
+
+    pub fn get_name_of(&self, id: LabelId) -> Option<&str> {
+        self.map.get(id).map(|l| l.name.as_str())
     }
 
     pub fn get_span_of(&self, id: LabelId) -> Option<Span> {
-        self.map.get(id).map(|l| l.2).flatten()
+        self.map.get(id).and_then(|l| l.span)
+    }
+
+    /// Whether the label is exported, which labels aren't unless marked with `.global`
+    pub fn get_visibility_of(&self, id: LabelId) -> Visibility {
+        self.map
+            .get(id)
+            .and_then(|l| l.visibility)
+            .unwrap_or_default()
+    }
+
+    /// Whether the label has been referred to anywhere, such as by a jump
+    pub fn is_referenced(&self, id: LabelId) -> bool {
+        self.map.get(id).is_some_and(|l| l.referenced)
+    }
+
+    /// Iterates over every label as `(name, value, span)`, in the order they were first seen, which is also the
+    /// order of their ids
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<i8>, Option<Span>)> {
+        self.map.iter().map(|l| (l.name.as_str(), l.value, l.span))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse_source(source: &str) -> Result<ParsedProgram, ParseError> {
+        let tokens = Lexer::new(source).lex();
+        let source_manager = SourceManager::new(source, String::from("test.basm"));
+
+        Parser::new(&tokens, &source_manager).parse()
+    }
+
+    fn parse_error(source: &str) -> ParseError {
+        parse_source(source).err().expect("the source should not parse")
+    }
+
+    #[test]
+    fn every_opcode_has_rules() {
+        let parse_rules = rules::parse_rules();
+
+        for opcode in Opcode::all() {
+            let rules = parse_rules
+                .get(&opcode)
+                .unwrap_or_else(|| panic!("`{}` has no operand rules", opcode.mnemonic()));
+
+            assert_eq!(rules.len(), opcode.operand_access().len(), "`{}`", opcode.mnemonic());
+        }
+    }
+
+    #[test]
+    fn parser_without_tokens_for_its_source() {
+        let source = "add r0, r1\n";
+        let source_manager = SourceManager::new(source, String::from("test.basm"));
+
+        // The tokens don't have to come from the source, so a parser given none of them has nothing to parse
+        let program = Parser::new(&[], &source_manager).parse().unwrap();
+
+        assert!(program.items.is_empty());
+    }
+
+    #[test]
+    fn parses_every_opcode() {
+        let source = "nop\nadd r0, r1\nldi r0, 1\nsub r0, r1\nand r0, r1\nor r0, r1\ninv r0\nxor r0, r1\nsr r0, r1\n\
+                      sl r0, r1\nin r0, 1\nout r0, 1\njz r0, 0\njlt r0, 0\nj 0\n";
+
+        let program = parse_source(source).unwrap();
+        let opcodes: Vec<Opcode> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Instruction(instruction, _, _) => Some(instruction.opcode()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(opcodes, Opcode::all().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn missing_second_operand_at_end_of_line() {
+        let error = parse_error("add r0,\n");
+
+        assert!(matches!(error, ParseError::MissingOperand(_, 1, _)), "{error:?}");
+        // Just past the comma, rather than on the newline
+        assert_eq!(error.span(), Some(Span { index: 7, len: 0 }));
+    }
+
+    #[test]
+    fn missing_second_operand_at_end_of_file() {
+        let error = parse_error("add r0,");
+
+        assert!(matches!(error, ParseError::MissingOperand(_, 1, _)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 7, len: 0 }));
+    }
+
+    #[test]
+    fn no_such_register_where_a_register_is_expected() {
+        let error = parse_error("add r0, r99\n");
+
+        assert!(matches!(error, ParseError::NoSuchRegister(_)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 8, len: 3 }));
+    }
+
+    #[test]
+    fn no_such_register_where_a_label_is_allowed() {
+        // Shaped like a register, so it isn't taken as a reference to a label that doesn't exist
+        let error = parse_error("j r99\n");
+
+        assert!(matches!(error, ParseError::NoSuchRegister(_)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 2, len: 3 }));
+    }
+
+    #[test]
+    fn comma_in_place_of_first_operand() {
+        let error = parse_error("add , r1\n");
+
+        assert!(matches!(error, ParseError::UnexpectedComma(_, 0, _)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 4, len: 1 }));
+    }
+
+    #[test]
+    fn comma_in_place_of_second_operand() {
+        let error = parse_error("add r0, , r1\n");
+
+        assert!(matches!(error, ParseError::UnexpectedComma(_, 1, _)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 8, len: 1 }));
+    }
+
+    #[test]
+    fn three_operand_rule() {
+        use crate::generator::{Generator, GeneratorError, GeneratorOptions};
+
+        static THREE_REGISTERS: OperandRules =
+            &[&[OperandType::Register], &[OperandType::Register], &[OperandType::Register]];
+
+        let source = "add r0, r1, r2\n";
+        let tokens = Lexer::new(source).lex();
+        let source_manager = SourceManager::new(source, String::from("test.basm"));
+
+        let mut parser = Parser::new(&tokens, &source_manager);
+        parser.parse_rules.insert(Opcode::Add, THREE_REGISTERS);
+        let program = parser.parse().unwrap();
+
+        let registers: Vec<Register> = match &program.items[..] {
+            [Item::Instruction(Instruction::MultiOperand(Opcode::Add, operands), _, _)] => operands
+                .iter()
+                .map(|operand| match operand {
+                    Operand::Register { value, .. } => *value,
+                    _ => panic!("{operand:?} isn't a register"),
+                })
+                .collect(),
+            items => panic!("{items:?}"),
+        };
+        assert_eq!(registers, [Register::R0, Register::R1, Register::R2]);
+
+        // No encoding has room for a third operand, which is reported rather than panicking
+        let mut generator = Generator::new(program.items, program.label_manager, GeneratorOptions::default());
+
+        assert!(matches!(
+            generator.generate(),
+            Err(GeneratorError::TooManyOperands(Span { index: 0, len: 14 }))
+        ));
+    }
+
+    #[test]
+    fn file_ending_mid_instruction() {
+        // (source, operand index, where it is missing), each without a newline at the end
+        let cases = [
+            ("add", 0, 3),
+            ("add r0", 1, 6),
+            ("add r0,", 1, 7),
+            ("ldi", 0, 3),
+            ("ldi r0", 1, 6),
+            ("ldi r0,", 1, 7),
+            ("inv", 0, 3),
+            ("out r0", 1, 6),
+            ("in r0,", 1, 6),
+            ("j", 0, 1),
+            ("jz r0", 1, 5),
+            ("jlt r0,", 1, 7),
+        ];
+
+        for (source, operand_index, index) in cases {
+            let error = parse_error(source);
+
+            assert!(
+                matches!(error, ParseError::MissingOperand(_, i, _) if i == operand_index),
+                "{source:?}: {error:?}"
+            );
+            assert_eq!(error.span(), Some(Span { index, len: 0 }), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn file_ending_mid_expression() {
+        let error = parse_error("ldi r0, 1 +");
+        assert!(matches!(error, ParseError::MissingToken(TokenType::Integer, _)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 11, len: 0 }));
+
+        let error = parse_error("ldi r0, (1");
+        assert!(matches!(error, ParseError::UnclosedParen(_)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 8, len: 1 }));
+
+        let error = parse_error("ldi r0, -");
+        assert!(matches!(error, ParseError::SignWithoutInteger(_)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 8, len: 1 }));
+
+        let error = parse_error(".byte 1,");
+        assert!(matches!(error, ParseError::MissingToken(TokenType::Integer, _)), "{error:?}");
+        assert_eq!(error.span(), Some(Span { index: 8, len: 0 }));
+    }
+
+    #[test]
+    fn every_form_at_end_of_file() {
+        for source in ["nop", "inv r0", "add r0, r1", "ldi r0, 1", "out r0, 2", "jz r0, 0", "j 0", ".byte 1"] {
+            assert!(parse_source(source).is_ok(), "{source:?}");
+        }
+    }
+
+    /// The text of an item's leading comments and its trailing comment
+    type CommentText<'a> = (Vec<&'a str>, Option<&'a str>);
+
+    /// The text of the comments of each item, and of the comments at the end
+    fn comments_of(source: &str) -> (Vec<CommentText<'_>>, Vec<&str>) {
+        let tokens = Lexer::new(source).lex();
+        let source_manager = SourceManager::new(source, String::from("test.basm"));
+        let mut parser = Parser::new(&tokens, &source_manager);
+        parser.set_keep_comments(true);
+
+        let program = parser.parse().unwrap_or_else(|_| panic!("{source:?} should parse"));
+        let text = |span: Span| &source[span.index as usize..(span.index + span.len) as usize];
+
+        let attached = program
+            .comments
+            .iter()
+            .map(|comments| {
+                (
+                    comments.leading.iter().map(|&span| text(span)).collect(),
+                    comments.trailing.map(text),
+                )
+            })
+            .collect();
+
+        (attached, program.end_comments.iter().map(|&span| text(span)).collect())
+    }
+
+    #[test]
+    fn comments_attach_to_items() {
+        let source = "\
+; The program
+; starts here
+start:          ; at the label
+    ldi r0, 1   ; load
+    ; before the data
+    .byte 1, 2  ; the data
+
+    j start
+; after everything
+";
+
+        let (attached, end_comments) = comments_of(source);
+
+        assert_eq!(
+            attached,
+            [
+                (vec!["; The program", "; starts here"], Some("; at the label")),
+                (vec![], Some("; load")),
+                (vec!["; before the data"], Some("; the data")),
+                (vec![], None),
+            ]
+        );
+        assert_eq!(end_comments, ["; after everything"]);
+    }
+
+    #[test]
+    fn comments_not_kept_by_default() {
+        let program = parse_source("    nop ; a comment\n; the end\n").ok().unwrap();
+
+        assert!(program.comments.is_empty());
+        assert!(program.end_comments.is_empty());
+    }
+}
@@ -1,21 +1,48 @@
-use std::ops::Range;
+use std::{borrow::Cow, fs, io, ops::Range, path::Path};
 
 use crate::lexer::Span;
 
+/// Where a span starts in its source, in the same terms diagnostics use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// The 1-based line number
+    pub line: u32,
+    /// The 0-based column in characters, with a tab counted as one character
+    pub column: u32,
+    /// The 0-based column as shown, with tabs counted as 4 columns like the terminal emitter
+    pub visual_column: u32,
+}
+
 pub struct SourceManager<'a> {
-    source: &'a str,
+    source: Cow<'a, str>,
     lines: Vec<Range<usize>>,
     file_name: String,
 }
 
 impl<'a> SourceManager<'a> {
+    #[allow(dead_code)]
     pub fn new(source: &'a str, file_name: String) -> Self {
+        Self::from_cow(Cow::Borrowed(source), file_name)
+    }
+
+    /// Reads the file at `path` into a new source manager, using the path as the file name
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<SourceManager<'static>> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+
+        Ok(SourceManager::from_cow(
+            Cow::Owned(source),
+            path.to_string_lossy().into_owned(),
+        ))
+    }
+
+    fn from_cow(source: Cow<'a, str>, file_name: String) -> Self {
         let mut lines = Vec::new();
         let mut last_newline = 0;
-        let mut current_index = 0;
         let mut saw_carriage_return = false;
 
-        for c in source.chars() {
+        // Ranges are in bytes so that they can be used to slice the source directly
+        for (current_index, c) in source.char_indices() {
             match c {
                 '\r' => {
                     saw_carriage_return = true;
@@ -30,12 +57,17 @@ impl<'a> SourceManager<'a> {
 
                     last_newline = current_index + 1;
                 }
-                _ => {}
+                _ => {
+                    // Only a carriage return directly before a newline is part of the line ending
+                    saw_carriage_return = false;
+                }
             }
-
-            current_index += 1;
         }
 
+        let current_index = source.len();
+
+        // A final line without a trailing newline still needs a range, otherwise diagnostics on it would have no
+        // line to be found in. Its range ends at the end of the source, just like one ended by a newline would
         if last_newline != current_index {
             lines.push(last_newline..current_index);
         }
@@ -47,6 +79,10 @@ impl<'a> SourceManager<'a> {
         }
     }
 
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
     pub fn get_span(&'a self, span: Span) -> Result<&'a str, ()> {
         let index = span.index as usize;
         let len = span.len as usize;
@@ -61,7 +97,7 @@ impl<'a> SourceManager<'a> {
     /// Gets the line containing the span from the source, also returning the line number and column index
     /// of the span in the line
     ///
-    /// The span must not cross multiple lines
+    /// A span that crosses multiple lines gives the line it starts on
     pub fn get_span_line(&'a self, span: Span) -> Result<(&'a str, u32, u32), ()> {
         let (line_range, line_number) = self
             .find_line_containing_char(span.index as usize)
@@ -71,7 +107,7 @@ impl<'a> SourceManager<'a> {
 
         let mut col = 0;
 
-        for (i, c) in line.chars().enumerate() {
+        for (i, c) in line.char_indices() {
             if i == span_line_index {
                 break;
             }
@@ -86,13 +122,45 @@ impl<'a> SourceManager<'a> {
         Ok((line, line_number + 1, col))
     }
 
+    /// Gets the column of the span in its line, counting every character, including tabs, as a single column
+    pub fn get_span_char_column(&self, span: Span) -> Result<u32, ()> {
+        let (line_range, _) = self
+            .find_line_containing_char(span.index as usize)
+            .ok_or(())?;
+        // A span on the `\n` of a `\r\n` is past the end of the line range, and the `\r` isn't a column of its own
+        let line = &self.source[line_range.start..(span.index as usize).min(line_range.end)];
+
+        Ok(line.chars().count() as u32)
+    }
+
+    /// Gets the line and columns where the span starts, as found by [`SourceManager::get_span_line`] and
+    /// [`SourceManager::get_span_char_column`]
+    pub fn get_span_position(&self, span: Span) -> Result<Position, ()> {
+        let (_, line, visual_column) = self.get_span_line(span)?;
+
+        Ok(Position {
+            line,
+            column: self.get_span_char_column(span)?,
+            visual_column,
+        })
+    }
+
+    /// Iterates over the text of each line in the source, without line endings
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|range| &self.source[range.clone()])
+    }
+
     pub fn file_name(&self) -> &String {
         &self.file_name
     }
 
     fn find_line_containing_char(&'a self, index: usize) -> Option<(Range<usize>, u32)> {
         for (line_number, line) in self.lines.iter().enumerate() {
-            if line.contains(&index) {
+            // The end of a line is included so that a zero-length span can point just past the last character, and so
+            // is the `\n` of a `\r\n` ending, so that a newline token is on the line it ends
+            let ending_len = self.source[line.end..].starts_with("\r\n") as usize;
+
+            if line.start <= index && index <= line.end + ending_len {
                 return Some((line.clone(), line_number as u32));
             }
         }
@@ -100,3 +168,72 @@ impl<'a> SourceManager<'a> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span_at(index: u32) -> Span {
+        Span { index, len: 1 }
+    }
+
+    #[test]
+    fn last_line_without_newline() {
+        let source_manager = SourceManager::new("nop\n\tadd r0, r1", String::from("test.basm"));
+
+        // The `r1` at the very end of the source
+        let (line, line_number, column) = source_manager.get_span_line(Span { index: 12, len: 2 }).unwrap();
+
+        assert_eq!(line, "\tadd r0, r1");
+        assert_eq!(line_number, 2);
+        assert_eq!(column, 11);
+        assert_eq!(
+            source_manager.get_span_position(Span { index: 12, len: 2 }),
+            Ok(Position {
+                line: 2,
+                column: 8,
+                visual_column: 11
+            })
+        );
+        assert_eq!(source_manager.lines().collect::<Vec<_>>(), ["nop", "\tadd r0, r1"]);
+    }
+
+    #[test]
+    fn zero_length_span_at_end_of_last_line() {
+        let source_manager = SourceManager::new("nop\nadd r0,", String::from("test.basm"));
+
+        let position = source_manager.get_span_position(Span { index: 11, len: 0 }).unwrap();
+
+        assert_eq!(position.line, 2);
+        assert_eq!(position.column, 7);
+    }
+
+    #[test]
+    fn empty_last_line() {
+        let source_manager = SourceManager::new("nop\nj 0\n", String::from("test.basm"));
+
+        // The newline ending the last line with anything on it is still on that line
+        let (line, line_number, column) = source_manager.get_span_line(span_at(7)).unwrap();
+
+        assert_eq!((line, line_number, column), ("j 0", 2, 3));
+        assert_eq!(source_manager.lines().count(), 2);
+    }
+
+    #[test]
+    fn single_line() {
+        let source_manager = SourceManager::new("ldi r0, 5", String::from("test.basm"));
+
+        assert_eq!(source_manager.get_span_line(span_at(0)), Ok(("ldi r0, 5", 1, 0)));
+        assert_eq!(source_manager.get_span_line(span_at(8)), Ok(("ldi r0, 5", 1, 8)));
+        assert_eq!(source_manager.get_span(Span { index: 4, len: 2 }), Ok("r0"));
+        assert_eq!(source_manager.lines().collect::<Vec<_>>(), ["ldi r0, 5"]);
+    }
+
+    #[test]
+    fn span_past_the_end() {
+        let source_manager = SourceManager::new("nop", String::from("test.basm"));
+
+        assert!(source_manager.get_span(Span { index: 2, len: 2 }).is_err());
+        assert!(source_manager.get_span_line(span_at(10)).is_err());
+    }
+}
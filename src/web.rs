@@ -0,0 +1,101 @@
+//! An entry point for running the assembler somewhere without a filesystem or terminal, such as in a browser
+
+use crate::{
+    assemble_with_emitter, AssembleOptions,
+    errors::{CollectingEmitter, Diagnostic, DiagnosticKind},
+    json,
+    lexer::Span,
+    sources::SourceManager,
+};
+
+/// A diagnostic with its span resolved to a position in the source
+#[derive(Debug, Clone)]
+pub struct ResolvedDiagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub span: Option<Span>,
+    /// The 1-based line number, if the diagnostic has a span
+    pub line: Option<u32>,
+    /// The 0-based column in characters, with a tab counted as one character, if the diagnostic has a span
+    pub column: Option<u32>,
+    /// The 0-based column as shown, with tabs counted as 4 columns like the terminal emitter, if the diagnostic has a
+    /// span
+    pub visual_column: Option<u32>,
+}
+
+impl ResolvedDiagnostic {
+    pub fn resolve(diagnostic: &Diagnostic, source_manager: &SourceManager) -> Self {
+        let span = diagnostic.label_span();
+        let position = span.and_then(|span| source_manager.get_span_position(span).ok());
+
+        Self {
+            kind: diagnostic.kind(),
+            message: diagnostic.label().clone(),
+            span,
+            line: position.map(|position| position.line),
+            column: position.map(|position| position.column),
+            visual_column: position.map(|position| position.visual_column),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let optional = |value: Option<u32>| value.map_or(String::from("null"), |v| v.to_string());
+
+        format!(
+            "{{\"severity\":{},\"message\":{},\"index\":{},\"length\":{},\"line\":{},\"column\":{},\"visual_column\":{}}}",
+            json::quote(&self.kind.to_string()),
+            json::quote(&self.message),
+            optional(self.span.map(|span| span.index)),
+            optional(self.span.map(|span| span.len)),
+            optional(self.line),
+            optional(self.column),
+            optional(self.visual_column)
+        )
+    }
+}
+
+/// Everything produced by assembling a source with [`assemble_web`]
+#[derive(Debug, Clone)]
+pub struct WebAssembleResult {
+    /// The unpadded output, present only if assembly succeeded
+    pub bytes: Option<Vec<u8>>,
+    pub diagnostics: Vec<ResolvedDiagnostic>,
+}
+
+impl WebAssembleResult {
+    pub fn to_json(&self) -> String {
+        let bytes = match &self.bytes {
+            Some(bytes) => {
+                let values: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+                format!("[{}]", values.join(","))
+            }
+            None => String::from("null"),
+        };
+
+        let diagnostics: Vec<String> = self.diagnostics.iter().map(|d| d.to_json()).collect();
+
+        format!(
+            "{{\"bytes\":{},\"diagnostics\":[{}]}}",
+            bytes,
+            diagnostics.join(",")
+        )
+    }
+}
+
+/// Assembles `source` without any filesystem or terminal access, collecting every diagnostic with its position
+/// resolved
+pub fn assemble_web(source: &str) -> WebAssembleResult {
+    let source_manager = SourceManager::new(source, String::from("<input>"));
+    let mut emitter = CollectingEmitter::new();
+
+    let assembled = assemble_with_emitter(&source_manager, &AssembleOptions::default(), &mut emitter);
+
+    WebAssembleResult {
+        bytes: assembled.map(|result| result.bytes),
+        diagnostics: emitter
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| ResolvedDiagnostic::resolve(diagnostic, &source_manager))
+            .collect(),
+    }
+}
@@ -0,0 +1,692 @@
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+/// A directory of its own for each test, so that tests running at the same time don't share files
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("brisc-assembler-cli-{}-{name}", std::process::id()));
+
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_brisc-assembler"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn path_str(path: &std::path::Path) -> &str {
+    path.to_str().unwrap()
+}
+
+const LOOP_PROGRAM: &str = "\
+start:
+    ldi r0, 1
+loop:
+    add r0, r0
+    jz r0, done
+    j loop
+table:
+    .byte 1, 2, 3
+done:
+    j done
+";
+
+#[test]
+fn base_address_only_shifts_displayed_addresses() {
+    let dir = test_dir("base-address");
+    let source = dir.join("prog.basm");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    let assemble_at = |base: &str| {
+        let output = dir.join(format!("prog-{base}.bin"));
+        let listing = dir.join(format!("prog-{base}.lst"));
+        let symbols = dir.join(format!("prog-{base}.sym"));
+        let map = dir.join(format!("prog-{base}.map"));
+
+        let result = run(&[
+            path_str(&source),
+            "-o",
+            path_str(&output),
+            "--listing",
+            path_str(&listing),
+            "--symbols",
+            path_str(&symbols),
+            "--map",
+            path_str(&map),
+            "--base-address",
+            base,
+        ]);
+        assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+
+        [output, listing, symbols, map].map(|path| fs::read(path).unwrap())
+    };
+
+    let [bytes_0, listing_0, symbols_0, map_0] = assemble_at("0");
+    let [bytes_100, listing_100, symbols_100, map_100] = assemble_at("0x100");
+
+    // `jz r0, done` and `j loop` still encode slot indices from the start of the image
+    assert_eq!(bytes_0, bytes_100);
+    assert_eq!(&bytes_100[..14], &[0x20, 0x01, 0x10, 0x00, 0xd0, 0x06, 0xf0, 0x01, 0x01, 0x02, 0x03, 0x00, 0xf0, 0x06]);
+
+    let listing_0 = String::from_utf8(listing_0).unwrap();
+    let listing_100 = String::from_utf8(listing_100).unwrap();
+    assert!(listing_0.contains("   5  0004  d0 06      jz r0, done"), "{listing_0}");
+    assert!(listing_100.contains("   5  0104  d0 06      jz r0, done"), "{listing_100}");
+
+    assert_eq!(String::from_utf8(symbols_0).unwrap(), "0000 start\n0002 loop\n000c done\n0008 table\n");
+    assert_eq!(String::from_utf8(symbols_100).unwrap(), "0100 start\n0102 loop\n010c done\n0108 table\n");
+
+    assert!(String::from_utf8(map_0).unwrap().contains("\n0008   000b       4  data  table\n"));
+    assert!(String::from_utf8(map_100).unwrap().contains("\n0108   010b       4  data  table\n"));
+}
+
+#[test]
+fn map_splits_regions_at_labels() {
+    let dir = test_dir("map");
+    let source = dir.join("prog.basm");
+    let map = dir.join("prog.map");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    let result = run(&[path_str(&source), "-o", path_str(&dir.join("prog.bin")), "--map", path_str(&map)]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+
+    assert_eq!(
+        fs::read_to_string(map).unwrap(),
+        "\
+start  end    bytes  kind  labels
+0000   0001       2  code  start
+0002   0007       6  code  loop
+0008   000b       4  data  table
+000c   000d       2  code  done
+000e   003f      50  free
+"
+    );
+}
+
+#[test]
+fn c_array_length_follows_padding() {
+    let dir = test_dir("c-array");
+    let source = dir.join("prog.basm");
+    fs::write(&source, "ldi r0, 1\nend: j end\n").unwrap();
+
+    let header_of = |extra: &[&str]| {
+        let header = dir.join("prog.h");
+        let mut args = vec![path_str(&source), "--format", "c-array", "-o", path_str(&header)];
+        args.extend_from_slice(extra);
+
+        let result = run(&args);
+        assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+
+        fs::read_to_string(header).unwrap()
+    };
+
+    let padded = header_of(&[]);
+    assert!(padded.contains("#define PROGRAM_LEN 64\n"), "{padded}");
+    assert_eq!(padded.matches("0x").count(), 64);
+
+    let unpadded = header_of(&["--no-pad", "--array-name", "blink"]);
+    assert!(unpadded.contains("#define BLINK_LEN 4\n"), "{unpadded}");
+    assert!(unpadded.contains("const uint8_t blink[] = {\n    0x20, 0x01, 0xf0, 0x01,\n};\n"), "{unpadded}");
+}
+
+#[test]
+fn checksum_is_appended_after_padding() {
+    let dir = test_dir("checksum");
+    let source = dir.join("prog.basm");
+    fs::write(&source, "ldi r0, 1\nj 1\nin r1, 1\nj 3\n").unwrap();
+
+    for (algorithm, expected) in [("sum", 0xc6), ("xor", 0x82)] {
+        let output = dir.join(format!("prog-{algorithm}.bin"));
+
+        let result = run(&[path_str(&source), "-o", path_str(&output), "--checksum", algorithm]);
+        assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+
+        let bytes = fs::read(&output).unwrap();
+        assert_eq!(&bytes[..8], &[0x20, 0x01, 0xf0, 0x01, 0xb1, 0x10, 0xf0, 0x03]);
+        // The checksum comes after the 64 bytes of the padded image
+        assert_eq!(bytes.len(), 65);
+        assert_eq!(bytes[64], expected, "{algorithm}");
+    }
+}
+
+#[test]
+fn seeded_label_out_of_range() {
+    let dir = test_dir("seeded-label");
+    let source = dir.join("prog.basm");
+    fs::write(&source, "j handler\n").unwrap();
+
+    let result = run(&[path_str(&source), "-o", path_str(&dir.join("prog.bin")), "--label", "handler=32"]);
+
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("in the range of 0-31, found 32"));
+
+    let result = run(&[path_str(&source), "-o", path_str(&dir.join("prog.bin")), "--label", "handler=0x1f"]);
+
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert_eq!(fs::read(dir.join("prog.bin")).unwrap()[..2], [0xf0, 0x1f]);
+}
+
+#[test]
+fn warnings_as_errors() {
+    let dir = test_dir("deny-warnings");
+    let source = dir.join("prog.basm");
+    fs::write(&source, "unused:\n    nop\nalso_unused:\n    nop\nend: j end\n").unwrap();
+
+    let output = dir.join("prog.bin");
+
+    let result = run(&[path_str(&source), "-o", path_str(&output)]);
+    assert!(result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("warning: Label `unused` is never used"));
+    fs::remove_file(&output).unwrap();
+
+    for flag in ["--deny-warnings", "-Werror"] {
+        let result = run(&[path_str(&source), "-o", path_str(&output), flag]);
+        let stderr = String::from_utf8_lossy(&result.stderr);
+
+        assert!(!result.status.success(), "{flag}");
+        assert!(!output.exists(), "{flag}");
+        // Every warning is reported before giving up
+        assert!(stderr.contains("error: Label `unused` is never used"), "{stderr}");
+        assert!(stderr.contains("error: Label `also_unused` is never used"), "{stderr}");
+        assert!(!stderr.contains("warning:"), "{stderr}");
+    }
+}
+
+#[test]
+fn several_files_each_assembled_on_their_own() {
+    let dir = test_dir("several-files");
+    let good = dir.join("good.basm");
+    let broken = dir.join("broken.basm");
+    let other = dir.join("other.basm");
+    fs::write(&good, "ldi r0, 1\nend: j end\n").unwrap();
+    fs::write(&broken, "add r0, r99\n").unwrap();
+    fs::write(&other, "ldi r1, 2\nend: j end\n").unwrap();
+
+    // The broken file is in the middle, so the one after it is only assembled if it doesn't stop everything
+    let result = run(&[path_str(&good), path_str(&broken), path_str(&other)]);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+
+    assert!(!result.status.success());
+    assert!(stderr.contains(&format!("{}:1:8", path_str(&broken))), "{stderr}");
+    assert!(!stderr.contains("good.basm"), "{stderr}");
+
+    assert_eq!(fs::read(dir.join("good.bin")).unwrap()[..4], [0x20, 0x01, 0xf0, 0x01]);
+    assert_eq!(fs::read(dir.join("other.bin")).unwrap()[..4], [0x21, 0x02, 0xf0, 0x01]);
+    assert!(!dir.join("broken.bin").exists());
+}
+
+#[test]
+fn every_format_writes_output() {
+    use brisc_assembler::formats::Format;
+    use clap::ValueEnum;
+
+    let dir = test_dir("formats");
+    let source = dir.join("prog.basm");
+    fs::write(&source, "ldi r0, 1\nend: j end\n").unwrap();
+
+    for format in Format::value_variants() {
+        let name = format.to_possible_value().unwrap().get_name().to_string();
+        let output = dir.join(format!("prog.{name}"));
+
+        let result = run(&[path_str(&source), "--format", &name, "-o", path_str(&output)]);
+
+        assert!(result.status.success(), "{name}: {}", String::from_utf8_lossy(&result.stderr));
+        assert!(!fs::read(&output).unwrap().is_empty(), "{name}");
+    }
+}
+
+#[test]
+fn no_color_wins_over_forced_color() {
+    let dir = test_dir("no-color");
+    let source = dir.join("bad.basm");
+    fs::write(&source, "add r0, r99\n").unwrap();
+
+    let stderr_with = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_brisc-assembler"))
+            .arg(path_str(&source))
+            .args(args)
+            .env("CLICOLOR_FORCE", "1")
+            .env_remove("NO_COLOR")
+            .env_remove("CLICOLOR")
+            .output()
+            .unwrap();
+
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    // stderr is piped here, so only forcing colors it
+    assert!(stderr_with(&[]).contains("\x1b["));
+    assert!(!stderr_with(&["--no-color"]).contains("\x1b["));
+    assert!(!stderr_with(&["--color", "never"]).contains("\x1b["));
+}
+
+#[test]
+fn depfile_lists_program_and_included_constants() {
+    let dir = test_dir("depfile");
+    let source = dir.join("blink.basm");
+    let hardware = dir.join("hardware.inc");
+    let timing = dir.join("timing.inc");
+    let output = dir.join("blink.bin");
+    let depfile = dir.join("blink.d");
+
+    fs::write(&hardware, ".port LED, 2\n").unwrap();
+    fs::write(&timing, ".set DELAY, 3\n").unwrap();
+    fs::write(&source, "    ldi r0, DELAY\n    out r0, LED\nend:\n    j end\n").unwrap();
+
+    let result = run(&[
+        path_str(&source),
+        "-o",
+        path_str(&output),
+        "--include-constants",
+        path_str(&hardware),
+        "--include-constants",
+        path_str(&timing),
+        "--emit-depfile",
+        path_str(&depfile),
+    ]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+
+    assert_eq!(
+        fs::read_to_string(&depfile).unwrap(),
+        format!(
+            "{}: {} {} {}\n",
+            path_str(&output),
+            path_str(&source),
+            path_str(&hardware),
+            path_str(&timing)
+        )
+    );
+}
+
+#[test]
+fn trace_parse_logs_rules_with_spans() {
+    let dir = test_dir("trace-parse");
+    let source = dir.join("prog.basm");
+    let output = dir.join("prog.bin");
+    fs::write(&source, "end: j end\n").unwrap();
+
+    let traced = run(&[path_str(&source), "-o", path_str(&output), "--trace-parse"]);
+    let stderr = String::from_utf8(traced.stderr).unwrap();
+    let lines: Vec<&str> = stderr.lines().collect();
+
+    assert_eq!(
+        lines,
+        [
+            r#"trace: > parse_line Label "end:" at 1:0 (bytes 0+4)"#,
+            r#"trace:   > parse_instruction Identifier "j" at 1:5 (bytes 5+1)"#,
+            r#"trace:     > parse_operand Identifier "end" at 1:7 (bytes 7+3)"#,
+            "trace:     < parse_operand -> Ok(Label { value: 0, span: Span { index: 7, len: 3 } })",
+            concat!(
+                "trace:   < parse_instruction -> Ok((SingleOperand(J, Label { value: 0, span: ",
+                "Span { index: 7, len: 3 } }), Span { index: 5, len: 5 }))",
+            ),
+            concat!(
+                "trace: < parse_line -> Ok([Label(0), Instruction(SingleOperand(J, Label { value: 0, span: ",
+                "Span { index: 7, len: 3 } }), Span { index: 5, len: 5 }, None)])",
+            ),
+        ]
+    );
+
+    // Nothing is traced without the flag
+    let untraced = run(&[path_str(&source), "-o", path_str(&output)]);
+    assert!(untraced.stderr.is_empty(), "{}", String::from_utf8_lossy(&untraced.stderr));
+}
+
+#[test]
+fn errors_in_constants_file_point_into_it() {
+    let dir = test_dir("constants-error");
+    let source = dir.join("blink.basm");
+    let hardware = dir.join("hardware.inc");
+    fs::write(&hardware, ".port LED, 3\n    nop\n").unwrap();
+    fs::write(&source, "    out r0, LED\n").unwrap();
+
+    let result = run(&[path_str(&source), "--include-constants", path_str(&hardware)]);
+    let stderr = String::from_utf8(result.stderr).unwrap();
+
+    assert!(!result.status.success());
+    assert!(stderr.contains(&format!("--> {}:2:4\n", path_str(&hardware))), "{stderr}");
+}
+
+#[test]
+fn count_registers_table() {
+    let dir = test_dir("count-registers");
+    let source = dir.join("prog.basm");
+    fs::write(
+        &source,
+        "    ldi r0, 1\n    add r1, r0\n    sub r1, r1\n    out r1, 2\n    in r15, 3\nend:\n    j end\n",
+    )
+    .unwrap();
+
+    let result = run(&[path_str(&source), "--count-registers"]);
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    // `sub r1, r1` reads r1 once for each operand
+    assert_eq!(lines[0], "register  reads  writes");
+    assert_eq!(lines[1], "r0            1       1");
+    assert_eq!(lines[2], "r1            4       2");
+    assert_eq!(lines[3], "r2            0       0");
+    assert_eq!(lines[16], "r15           0       1");
+    assert_eq!(lines[17..], ["used: 3/16", "unused: r2, r3, r4, r5, r6, r7, r8, r9, r10, r11, r12, r13, r14"]);
+}
+
+#[test]
+fn self_test_passes_without_any_files() {
+    // An empty directory to run in, so that nothing could be read from or written to it unnoticed
+    let dir = test_dir("self-test");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_brisc-assembler"))
+        .arg("--self-test")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "PASS\n");
+    assert!(output.stderr.is_empty());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+}
+
+#[test]
+fn binary_listing_and_symbols_from_one_run_agree() {
+    let dir = test_dir("multiple-outputs");
+    let source = dir.join("loop.basm");
+    let binary = dir.join("loop.bin");
+    let listing = dir.join("loop.lst");
+    let symbols = dir.join("loop.sym");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    let output = run(&[
+        path_str(&source),
+        "--format",
+        "bin",
+        "-o",
+        path_str(&binary),
+        "--listing",
+        path_str(&listing),
+        "--symbols",
+        path_str(&symbols),
+    ]);
+    assert!(output.status.success());
+
+    let binary = fs::read(&binary).unwrap();
+    let listing = fs::read_to_string(&listing).unwrap();
+    let symbols = fs::read_to_string(&symbols).unwrap();
+
+    // Every address and its bytes in the listing, such as `0008  01 02` from `   8  0008  01 02      .byte 1, 2, 3`
+    let mut listed = Vec::new();
+    let mut label_addresses = Vec::new();
+    let mut pending_labels = Vec::new();
+    for line in listing.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        if let Some(label) = fields.last().and_then(|field| field.strip_suffix(':')) {
+            pending_labels.push(label.to_string());
+            continue;
+        }
+
+        // Lines continuing a `.byte` have no line number
+        let rest = if fields[0].len() == 4 { &fields[..] } else { &fields[1..] };
+        let address = usize::from_str_radix(rest[0], 16).unwrap();
+        let bytes = [rest[1], rest[2]].map(|byte| u8::from_str_radix(byte, 16).unwrap());
+
+        for label in pending_labels.drain(..) {
+            label_addresses.push((label, address));
+        }
+        listed.push((address, bytes));
+    }
+
+    assert_eq!(listed.len() * 2, 14);
+    for (address, bytes) in listed {
+        assert_eq!(binary[address..address + 2], bytes, "at 0x{address:04x}");
+    }
+
+    // Each symbol is at the address of the line after its label in the listing
+    assert_eq!(symbols.lines().count(), label_addresses.len());
+    for (label, address) in label_addresses {
+        assert!(symbols.lines().any(|line| line == format!("{address:04x} {label}")), "{label} at {address:04x}");
+    }
+}
+
+#[test]
+fn json_format_prints_a_summary() {
+    let dir = test_dir("json-summary");
+    let source = dir.join("loop.basm");
+    let output = dir.join("loop.bin");
+    let binary = dir.join("loop-bin.bin");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    let result = run(&[path_str(&source), "--format", "json", "-o", path_str(&output)]);
+
+    // 5 instructions and 2 slots of data
+    assert!(result.status.success());
+    assert_eq!(
+        String::from_utf8(result.stdout).unwrap(),
+        format!(
+            "{{\"ok\":true,\"instruction_count\":7,\"byte_len\":14,\"output_path\":\"{}\"}}\n",
+            path_str(&output)
+        )
+    );
+
+    // The same bytes are written as with --format bin
+    assert!(run(&[path_str(&source), "-o", path_str(&binary)]).status.success());
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&binary).unwrap());
+}
+
+#[test]
+fn json_summary_of_an_empty_program() {
+    let dir = test_dir("json-summary-empty");
+    let source = dir.join("empty.basm");
+    let output = dir.join("empty.bin");
+    fs::write(&source, "").unwrap();
+
+    let result = run(&[path_str(&source), "--format", "json", "-o", path_str(&output)]);
+
+    assert!(result.status.success());
+    assert_eq!(
+        String::from_utf8(result.stdout).unwrap(),
+        format!(
+            "{{\"ok\":true,\"instruction_count\":0,\"byte_len\":0,\"output_path\":\"{}\"}}\n",
+            path_str(&output)
+        )
+    );
+}
+
+#[test]
+fn json_format_cannot_be_used_with_debug() {
+    let dir = test_dir("json-summary-debug");
+    let source = dir.join("loop.basm");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    let result = run(&[path_str(&source), "--format", "json", "--debug"]);
+
+    assert!(!result.status.success());
+    assert!(result.stdout.is_empty());
+    assert!(!dir.join("loop.bin").exists());
+}
+
+#[test]
+fn comment_char_option() {
+    let dir = test_dir("comment-char");
+    let source = dir.join("hash.basm");
+    let output = dir.join("hash.bin");
+    fs::write(&source, "ldi r0, 1 # one\n; still a comment\nend: j end # forever\n").unwrap();
+
+    let result = run(&[path_str(&source), "--comment-char", "#", "--no-pad", "-o", path_str(&output)]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert_eq!(fs::read(&output).unwrap(), [0x20, 0x01, 0xf0, 0x01]);
+
+    for (value, message) in [
+        (",", "`,` can't start a comment, since it already starts something else"),
+        ("x", "`x` can't start a comment, since it already starts something else"),
+        ("//", "expected a single character, found `//`"),
+    ] {
+        let result = run(&[path_str(&source), "--comment-char", value]);
+
+        assert!(!result.status.success(), "{value}");
+        assert!(String::from_utf8(result.stderr).unwrap().contains(message), "{value}");
+    }
+}
+
+#[test]
+fn straight_line_length_with_cycle_costs() {
+    let dir = test_dir("straight-line-length");
+    let source = dir.join("loop.basm");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    // Up to the `j loop`, as `jz r0, done` isn't taken
+    let result = run(&[path_str(&source), "--straight-line-length", "--cycle-cost", "j=2"]);
+
+    assert!(result.status.success());
+    assert_eq!(String::from_utf8(result.stdout).unwrap(), "4 instructions, 5 cycles\n");
+    assert!(!dir.join("loop.bin").exists());
+}
+
+#[test]
+fn only_nibble_4_is_unused() {
+    let output = run(&["--list-unused-opcodes"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0x4\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn print_hash_is_the_same_across_runs() {
+    use brisc_assembler::formats::fingerprint;
+
+    let dir = test_dir("print-hash");
+    let source = dir.join("loop.basm");
+    let output = dir.join("loop.bin");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    let hash_with = |args: &[&str]| {
+        let result = run(&[&[path_str(&source), "-o", path_str(&output)], args].concat());
+        assert!(result.status.success());
+
+        String::from_utf8(result.stdout).unwrap()
+    };
+
+    let first = hash_with(&["--print-hash"]);
+    assert_eq!(hash_with(&["--print-hash"]), first);
+
+    // Of the 14 bytes of the program without its padding, unless asked for
+    let written = fs::read(&output).unwrap();
+    assert_eq!(first, format!("{:016x}\n", fingerprint(&written[..14])));
+    assert_eq!(
+        hash_with(&["--print-hash", "--hash-padded"]),
+        format!("{:016x}\n", fingerprint(&written))
+    );
+
+    // And not padding the output doesn't change it
+    assert_eq!(hash_with(&["--print-hash", "--no-pad"]), first);
+}
+
+#[test]
+fn failing_to_write_an_extra_file_fails() {
+    let dir = test_dir("aux-write-error");
+    let source = dir.join("loop.basm");
+    let output = dir.join("loop.bin");
+    let missing = dir.join("missing");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    for flag in ["--listing", "--symbols", "--map", "--emit-meta", "--emit-depfile"] {
+        let path = missing.join("out.txt");
+
+        let result = run(&[path_str(&source), "-o", path_str(&output), flag, path_str(&path)]);
+
+        assert!(!result.status.success(), "{flag}");
+        let stderr = String::from_utf8(result.stderr).unwrap();
+        assert!(stderr.contains(&format!("File write error: {}: ", path_str(&path))), "{flag}: {stderr}");
+    }
+}
+
+#[test]
+fn print_isa_as_text_or_json() {
+    let text = run(&["--print-isa"]);
+    assert!(text.status.success());
+    let text = String::from_utf8(text.stdout).unwrap();
+    assert!(text.starts_with("mnemonic  opcode  encoding          operands\nnop       0x0     no-operand\n"), "{text}");
+
+    let json = run(&["--print-isa", "--format", "json"]);
+    assert!(json.status.success());
+    let json = String::from_utf8(json.stdout).unwrap();
+    assert!(
+        json.starts_with("[{\"mnemonic\":\"nop\",\"opcode\":0,\"encoding\":\"no-operand\",\"operands\":[]},"),
+        "{json}"
+    );
+    assert!(json.trim_end().ends_with(']'), "{json}");
+
+    let grid = run(&["--print-isa", "--format", "grid"]);
+    assert!(!grid.status.success());
+    assert!(grid.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(grid.stderr).unwrap(),
+        "--print-isa can only be printed as text, the default, or with --format json\n"
+    );
+}
+
+#[test]
+fn image_larger_than_memory_is_padded_with_zeros() {
+    let dir = test_dir("image-size-larger");
+    let source = dir.join("loop.basm");
+    let output = dir.join("loop.bin");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    let result = run(&[path_str(&source), "--image-size", "128", "-o", path_str(&output)]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+
+    // The 14 bytes of the program, and zeros past the 64 bytes of instruction memory up to the size of the image
+    let bytes = fs::read(&output).unwrap();
+    assert_eq!(bytes.len(), 128);
+    assert_eq!(bytes[..4], [0x20, 0x01, 0x10, 0x00]);
+    assert!(bytes[14..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn image_smaller_than_the_program_fails() {
+    let dir = test_dir("image-size-smaller");
+    let source = dir.join("loop.basm");
+    let output = dir.join("loop.bin");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    let result = run(&[path_str(&source), "--image-size", "8", "-o", path_str(&output), "--color", "never"]);
+
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("Output of 14 bytes doesn't fit in an image of 8 bytes"), "{stderr}");
+    assert!(!output.exists());
+
+    // A program that fits exactly is padded with nothing
+    let result = run(&[path_str(&source), "--image-size", "14", "-o", path_str(&output)]);
+    assert!(result.status.success());
+    assert_eq!(fs::read(&output).unwrap().len(), 14);
+}
+
+#[test]
+fn json_format_with_print_hash_is_only_json() {
+    use brisc_assembler::formats::fingerprint;
+
+    let dir = test_dir("json-summary-hash");
+    let source = dir.join("loop.basm");
+    let output = dir.join("loop.bin");
+    fs::write(&source, LOOP_PROGRAM).unwrap();
+
+    let result = run(&[path_str(&source), "--format", "json", "--print-hash", "-o", path_str(&output)]);
+    assert!(result.status.success());
+
+    // A single object, with the hash of the program as --print-hash would print it
+    let hash = fingerprint(&fs::read(&output).unwrap()[..14]);
+    assert_eq!(
+        String::from_utf8(result.stdout).unwrap(),
+        format!(
+            "{{\"ok\":true,\"instruction_count\":7,\"byte_len\":14,\"output_path\":\"{}\",\"hash\":\"{hash:016x}\"}}\n",
+            path_str(&output)
+        )
+    );
+}
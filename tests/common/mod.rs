@@ -0,0 +1,32 @@
+// Each test crate only uses some of these
+#![allow(dead_code)]
+
+use brisc_assembler::{assemble, errors::Diagnostic, sources::SourceManager, AssembleOptions, AssembleResult};
+
+pub fn assemble_source(source: &str) -> Result<AssembleResult, Vec<Diagnostic>> {
+    assemble_source_with(source, &AssembleOptions::default())
+}
+
+pub fn assemble_source_with(source: &str, options: &AssembleOptions) -> Result<AssembleResult, Vec<Diagnostic>> {
+    let source_manager = SourceManager::new(source, String::from("test.basm"));
+
+    assemble(&source_manager, options)
+}
+
+/// Assembles a source that is expected to fail, returning its only diagnostic
+pub fn assemble_error(source: &str) -> Diagnostic {
+    let mut diagnostics = assemble_source(source).expect_err("the source should not assemble");
+
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    diagnostics.remove(0)
+}
+
+/// The span of the first occurrence of `text` in `source`
+pub fn span_of(source: &str, text: &str) -> brisc_assembler::lexer::Span {
+    let index = source.find(text).unwrap_or_else(|| panic!("`{text}` is not in the source"));
+
+    brisc_assembler::lexer::Span {
+        index: index as u32,
+        len: text.len() as u32,
+    }
+}
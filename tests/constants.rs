@@ -0,0 +1,88 @@
+mod common;
+
+use brisc_assembler::{errors::Diagnostic, lexer::Span, parse_constants, sources::SourceManager, AssembleOptions};
+use common::{assemble_error, assemble_source, span_of};
+
+#[test]
+fn set_redefined_mid_file() {
+    let source = "\
+.set COUNT, 1
+    ldi r0, COUNT
+.set COUNT, 2
+    ldi r1, COUNT
+    out r1, COUNT
+";
+
+    // Each use takes the value in effect at its line
+    assert_eq!(assemble_source(source).unwrap().bytes, [0x20, 0x01, 0x21, 0x02, 0xc1, 0x20]);
+}
+
+#[test]
+fn set_used_before_it_is_defined() {
+    let source = "    ldi r0, COUNT\n.set COUNT, 1\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert!(diagnostic.label().contains("`COUNT`, which isn't a constant"), "{}", diagnostic.label());
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, "COUNT")));
+}
+
+#[test]
+fn constant_port_out_of_range() {
+    let source = ".set P, 20\n    out r0, P\n";
+
+    let diagnostic = assemble_error(source);
+
+    // Pointed at where the constant is used, rather than where it is set
+    assert_eq!(diagnostic.label(), "Port must be in the range of 0-15, found `P` (which is 20)");
+    let use_site = span_of(source, "P\n");
+    assert_eq!(diagnostic.label_span(), Some(Span { len: 1, ..use_site }));
+}
+
+#[test]
+fn constant_port_in_range() {
+    let result = assemble_source(".set P, 5\n    out r0, P\n").unwrap();
+
+    assert_eq!(result.bytes, [0xc0, 0x50]);
+}
+
+#[test]
+fn named_port_out_of_range() {
+    let source = ".port P, 20\n    out r0, P\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(diagnostic.label(), "Port must be in the range of 0-15, found `20`");
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, "20")));
+}
+
+fn shared_constants(source: &str) -> Result<brisc_assembler::parser::ConstantDefinitions, Vec<Diagnostic>> {
+    let source_manager = SourceManager::new(source, String::from("hardware.inc"));
+
+    parse_constants(&source_manager, &AssembleOptions::default())
+}
+
+#[test]
+fn port_from_a_shared_constants_file() {
+    let constants = shared_constants(".port LED, 3\n.set ON, 1\n").unwrap();
+    let options = AssembleOptions {
+        constants,
+        ..Default::default()
+    };
+
+    let result = common::assemble_source_with("    ldi r0, ON\n    out r0, LED\nend:\n    j end\n", &options).unwrap();
+
+    assert_eq!(result.bytes, [0x20, 0x01, 0xc0, 0x30, 0xf0, 0x02]);
+    // What a shared file defines isn't expected to all be used by every program
+    assert!(result.warnings.is_empty());
+}
+
+#[test]
+fn instruction_in_a_shared_constants_file() {
+    let source = ".port LED, 3\nnop\n";
+
+    let diagnostics = shared_constants(source).unwrap_err();
+
+    assert_eq!(diagnostics[0].label(), "A constants file can only use `.set` and `.port`, found `nop`");
+    assert_eq!(diagnostics[0].label_span(), Some(span_of(source, "nop")));
+}
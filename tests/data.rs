@@ -0,0 +1,117 @@
+mod common;
+
+use brisc_assembler::{generator::Endianness, AssembleOptions};
+use common::{assemble_source, assemble_source_with};
+
+#[test]
+fn db_is_the_same_as_byte() {
+    let db = assemble_source("db 1, 2, 3\nafter: j after\n").unwrap();
+    let byte = assemble_source(".byte 1, 2, 3\nafter: j after\n").unwrap();
+
+    // Padded to a whole slot, so the label after it is at slot 2
+    assert_eq!(db.bytes, [0x01, 0x02, 0x03, 0x00, 0xf0, 0x02]);
+    assert_eq!(db.bytes, byte.bytes);
+    assert_eq!(db.symbols[0].value, 2);
+}
+
+#[test]
+fn dw_follows_endianness() {
+    let big = assemble_source("dw 0x1234\n").unwrap();
+    let little = assemble_source_with(
+        "dw 0x1234\n",
+        &AssembleOptions {
+            endianness: Endianness::Little,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(big.bytes, [0x12, 0x34]);
+    assert_eq!(little.bytes, [0x34, 0x12]);
+    assert_eq!(assemble_source("dw 0x1234\n").unwrap().bytes, assemble_source(".word 0x1234\n").unwrap().bytes);
+}
+
+#[test]
+fn data_counts_towards_memory() {
+    let values = vec!["0"; 32].join(", ");
+    let source = format!("dw {values}\nnop\n");
+
+    assert!(assemble_source(&source).is_err());
+}
+
+#[test]
+fn checksum_here_wraps_around() {
+    let result = assemble_source("    ldi r0, 100\n    ldi r1, 120\n    .checksum_here\n").unwrap();
+
+    // 0x20 + 0x64 + 0x21 + 0x78 = 0x11d, with the slot padded like a single `.byte`
+    assert_eq!(result.bytes, [0x20, 0x64, 0x21, 0x78, 0x1d, 0x00]);
+}
+
+#[test]
+fn checksum_here_includes_earlier_checksums() {
+    let result = assemble_source(".checksum_here\n.byte 0xff\n.checksum_here\n").unwrap();
+
+    // An empty sum is 0, and the second sum is 0 + 0 + 0xff + 0
+    assert_eq!(result.bytes, [0x00, 0x00, 0xff, 0x00, 0xff, 0x00]);
+}
+
+#[test]
+fn byte_elements_mixing_literals_constants_and_expressions() {
+    let source = ".set LED, 0x05\n.set BASE, 0x10\n    .byte LED | 0x80, (BASE + 1), 7, LED * 2, -1\n";
+
+    let result = assemble_source(source).unwrap();
+
+    assert_eq!(result.bytes, [0x85, 0x11, 0x07, 0x0a, 0xff, 0x00]);
+}
+
+#[test]
+fn word_elements_from_expressions() {
+    assert_eq!(assemble_source(".word 0x1234 + 1, -1\n").unwrap().bytes, [0x12, 0x35, 0xff, 0xff]);
+}
+
+#[test]
+fn element_out_of_range_points_at_that_element() {
+    let source = ".set BASE, 0x10\n    .byte 1, BASE * 20, 3\n";
+
+    let diagnostics = assemble_source(source).unwrap_err();
+
+    assert_eq!(diagnostics[0].label(), "Value `BASE * 20` (which is 320) does not fit in a byte (-128 to 255)");
+    assert_eq!(diagnostics[0].label_span(), Some(common::span_of(source, "BASE * 20")));
+}
+
+#[test]
+fn element_with_a_label_out_of_range() {
+    // Only checked once `x` has a value
+    let source = "x:  .byte 1, x + 300\n";
+
+    let diagnostics = assemble_source(source).unwrap_err();
+
+    assert_eq!(diagnostics[0].label(), "Value `x + 300` (which is 300) does not fit in a byte (-128 to 255)");
+    assert_eq!(diagnostics[0].label_span(), Some(common::span_of(source, "x + 300")));
+}
+
+#[test]
+fn label_addresses_as_data() {
+    // `end` is referred to before it is defined
+    let result = assemble_source("loop:\n    nop\n    .byte loop, end\nend:\n    j end\n").unwrap();
+
+    assert_eq!(result.bytes, [0x00, 0x00, 0x00, 0x02, 0xf0, 0x02]);
+}
+
+#[test]
+fn label_address_as_word() {
+    let result = assemble_source("    .word handler\nhandler:\n    j handler\n").unwrap();
+
+    assert_eq!(result.bytes, [0x00, 0x01, 0xf0, 0x01]);
+}
+
+#[test]
+fn label_address_overflowing_a_byte() {
+    // A label is at most slot 31, so it only overflows a byte once worked into something larger
+    let source = "    .space 20\nfar:\n    nop\n    .byte far * 30\n";
+
+    let diagnostics = assemble_source(source).unwrap_err();
+
+    assert_eq!(diagnostics[0].label(), "Value `far * 30` (which is 300) does not fit in a byte (-128 to 255)");
+    assert_eq!(diagnostics[0].label_span(), Some(common::span_of(source, "far * 30")));
+}
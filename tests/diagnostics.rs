@@ -0,0 +1,203 @@
+mod common;
+
+use brisc_assembler::lexer::Span;
+use common::assemble_error;
+
+#[test]
+fn missing_second_operand_message() {
+    for source in ["add r0,\n", "add r0,"] {
+        let diagnostic = assemble_error(source);
+
+        assert_eq!(diagnostic.label(), "Missing second operand for `add`");
+        assert_eq!(diagnostic.label_span(), Some(Span { index: 7, len: 0 }));
+    }
+}
+
+#[test]
+fn label_and_instruction_on_last_line_without_newline() {
+    let result = common::assemble_source("j end\nend: j end").unwrap();
+
+    assert_eq!(result.bytes, [0xf0, 0x01, 0xf0, 0x01]);
+
+    let result = common::assemble_source("start: j start\nend:").unwrap_err();
+
+    assert_eq!(result[0].label_span(), Some(common::span_of("start: j start\nend:", "end:")));
+}
+
+#[test]
+fn no_such_register_message() {
+    let diagnostic = assemble_error("add r0, r99\n");
+
+    assert_eq!(diagnostic.label(), "No such register `r99` (valid registers are r0 to r15)");
+}
+
+#[test]
+fn comma_in_place_of_an_operand() {
+    let diagnostic = assemble_error("add , r1\n");
+
+    assert_eq!(diagnostic.label(), "Expected the first operand of `add`, found `,`");
+    assert_eq!(diagnostic.label_span(), Some(Span { index: 4, len: 1 }));
+
+    let diagnostic = assemble_error("add r0, , r1\n");
+
+    assert_eq!(diagnostic.label(), "Expected the second operand of `add` after `,`, found another `,`");
+    assert_eq!(diagnostic.label_span(), Some(Span { index: 8, len: 1 }));
+}
+
+#[test]
+fn label_starting_with_a_digit() {
+    let diagnostic = assemble_error("1loop: nop\n");
+
+    assert_eq!(diagnostic.label(), "Invalid label `1loop:`, labels cannot start with a digit");
+    assert_eq!(diagnostic.label_span(), Some(Span { index: 0, len: 6 }));
+}
+
+#[test]
+fn wrong_kind_of_operand_names_instruction_and_position() {
+    let cases = [
+        ("add 5, r1\n", "5", "`add` expects a register as its first operand, found `5`"),
+        ("add r0, 5\n", "5", "`add` expects a register as its second operand, found `5`"),
+        ("out 3, 1\n", "3", "`out` expects a register as its first operand, found `3`"),
+    ];
+
+    for (source, found, label) in cases {
+        let diagnostic = assemble_error(source);
+
+        assert_eq!(diagnostic.label(), label);
+        assert_eq!(diagnostic.label_span(), Some(common::span_of(source, found)));
+    }
+}
+
+#[test]
+fn register_where_a_label_is_expected() {
+    let cases = [
+        ("j r0\n", "r0", "`j` expects an integer or a label as its first operand, found register `r0`"),
+        ("jz r1, r2\n", "r2", "`jz` expects an integer or a label as its second operand, found register `r2`"),
+    ];
+
+    for (source, register, label) in cases {
+        let diagnostic = assemble_error(source);
+
+        assert_eq!(diagnostic.label(), label);
+        assert_eq!(diagnostic.label_span(), Some(common::span_of(source, register)));
+    }
+}
+
+#[test]
+fn label_named_like_a_register() {
+    // A label can still be named like a register, as long as it is defined
+    let result = common::assemble_source("r0: nop\n    j r0\n").unwrap();
+
+    assert_eq!(result.bytes, [0x00, 0x00, 0xf0, 0x00]);
+}
+
+#[test]
+fn comma_at_start_of_line() {
+    const LABEL: &str =
+        "Unexpected `,` at the start of a statement, the operand after it may belong on the line above";
+
+    for source in [", r1\n", "    inv r0\n    , r1\n"] {
+        let diagnostic = assemble_error(source);
+
+        assert_eq!(diagnostic.label(), LABEL);
+        assert_eq!(diagnostic.label_span(), Some(common::span_of(source, ",")));
+    }
+}
+
+#[test]
+fn every_invalid_token_reported_once() {
+    let source = "    add r0, $\n    ? nop\n    add r1, $\n";
+
+    let diagnostics = common::assemble_source(source).unwrap_err();
+    let found: Vec<(&str, Option<Span>)> = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.label().as_str(), diagnostic.label_span()))
+        .collect();
+
+    // The two `$` have the same message, but are kept apart by where they are
+    assert_eq!(
+        found,
+        [
+            ("Invalid token found `$`", Some(Span { index: 12, len: 1 })),
+            ("Invalid token found `?`", Some(Span { index: 18, len: 1 })),
+            ("Invalid token found `$`", Some(Span { index: 36, len: 1 })),
+        ]
+    );
+}
+
+#[test]
+fn label_where_only_an_integer_is_allowed() {
+    let source = "loop:\n    ldi r0, loop\n    j loop\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(
+        diagnostic.label(),
+        "`ldi` expects an integer as its second operand, found `loop`, which isn't a constant, and labels can't be used there"
+    );
+    // The `loop` operand, rather than the label definition
+    let operand = common::span_of(source, "r0, loop");
+    assert_eq!(diagnostic.label_span(), Some(Span { index: operand.index + 4, len: 4 }));
+}
+
+#[test]
+fn register_where_only_an_integer_is_allowed() {
+    // Not mistaken for a label, since it looks like a register
+    let diagnostic = assemble_error("    ldi r0, r1\n");
+
+    assert_eq!(diagnostic.label(), "`ldi` expects an integer as its second operand, found `r1`");
+}
+
+#[test]
+fn label_where_a_jump_destination_is_allowed() {
+    let result = common::assemble_source("    nop\nloop:\n    jz r0, loop\n    j loop\n").unwrap();
+
+    assert_eq!(result.bytes, [0x00, 0x00, 0xd0, 0x01, 0xf0, 0x01]);
+}
+
+#[test]
+fn collecting_emitter_gets_what_assemble_returns() {
+    use brisc_assembler::{
+        assemble, assemble_with_emitter, errors::CollectingEmitter, sources::SourceManager, AssembleOptions,
+    };
+
+    // The `.global` of a label that's never defined is a warning, and the jump to it an error. Warnings are only part
+    // of a result that assembled, so here the error is what both report.
+    let failing = ".global nope\n    ldi r0, 1\n    j nope\n";
+    // Without the error, both report the warning along with the result
+    let warning_only = ".global nope\nend:\n    j end\n";
+
+    for source in [failing, warning_only] {
+        let source_manager = SourceManager::new(source, String::from("test.basm"));
+        let options = AssembleOptions::default();
+        let mut emitter = CollectingEmitter::new();
+
+        let result = assemble_with_emitter(&source_manager, &options, &mut emitter);
+
+        let expected = match assemble(&source_manager, &options) {
+            Ok(assembled) => {
+                assert_eq!(result.unwrap().bytes, assembled.bytes);
+                assembled.warnings
+            }
+            Err(diagnostics) => {
+                assert!(result.is_none());
+                diagnostics
+            }
+        };
+        assert!(!expected.is_empty(), "{source:?}");
+        assert_eq!(emitter.into_diagnostics(), expected, "{source:?}");
+    }
+}
+
+#[test]
+fn shift_by_an_integer() {
+    for (source, mnemonic) in [("    sr r0, 3\n", "sr"), ("    sl r0, 3\n", "sl")] {
+        let diagnostic = assemble_error(source);
+
+        assert_eq!(
+            *diagnostic.label(),
+            format!("`{mnemonic}` can only shift by the amount in a register, load it into one with `ldi` first")
+        );
+        assert_eq!(diagnostic.label_span(), Some(common::span_of(source, "3")));
+    }
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use brisc_assembler::{
+    disassembler::{
+        disassemble, validate_image, write_disassembly, DisassemblyImmediates, DisassemblyOptions, DisassemblyStop,
+        ImageIssue,
+    },
+    AssembleOptions,
+};
+use common::{assemble_source, assemble_source_with};
+
+const PROGRAM: &str = "\
+    ldi r13, 5
+    ldi r1, -1
+loop:
+    in r2, 3
+    add r2, r1
+    jz r2, done
+    out r2, 4
+    j loop
+done:
+    j done
+";
+
+fn disassembly_of(bytes: &[u8], options: &DisassemblyOptions) -> String {
+    let mut out = Vec::new();
+    write_disassembly(&mut out, &disassemble(bytes), options).unwrap();
+
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn lines_start_with_address_and_bytes() {
+    let bytes = assemble_source(PROGRAM).unwrap().bytes;
+
+    let disassembly = disassembly_of(&bytes, &DisassemblyOptions::default());
+
+    assert_eq!(
+        disassembly.lines().take(2).collect::<Vec<_>>(),
+        ["0x00: 2d 05    ldi r13, 5", "0x02: 21 ff    ldi r1, -1"]
+    );
+    assert!(disassembly.contains("0x08: d2 07    jz r2, 7\n"), "{disassembly}");
+}
+
+#[test]
+fn labelled_disassembly_assembles_to_the_same_bytes() {
+    let bytes = assemble_source(PROGRAM).unwrap().bytes;
+
+    let options = DisassemblyOptions {
+        labels: true,
+        ..Default::default()
+    };
+    let disassembly = disassembly_of(&bytes, &options);
+
+    assert!(disassembly.contains("L_04:\n    in r2, 3"), "{disassembly}");
+    assert!(disassembly.contains("    jz r2, L_0E"), "{disassembly}");
+
+    let reassembled = assemble_source(&disassembly).unwrap().bytes;
+
+    assert_eq!(reassembled, bytes);
+}
+
+#[test]
+fn assembled_program_is_a_valid_image() {
+    let bytes = assemble_source(PROGRAM).unwrap().bytes;
+
+    assert_eq!(validate_image(&bytes), Ok(()));
+    // Padding is all `nop`, which is valid too
+    assert_eq!(validate_image(&[0; 64]), Ok(()));
+}
+
+#[test]
+fn corrupted_images() {
+    use brisc_assembler::ast::Opcode;
+
+    let cases: [(&[u8], Vec<ImageIssue>); 5] = [
+        (&[0x00, 0x00, 0x41, 0x00], vec![ImageIssue::UnknownOpcode { address: 2, value: 0x4 }]),
+        (&[0xc1, 0x21], vec![ImageIssue::UnusedBitsSet { address: 0, opcode: Opcode::Out }]),
+        (&[0xf0, 0x20], vec![ImageIssue::JumpOutOfRange { address: 0, destination: 0x20 }]),
+        (&[0x00, 0x00, 0x00], vec![ImageIssue::OddLength(3)]),
+        (&[0; 66], vec![ImageIssue::TooLong(66)]),
+    ];
+
+    for (bytes, issues) in cases {
+        assert_eq!(validate_image(bytes), Err(issues), "{bytes:02x?}");
+    }
+}
+
+#[test]
+fn every_issue_is_reported() {
+    let issues = validate_image(&[0x41, 0x00, 0xf0, 0x40, 0x00]).unwrap_err();
+
+    assert_eq!(issues.len(), 3);
+}
+
+/// The program as written to a `.bin`, padded to the whole of instruction memory
+fn padded_image() -> Vec<u8> {
+    let mut bytes = assemble_source(PROGRAM).unwrap().bytes;
+    bytes.resize(64, 0);
+
+    bytes
+}
+
+#[test]
+fn padding_is_left_out_by_default() {
+    let disassembly = disassembly_of(&padded_image(), &DisassemblyOptions::default());
+
+    assert_eq!(disassembly.lines().count(), 8);
+    assert!(disassembly.ends_with("0x0E: f0 07    j 7\n"), "{disassembly}");
+}
+
+#[test]
+fn full_disassembly_shows_every_slot() {
+    let options = DisassemblyOptions {
+        stop: DisassemblyStop::Full,
+        ..Default::default()
+    };
+
+    let disassembly = disassembly_of(&padded_image(), &options);
+
+    assert_eq!(disassembly.lines().count(), 32);
+    assert!(disassembly.ends_with("0x3E: 00 00    nop\n"), "{disassembly}");
+}
+
+#[test]
+fn nop_inside_the_program_is_kept() {
+    let bytes = [0x00, 0x00, 0xf0, 0x00, 0x00, 0x00];
+
+    let disassembly = disassembly_of(&bytes, &DisassemblyOptions::default());
+
+    assert_eq!(disassembly, "0x00: 00 00    nop\n0x02: f0 00    j 0\n");
+}
+
+fn with_immediates(immediates: DisassemblyImmediates) -> DisassemblyOptions {
+    DisassemblyOptions {
+        immediates,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn ldi_immediate_in_each_mode() {
+    // `ldi r0, 0xff`, then a port and a jump destination that don't change with the mode
+    let bytes = [0x20, 0xff, 0xc0, 0xf0, 0xf0, 0x1f];
+
+    for (immediates, ldi) in [
+        (DisassemblyImmediates::Signed, "ldi r0, -1"),
+        (DisassemblyImmediates::Unsigned, "ldi r0, 255"),
+        (DisassemblyImmediates::Hex, "ldi r0, 0xff"),
+    ] {
+        let disassembly = disassembly_of(&bytes, &with_immediates(immediates));
+
+        assert_eq!(
+            disassembly.lines().collect::<Vec<_>>(),
+            [
+                format!("0x00: 20 ff    {ldi}"),
+                String::from("0x02: c0 f0    out r0, 15"),
+                String::from("0x04: f0 1f    j 31"),
+            ],
+            "{immediates:?}"
+        );
+    }
+}
+
+#[test]
+fn every_mode_assembles_to_the_same_bytes() {
+    let bytes = assemble_source(PROGRAM).unwrap().bytes;
+    let relax = AssembleOptions {
+        relax: true,
+        ..Default::default()
+    };
+
+    for immediates in [DisassemblyImmediates::Signed, DisassemblyImmediates::Unsigned, DisassemblyImmediates::Hex] {
+        let options = DisassemblyOptions {
+            labels: true,
+            immediates,
+            ..Default::default()
+        };
+        let disassembly = disassembly_of(&bytes, &options);
+
+        assert_eq!(assemble_source_with(&disassembly, &relax).unwrap().bytes, bytes, "{immediates:?}");
+    }
+}
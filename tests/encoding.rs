@@ -0,0 +1,62 @@
+mod common;
+
+use common::assemble_source;
+
+fn encode(source: &str) -> Vec<u8> {
+    assemble_source(source).unwrap().bytes
+}
+
+#[test]
+fn jump_puts_opcode_in_high_nibble_and_destination_in_second_byte() {
+    // The register nibble of `j` isn't used, and is always zero
+    assert_eq!(encode("j 3\n"), [0xf0, 0x03]);
+    assert_eq!(encode("j 0\n"), [0xf0, 0x00]);
+}
+
+#[test]
+fn conditional_jump_puts_register_in_low_nibble() {
+    assert_eq!(encode("jz r2, 3\n"), [0xd2, 0x03]);
+    assert_eq!(encode("jlt r15, 31\n"), [0xef, 0x1f]);
+}
+
+#[test]
+fn nop_is_all_zeros() {
+    assert_eq!(encode("nop\n"), [0x00, 0x00]);
+}
+
+#[test]
+fn inv_leaves_second_byte_zero() {
+    assert_eq!(encode("inv r5\n"), [0x75, 0x00]);
+}
+
+#[test]
+fn trailing_comment_after_every_form() {
+    let forms = [
+        "nop",
+        "inv r5",
+        "add r0, r1",
+        "ldi r3, 7",
+        "out r2, 1",
+        "loop: j loop",
+    ];
+
+    for form in forms {
+        let commented = format!("{form} ; the comment\n");
+
+        assert_eq!(encode(&commented), encode(&format!("{form}\n")), "{commented:?}");
+    }
+}
+
+#[test]
+fn low_nibble_of_second_byte_is_zero() {
+    assert_eq!(encode("add r0, r15\n"), [0x10, 0xf0]);
+    assert_eq!(encode("out r1, 15\n"), [0xc1, 0xf0]);
+}
+
+#[test]
+fn shift_by_a_register() {
+    // The amount to shift by is the register in the high nibble of the second byte
+    assert_eq!(encode("sr r0, r1\n"), [0x90, 0x10]);
+    assert_eq!(encode("sl r0, r1\n"), [0xa0, 0x10]);
+    assert_eq!(encode("sl r7, r12\n"), [0xa7, 0xc0]);
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use brisc_assembler::lexer::Span;
+use common::{assemble_error, assemble_source, span_of};
+
+fn immediate_of(operand: &str) -> u8 {
+    let source = format!(".set BASE, 10\n    ldi r0, {operand}\n");
+
+    assemble_source(&source).unwrap().bytes[1]
+}
+
+#[test]
+fn sign_before_a_number_is_unary() {
+    assert_eq!(immediate_of("-3"), 0xfd);
+    assert_eq!(immediate_of("+3"), 3);
+    assert_eq!(immediate_of("5 - -3"), 8);
+}
+
+#[test]
+fn minus_after_a_value_is_binary() {
+    assert_eq!(immediate_of("BASE-3"), 7);
+    assert_eq!(immediate_of("BASE - 3"), 7);
+    assert_eq!(immediate_of("5-3"), 2);
+}
+
+#[test]
+fn immediate_expressions() {
+    assert_eq!(immediate_of("2*3"), 6);
+    assert_eq!(immediate_of("BASE * 2 + 1"), 21);
+    assert_eq!(immediate_of("(1 + 2) * 4"), 12);
+}
+
+#[test]
+fn jump_destination_expression() {
+    assert_eq!(assemble_source("jz r0, 2 + 1\n").unwrap().bytes, [0xd0, 0x03]);
+}
+
+#[test]
+fn immediate_expression_out_of_range() {
+    let source = "    ldi r0, 100 * 2\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(
+        diagnostic.label(),
+        "Value `100 * 2` (which is 200) is out of range for an 8-bit signed integer value (-128 to 127)"
+    );
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, "100 * 2")));
+}
+
+#[test]
+fn label_in_immediate_expression() {
+    let source = "x:  ldi r0, x + 1\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(
+        diagnostic.label(),
+        "`ldi` expects an integer as its second operand, found `x`, which isn't a constant, and labels can't be used there"
+    );
+    // Only the label is pointed at, rather than the whole expression
+    let expression = span_of(source, "x + 1");
+    assert_eq!(diagnostic.label_span(), Some(Span { len: 1, ..expression }));
+}
+
+#[test]
+fn sign_apart_from_its_number() {
+    let source = "    ldi r0, - 3\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(diagnostic.label(), "Expected an integer directly after `-`");
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, "-")));
+}
+
+const OVERFLOW_LABEL: &str = "is too large to work out, as it doesn't fit in a 64-bit signed integer";
+
+#[test]
+fn addition_overflow() {
+    let source = ".byte 0x7fffffffffffffff + 1 + 0x7fffffffffffffff + 2\n";
+
+    let diagnostic = assemble_error(source);
+
+    // The first addition already overflows, rather than wrapping around to be cancelled out by the second
+    assert_eq!(*diagnostic.label(), format!("`0x7fffffffffffffff + 1` {OVERFLOW_LABEL}"));
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, "0x7fffffffffffffff + 1")));
+}
+
+#[test]
+fn subtraction_overflow() {
+    let source = "    ldi r0, -0x7fffffffffffffff - 2\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(*diagnostic.label(), format!("`-0x7fffffffffffffff - 2` {OVERFLOW_LABEL}"));
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, "-0x7fffffffffffffff - 2")));
+}
+
+#[test]
+fn overflow_with_a_label() {
+    // Only known to overflow once `x` has a value, which is 1
+    let source = "    nop\nx:  .byte x + 0x7fffffffffffffff\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(*diagnostic.label(), format!("`x + 0x7fffffffffffffff` {OVERFLOW_LABEL}"));
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, "x + 0x7fffffffffffffff")));
+}
+
+#[test]
+fn multiplication_overflow() {
+    let source = ".byte 65536*65536*65536*65536\n";
+
+    let diagnostic = assemble_error(source);
+
+    // 2^48 still fits, and multiplying it by 65536 again is what overflows
+    assert_eq!(*diagnostic.label(), format!("`65536*65536*65536*65536` {OVERFLOW_LABEL}"));
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, "65536*65536*65536*65536")));
+}
+
+#[test]
+fn multiplication_binds_more_tightly() {
+    assert_eq!(immediate_of("2 + 3 * 4"), 14);
+    assert_eq!(immediate_of("3 * 4 + 2"), 14);
+    assert_eq!(immediate_of("(2 + 3) * 4"), 20);
+}
+
+#[test]
+fn operators_of_equal_precedence_go_left_to_right() {
+    assert_eq!(immediate_of("10 - 3 - 2"), 5);
+    assert_eq!(immediate_of("10 - (3 - 2)"), 9);
+}
+
+#[test]
+fn deeply_nested_parentheses() {
+    assert_eq!(immediate_of("((((((1 + 2)))) * ((3))))"), 9);
+    assert_eq!(immediate_of("(((((((((((((((((((((7)))))))))))))))))))))"), 7);
+    assert_eq!(immediate_of("2 * (3 + (4 * (1 + 1)))"), 22);
+}
+
+#[test]
+fn unclosed_paren() {
+    let source = "    ldi r0, ((1 + 2) * 3\n";
+
+    let diagnostic = assemble_error(source);
+
+    // At the `(` that is never closed, which is the outer one
+    assert_eq!(diagnostic.label(), "This `(` is never closed");
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, "(")));
+}
+
+#[test]
+fn unmatched_paren() {
+    let source = "    ldi r0, (1 + 2)) * 3\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(diagnostic.label(), "This `)` has no `(` before it to close");
+    // The second of the two, since the first closes the `(`
+    let both = span_of(source, "))");
+    assert_eq!(diagnostic.label_span(), Some(Span { index: both.index + 1, len: 1 }));
+}
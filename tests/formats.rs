@@ -0,0 +1,141 @@
+use brisc_assembler::{
+    assemble,
+    formats::{self, OutputFormat},
+    sources::SourceManager,
+    AssembleOptions,
+};
+
+fn write_to_string(format: &dyn OutputFormat, bytes: &[u8]) -> String {
+    let mut out = Vec::new();
+    format.write(bytes, &mut out).unwrap();
+
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn hexlines() {
+    let source = "\
+    ldi r0, 0 ; Our counter 'i'
+    ldi r1, 1
+table:
+    .byte 7, 8
+loop:
+    add r0, r1 ; i += 1
+    j loop
+";
+    let source_manager = SourceManager::new(source, String::from("test.basm"));
+    let result = assemble(&source_manager, &AssembleOptions::default()).unwrap();
+
+    let hexlines = formats::Hexlines {
+        records: &result.records,
+        source_manager: &source_manager,
+        metadata: &result.metadata,
+    };
+
+    // Data and padding are left out, so only the lines of instructions change when one is added
+    assert_eq!(
+        write_to_string(&hexlines, &result.bytes),
+        "\
+2000  ; ldi r0, 0 ; Our counter 'i'
+2101  ; ldi r1, 1
+1010  ; add r0, r1 ; i += 1
+f003  ; j loop
+"
+    );
+}
+
+#[test]
+fn listing_shows_data() {
+    let source = "    ldi r0, 1
+table:
+    db 1, 2, 3
+    dw 0x1234
+end: j end
+";
+    let source_manager = SourceManager::new(source, String::from("test.basm"));
+    let result = assemble(&source_manager, &AssembleOptions::default()).unwrap();
+
+    let mut out = Vec::new();
+    formats::write_listing(
+        &mut out,
+        &result.bytes,
+        &result.records,
+        &result.data_records,
+        &source_manager,
+        &formats::ListingOptions {
+            base_address: 0,
+            cycles: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "   1  0000  20 01      ldi r0, 1\n",
+            "   2               table:\n",
+            "   3  0002  01 02      db 1, 2, 3\n",
+            "      0004  03 00\n",
+            "   4  0006  12 34      dw 0x1234\n",
+            "   5  0008  f0 04  end: j end\n",
+        )
+    );
+}
+
+#[test]
+fn grid_with_mnemonics() {
+    let source = "ldi r0, 1\ntable: .byte 7, 8\nloop: add r0, r1\nj loop\nout r0, 2\n";
+    let source_manager = SourceManager::new(source, String::from("test.basm"));
+    let result = assemble(&source_manager, &AssembleOptions::default()).unwrap();
+
+    let grid = formats::Grid {
+        records: &result.records,
+        base_address: 0,
+        mnemonics: true,
+    };
+
+    // The data between the instructions has no mnemonic
+    assert_eq!(
+        write_to_string(&grid, &result.bytes),
+        concat!(
+            "       +0      +2      +4      +6\n",
+            "0000 | 20 01 | 07 08 | 10 10 | f0 02 |\n",
+            "     | ldi   |       | add   | j     |\n",
+            "0008 | c0 20 |\n",
+            "     | out   |\n",
+        )
+    );
+}
+
+#[test]
+fn symbols_in_each_order() {
+    let source = "zeta:\n    nop\nalpha:\n    jz r0, zeta\nmid:\n    j alpha\n";
+    let source_manager = SourceManager::new(source, String::from("test.basm"));
+    let options = AssembleOptions {
+        // Seeded labels have a value, but aren't defined anywhere in the source
+        seeded_labels: vec![(String::from("handler"), 1)],
+        ..Default::default()
+    };
+    let result = assemble(&source_manager, &options).unwrap();
+
+    let symbols_in = |order| {
+        let mut out = Vec::new();
+        formats::write_symbols(&mut out, &result.symbols, 0, order).unwrap();
+
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(
+        symbols_in(formats::SymbolOrder::Source),
+        "0002 handler\n0000 zeta\n0002 alpha\n0004 mid\n"
+    );
+    assert_eq!(
+        symbols_in(formats::SymbolOrder::Name),
+        "0002 alpha\n0002 handler\n0004 mid\n0000 zeta\n"
+    );
+    // Labels at the same address stay in source order
+    assert_eq!(
+        symbols_in(formats::SymbolOrder::Address),
+        "0000 zeta\n0002 handler\n0002 alpha\n0004 mid\n"
+    );
+}
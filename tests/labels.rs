@@ -0,0 +1,214 @@
+mod common;
+
+use brisc_assembler::{lexer::Span, parser::Visibility, AssembleOptions};
+use common::{assemble_source, assemble_source_with, span_of};
+
+const FORWARD_JUMP: &str = "\
+    jz r0, skip
+    inv r0
+skip:
+    j skip
+";
+
+#[test]
+fn forward_references_resolve_by_default() {
+    let result = assemble_source(FORWARD_JUMP).unwrap();
+
+    assert_eq!(result.bytes, [0xd0, 0x02, 0x70, 0x00, 0xf0, 0x02]);
+}
+
+#[test]
+fn forward_references_can_be_forbidden() {
+    let options = AssembleOptions {
+        forbid_forward_references: true,
+        ..Default::default()
+    };
+
+    let diagnostics = assemble_source_with(FORWARD_JUMP, &options).unwrap_err();
+
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].label(), "Label `skip` is used before it is defined");
+    assert_eq!(diagnostics[0].label_span(), Some(span_of(FORWARD_JUMP, "skip")));
+}
+
+#[test]
+fn backward_references_are_allowed_when_forward_ones_are_forbidden() {
+    let options = AssembleOptions {
+        forbid_forward_references: true,
+        ..Default::default()
+    };
+
+    let result = assemble_source_with("loop:\n    inv r0\n    j loop\n", &options).unwrap();
+
+    assert_eq!(result.bytes, [0x70, 0x00, 0xf0, 0x00]);
+}
+
+fn seeded(name: &str, value: i8) -> AssembleOptions {
+    AssembleOptions {
+        seeded_labels: vec![(String::from(name), value)],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn seeded_label_as_jump_target() {
+    let result = assemble_source_with("    jz r1, handler\n    j handler\n", &seeded("handler", 20)).unwrap();
+
+    assert_eq!(result.bytes, [0xd1, 0x14, 0xf0, 0x14]);
+
+    let symbol = result.symbols.iter().find(|symbol| symbol.name == "handler").unwrap();
+    assert_eq!(symbol.value, 20);
+    assert_eq!(symbol.span, None);
+}
+
+#[test]
+fn seeded_label_defined_in_source() {
+    let source = "handler:\n    j handler\n";
+
+    let diagnostics = assemble_source_with(source, &seeded("handler", 20)).unwrap_err();
+
+    assert_eq!(diagnostics[0].label(), "Label `handler` is already given a value outside the source");
+    assert_eq!(diagnostics[0].label_span(), Some(span_of(source, "handler:")));
+}
+
+#[test]
+fn readme_label_example() {
+    let source = "\
+start:
+    ldi r1, 3    ; 21 03
+loop:
+    jz r1, done  ; d1 04
+    sub r1, r2   ; 31 20
+    j loop       ; f0 01
+done:
+    j start      ; f0 00
+";
+
+    let result = assemble_source(source).unwrap();
+
+    // A label on the first instruction is 0, and `loop` is used by one jump while `done` is jumped to forward
+    assert_eq!(result.bytes, [0x21, 0x03, 0xd1, 0x04, 0x31, 0x20, 0xf0, 0x01, 0xf0, 0x00]);
+}
+
+#[test]
+fn readme_for_loop_example() {
+    let source = "\
+ldi r0, 0 ; Our counter 'i'
+ldi r1, 1 ; A register just to hold our increment, 1
+
+for_loop:
+    ldi r2, 5           ; Our maximum value, 5
+
+    ; Do something here you want to happen 5 times
+
+    sub r2, r0          ; r2 = r2 - r0
+    jz r2, for_loop_end ; If (5 - i) == 0, break the loop
+    add r0, r1          ; i++
+    j for_loop          ; Loop again
+for_loop_end:
+    nop                 ; Continue with the rest of the program
+";
+
+    let result = assemble_source(source).unwrap();
+
+    assert_eq!(
+        result.bytes,
+        [0x20, 0x00, 0x21, 0x01, 0x22, 0x05, 0x32, 0x00, 0xd2, 0x07, 0x10, 0x10, 0xf0, 0x02, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn several_jumps_to_one_label_in_a_chain() {
+    let source = "\
+a:  jz r0, c
+b:  jz r1, c
+c:  jz r2, a
+    j b
+";
+
+    assert_eq!(assemble_source(source).unwrap().bytes, [0xd0, 0x02, 0xd1, 0x02, 0xd2, 0x00, 0xf0, 0x01]);
+}
+
+#[test]
+fn global_label_is_exported_symbol() {
+    let source = ".global start\nstart:\n    j start\n";
+
+    let result = assemble_source(source).unwrap();
+    let start = result.symbols.iter().find(|symbol| symbol.name == "start").unwrap();
+
+    assert_eq!(start.visibility, Visibility::Global);
+    // Exported labels aren't warned about for being unused here
+    assert!(result.warnings.is_empty());
+}
+
+#[test]
+fn global_of_undefined_label() {
+    let source = ".global nope\nend:\n    j end\n";
+
+    let result = assemble_source(source).unwrap();
+
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].label(), "`.global` of label `nope`, which is never defined");
+    assert_eq!(result.warnings[0].label_span(), Some(span_of(source, "nope")));
+}
+
+#[test]
+fn local_of_undefined_label_warned_once() {
+    let source = ".local nope\n.local nope\nend:\n    j end\n";
+
+    let result = assemble_source(source).unwrap();
+
+    assert_eq!(result.warnings.len(), 1);
+    assert_eq!(result.warnings[0].label(), "`.local` of label `nope`, which is never defined");
+}
+
+#[test]
+fn global_and_local_of_one_label() {
+    let source = ".global start\n.local start\nstart:\n    j start\n";
+
+    let diagnostic = common::assemble_error(source);
+    let second_mark = source.find(".local ").unwrap() + ".local ".len();
+
+    assert_eq!(diagnostic.label_span(), Some(Span { index: second_mark as u32, len: 5 }));
+}
+
+#[test]
+fn dash_is_subtraction_by_default() {
+    // `loop` is slot 1, so `loop-1` is 0
+    let source = "    nop\nloop:\n    .byte loop-1, loop\n";
+
+    assert_eq!(assemble_source(source).unwrap().bytes, [0x00, 0x00, 0x00, 0x01]);
+}
+
+#[test]
+fn dashed_labels_need_the_option() {
+    let source = "my-label:\n    j my-label\n";
+
+    let diagnostics = assemble_source(source).unwrap_err();
+    assert_eq!(
+        diagnostics[0].label(),
+        "Invalid label `my-label:`, labels cannot contain `-` unless --allow-dash-idents is given"
+    );
+    assert_eq!(diagnostics[0].label_span(), Some(span_of(source, "my-label:")));
+
+    let options = AssembleOptions {
+        allow_dash_identifiers: true,
+        ..Default::default()
+    };
+    assert_eq!(assemble_source_with(source, &options).unwrap().bytes, [0xf0, 0x00]);
+}
+
+#[test]
+fn numeric_label() {
+    let source = "5: nop\n";
+
+    let diagnostics = assemble_source(source).unwrap_err();
+
+    // One error covering both the number and the `:`, rather than one for each
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].label(),
+        "Invalid label `5:`, labels cannot be numbers, give it a name that starts with a letter or `_`"
+    );
+    assert_eq!(diagnostics[0].label_span(), Some(span_of(source, "5:")));
+}
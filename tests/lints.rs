@@ -0,0 +1,185 @@
+mod common;
+
+use brisc_assembler::{
+    ast::Register,
+    errors::{Diagnostic, DiagnosticKind},
+    lints::{Lint, LintOptions},
+    AssembleOptions,
+};
+use common::{assemble_source_with, span_of};
+
+fn warnings_of(source: &str, lints: LintOptions) -> Vec<Diagnostic> {
+    let options = AssembleOptions {
+        lints,
+        ..Default::default()
+    };
+
+    assemble_source_with(source, &options).unwrap().warnings
+}
+
+const UNTERMINATED_WARNING: &str =
+    "Execution continues past the end of the program after this instruction, end it with a loop such as `end: j end`";
+
+#[test]
+fn terminated_program() {
+    assert!(warnings_of("    ldi r0, 1\nend:\n    j end\n", LintOptions::default()).is_empty());
+}
+
+#[test]
+fn unterminated_program() {
+    let source = "    ldi r0, 1\n    out r0, 2\n";
+
+    let warnings = warnings_of(source, LintOptions::default());
+
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].kind(), DiagnosticKind::Warning);
+    assert_eq!(warnings[0].label(), UNTERMINATED_WARNING);
+    assert_eq!(warnings[0].label_span(), Some(span_of(source, "out r0, 2")));
+}
+
+#[test]
+fn unterminated_program_allowed() {
+    let lints = LintOptions {
+        allowed: vec![Lint::Unterminated],
+        ..Default::default()
+    };
+
+    assert!(warnings_of("    ldi r0, 1\n    out r0, 2\n", lints).is_empty());
+}
+
+#[test]
+fn data_after_the_final_jump_is_still_terminated() {
+    assert!(warnings_of("end:\n    j end\ntable:\n    .byte 1, 2\n", LintOptions::default())
+        .iter()
+        .all(|warning| warning.label() != UNTERMINATED_WARNING));
+}
+
+#[test]
+fn conditional_jump_at_the_end_is_unterminated() {
+    let warnings = warnings_of("start:\n    jz r0, start\n", LintOptions::default());
+
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].label(), UNTERMINATED_WARNING);
+}
+
+#[test]
+fn duplicate_addresses() {
+    let source = "    jz r0, handler\nhandler:\n    ldi r0, 1\nend:\n    j end\n";
+    let mut options = AssembleOptions {
+        // `interrupt` has the same address as `handler`
+        seeded_labels: vec![(String::from("interrupt"), 1)],
+        ..Default::default()
+    };
+
+    assert!(assemble_source_with(source, &options).unwrap().warnings.is_empty());
+
+    options.lints.warned.push(Lint::DuplicateAddress);
+    let warnings = assemble_source_with(source, &options).unwrap().warnings;
+
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].label(), "Labels `interrupt` and `handler` both refer to address 0x02");
+    assert_eq!(warnings[0].label_span(), Some(span_of(source, "handler:")));
+}
+
+#[test]
+fn used_constants_and_ports() {
+    let source = ".set COUNT, 3\n.port LED, 2\n    ldi r0, COUNT\n    out r0, LED\nend:\n    j end\n";
+
+    assert!(warnings_of(source, LintOptions::default()).is_empty());
+}
+
+#[test]
+fn unused_constants_and_ports() {
+    let source = ".set COUNT, 3\n.port LED, 2\nend:\n    j end\n";
+
+    let warnings = warnings_of(source, LintOptions::default());
+    let labels: Vec<&str> = warnings.iter().map(|warning| warning.label().as_str()).collect();
+
+    // In source order, pointing at each name where it is defined
+    assert_eq!(labels, ["Constant `COUNT` is never used", "Port `LED` is never used"]);
+    assert_eq!(warnings[0].label_span(), Some(span_of(source, "COUNT")));
+    assert_eq!(warnings[1].label_span(), Some(span_of(source, "LED")));
+}
+
+#[test]
+fn unused_constants_and_ports_allowed() {
+    let source = ".set COUNT, 3\n.port LED, 2\nend:\n    j end\n";
+    let lints = LintOptions {
+        allowed: vec![Lint::UnusedConstant, Lint::UnusedPort],
+        ..Default::default()
+    };
+
+    assert!(warnings_of(source, lints).is_empty());
+}
+
+fn zero_register_warnings(source: &str, register: Register) -> Vec<Diagnostic> {
+    let lints = LintOptions {
+        zero_register: Some(register),
+        ..Default::default()
+    };
+
+    warnings_of(source, lints)
+}
+
+#[test]
+fn zero_register_kept_at_zero() {
+    let source = "\
+    ldi r0, 0
+    add r0, r0
+    and r0, r3
+    sl r0, r1
+    add r1, r0
+    in r2, 1
+end:
+    j end
+";
+
+    assert!(zero_register_warnings(source, Register::R0).is_empty());
+}
+
+#[test]
+fn zero_register_written() {
+    let source = "    ldi r0, 5\n    add r0, r1\n    in r0, 1\n    inv r0\nend:\n    j end\n";
+
+    let warnings = zero_register_warnings(source, Register::R0);
+    let spans: Vec<_> = warnings.iter().map(|warning| warning.label_span()).collect();
+
+    assert_eq!(warnings[0].label(), "`r0` is reserved to always hold zero, but this instruction may change it");
+    assert_eq!(
+        spans,
+        ["ldi r0, 5", "add r0, r1", "in r0, 1", "inv r0"].map(|text| Some(span_of(source, text)))
+    );
+}
+
+#[test]
+fn zero_register_is_configurable() {
+    let source = "    ldi r0, 5\n    ldi r7, 1\nend:\n    j end\n";
+
+    let warnings = zero_register_warnings(source, Register::R7);
+
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].label(), "`r7` is reserved to always hold zero, but this instruction may change it");
+    assert_eq!(warnings[0].label_span(), Some(span_of(source, "ldi r7, 1")));
+}
+
+#[test]
+fn reserved_opcode_in_data() {
+    let source = "    j end\n    .byte 0x41, 0x00, 0x20, 0x01\nend:\n    j end\n";
+    let lints = LintOptions {
+        warned: vec![Lint::ReservedOpcode],
+        ..Default::default()
+    };
+
+    // Off unless asked for
+    assert!(warnings_of(source, LintOptions::default()).is_empty());
+
+    let warnings = warnings_of(source, lints);
+
+    // Only the first slot of the data starts with 0x4
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(
+        warnings[0].label(),
+        "Data at address 0x02 starts with 0x4, which isn't the opcode of any instruction"
+    );
+    assert_eq!(warnings[0].label_span(), Some(span_of(source, ".byte 0x41, 0x00, 0x20, 0x01")));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::{assemble_error, span_of};
+
+#[test]
+fn byte_run_past_the_end_of_memory() {
+    let values = vec!["1"; 70].join(", ");
+    let source = format!("nop\n.byte {values}\n");
+
+    let diagnostic = assemble_error(&source);
+
+    // The nop and 70 bytes of data, which is already an even number
+    assert_eq!(diagnostic.label(), "Output of 72 bytes exceeds the instruction memory size (64 bytes)");
+    assert_eq!(diagnostic.label_span(), Some(span_of(&source, &format!(".byte {values}"))));
+}
+
+#[test]
+fn instruction_past_the_end_of_memory() {
+    let source = format!("{}    j 0\n", "    nop\n".repeat(32));
+
+    let diagnostic = assemble_error(&source);
+
+    // Instructions are still counted as such, rather than in bytes
+    assert_eq!(diagnostic.label(), "Maximum number of instructions reached (32)");
+    assert_eq!(diagnostic.label_span(), Some(span_of(&source, "j 0")));
+}
+
+#[test]
+fn byte_run_filling_memory_exactly() {
+    let values = vec!["1"; 62].join(", ");
+    let source = format!("nop\n.byte {values}\n");
+
+    let result = common::assemble_source(&source).unwrap();
+
+    assert_eq!(result.bytes.len(), 64);
+    assert_eq!(result.instruction_count, 32);
+}
+
+#[test]
+fn space_reserves_zeros_between_code() {
+    let source = "start: ldi r0, 1\nbuffer: .space 4\nend: j start\n";
+
+    let result = common::assemble_source(source).unwrap();
+
+    assert_eq!(result.bytes, [0x20, 0x01, 0, 0, 0, 0, 0xf0, 0x00]);
+
+    let buffer = result.symbols.iter().find(|symbol| symbol.name == "buffer").unwrap();
+    let end = result.symbols.iter().find(|symbol| symbol.name == "end").unwrap();
+
+    // Label values are slots of two bytes
+    assert_eq!(buffer.value, 1);
+    assert_eq!(end.value, 3);
+}
+
+#[test]
+fn space_past_the_end_of_memory() {
+    let source = "nop\n.space 64\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(diagnostic.label(), "Output of 66 bytes exceeds the instruction memory size (64 bytes)");
+    assert_eq!(diagnostic.label_span(), Some(span_of(source, ".space 64")));
+}
+
+fn in_slots(start: usize, end: usize) -> brisc_assembler::AssembleOptions {
+    brisc_assembler::AssembleOptions {
+        slot_range: Some(brisc_assembler::generator::SlotRange { start, end }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn routine_fitting_its_slot_range() {
+    let source = "routine:\n    ldi r0, 1\n    j routine\n";
+
+    let result = common::assemble_source_with(source, &in_slots(16, 32)).unwrap();
+
+    // Everything before the range is padding, and labels count from slot 0 of the whole memory
+    assert_eq!(result.bytes.len(), 36);
+    assert!(result.bytes[..32].iter().all(|&byte| byte == 0));
+    assert_eq!(result.bytes[32..], [0x20, 0x01, 0xf0, 0x10]);
+    assert_eq!(result.symbols[0].value, 16);
+}
+
+#[test]
+fn routine_overflowing_its_slot_range() {
+    let source = "routine:\n    ldi r0, 1\n    ldi r1, 2\n    j routine\n";
+
+    let diagnostics = common::assemble_source_with(source, &in_slots(16, 18)).unwrap_err();
+
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].label(), "`j routine` doesn't fit in the slot range 16:18");
+    assert_eq!(diagnostics[0].label_span(), Some(span_of(source, "j routine")));
+}
+
+#[test]
+fn labels_after_gaps_are_slot_indices() {
+    let source = "\
+start:
+    jz r0, after
+odd:
+    .byte 1, 2, 3
+gap:
+    .space 5
+after:
+    j start
+last:
+    j after
+";
+
+    let result = common::assemble_source(source).unwrap();
+
+    // 3 bytes and 5 bytes each take up a whole number of 2 byte slots, with a padding byte after each
+    for (name, slot) in [("start", 0), ("odd", 1), ("gap", 3), ("after", 6), ("last", 7)] {
+        let symbol = result.symbols.iter().find(|symbol| symbol.name == name).unwrap();
+        assert_eq!(symbol.value, slot, "{name}");
+    }
+
+    // Jumps are to slot 6, not to byte address 12
+    assert_eq!(result.bytes[..2], [0xd0, 0x06]);
+    assert_eq!(result.bytes[2..6], [1, 2, 3, 0]);
+    assert_eq!(result.bytes[6..12], [0; 6]);
+    assert_eq!(result.bytes[12..], [0xf0, 0x00, 0xf0, 0x06]);
+}
+
+#[test]
+fn labels_after_gaps_in_a_slot_range() {
+    let source = "\
+    .space 3
+after:
+    j after
+";
+
+    let result = common::assemble_source_with(source, &in_slots(10, 16)).unwrap();
+
+    // Slot 2 of the routine, which is placed from slot 10
+    assert_eq!(result.symbols[0].value, 12);
+    assert_eq!(result.bytes[20..], [0, 0, 0, 0, 0xf0, 12]);
+}
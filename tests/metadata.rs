@@ -0,0 +1,54 @@
+mod common;
+
+use brisc_assembler::ast::{MetaEntry, MetaValue};
+use brisc_assembler::lexer::Span;
+use common::{assemble_error, assemble_source};
+
+const BLINK_META: &str = "\
+.set VERSION, 3
+.meta name \"blink \\\"fast\\\"\"
+.meta version VERSION
+end:
+    j end
+";
+
+fn key_values(metadata: &[MetaEntry]) -> Vec<(&str, &MetaValue)> {
+    metadata.iter().map(|entry| (entry.key.as_str(), &entry.value)).collect()
+}
+
+#[test]
+fn entries_collected_in_source_order() {
+    let result = assemble_source(BLINK_META).unwrap();
+
+    assert_eq!(
+        key_values(&result.metadata),
+        [
+            ("name", &MetaValue::String(String::from("blink \"fast\""))),
+            ("version", &MetaValue::Integer(3)),
+        ]
+    );
+    // Metadata adds nothing to the program
+    assert_eq!(result.bytes, [0xf0, 0x00]);
+}
+
+#[test]
+fn sidecar_json() {
+    let result = assemble_source(BLINK_META).unwrap();
+    let mut out = Vec::new();
+
+    brisc_assembler::formats::write_metadata(&mut out, &result.metadata).unwrap();
+
+    assert_eq!(String::from_utf8(out).unwrap().trim_end(), r#"{"name":"blink \"fast\"","version":3}"#);
+}
+
+#[test]
+fn duplicate_key() {
+    let source = ".meta version 1\n.meta version 2\n";
+
+    let diagnostic = assemble_error(source);
+
+    assert_eq!(diagnostic.label(), "Metadata `version` is already given a value");
+    // At the second key, rather than the first
+    let second_key = source.rfind("version").unwrap() as u32;
+    assert_eq!(diagnostic.label_span(), Some(Span { index: second_key, len: 7 }));
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use brisc_assembler::AssembleOptions;
+use common::{assemble_source_with, span_of};
+
+fn strict() -> AssembleOptions {
+    AssembleOptions {
+        strict_registers: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn sigil_marks_a_register() {
+    let result = assemble_source_with("    add $r0, $R15\n", &strict()).unwrap();
+
+    assert_eq!(result.bytes, [0x10, 0xf0]);
+}
+
+#[test]
+fn bare_register_name_is_a_label() {
+    let result = assemble_source_with("r0:\n    jz $r1, r0\n    j r0\n", &strict()).unwrap();
+
+    assert_eq!(result.bytes, [0xd1, 0x00, 0xf0, 0x00]);
+    assert_eq!(result.symbols[0].name, "r0");
+}
+
+#[test]
+fn bare_register_name_where_a_register_is_expected() {
+    let source = "    add r0, $r1\n";
+
+    let diagnostics = assemble_source_with(source, &strict()).unwrap_err();
+
+    assert_eq!(diagnostics[0].label(), "`add` expects a register as its first operand, found `r0`");
+    assert_eq!(diagnostics[0].label_span(), Some(span_of(source, "r0")));
+}
+
+#[test]
+fn no_such_register_with_sigil() {
+    let source = "    add $r99, $r1\n";
+
+    let diagnostics = assemble_source_with(source, &strict()).unwrap_err();
+
+    assert_eq!(diagnostics[0].label(), "No such register `$r99` (valid registers are r0 to r15)");
+    assert_eq!(diagnostics[0].label_span(), Some(span_of(source, "$r99")));
+}
+
+#[test]
+fn sigil_without_strict_registers() {
+    let diagnostics = common::assemble_source("    add $r0, $r1\n").unwrap_err();
+
+    assert_eq!(diagnostics[0].label(), "Invalid token found `$`");
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use brisc_assembler::{errors::DiagnosticKind, AssembleOptions};
+use common::{assemble_source, assemble_source_with, span_of};
+
+const PROGRAM: &str = "    ldi r0, 200\n    ldi r1, 255\n";
+
+#[test]
+fn unsigned_immediate_is_an_error_when_strict() {
+    let diagnostics = assemble_source(PROGRAM).unwrap_err();
+
+    assert_eq!(diagnostics[0].label_span(), Some(span_of(PROGRAM, "200")));
+}
+
+#[test]
+fn unsigned_immediate_is_relaxed_with_a_warning() {
+    let options = AssembleOptions {
+        relax: true,
+        ..Default::default()
+    };
+
+    let result = assemble_source_with(PROGRAM, &options).unwrap();
+
+    assert_eq!(result.bytes, [0x20, 0xc8, 0x21, 0xff]);
+
+    let relaxations: Vec<_> = result
+        .warnings
+        .iter()
+        .filter(|warning| warning.label().contains("was taken as"))
+        .collect();
+
+    assert_eq!(relaxations.len(), 2, "{:?}", result.warnings);
+    assert!(relaxations.iter().all(|warning| warning.kind() == DiagnosticKind::Warning));
+    assert_eq!(
+        relaxations[0].label(),
+        "Value `200` is out of range for an 8-bit signed integer value, and was taken as -56"
+    );
+    assert_eq!(relaxations[0].label_span(), Some(span_of(PROGRAM, "200")));
+    assert_eq!(relaxations[1].label_span(), Some(span_of(PROGRAM, "255")));
+}
+
+#[test]
+fn relax_still_rejects_values_that_dont_fit_a_byte() {
+    let options = AssembleOptions {
+        relax: true,
+        ..Default::default()
+    };
+
+    assert!(assemble_source_with("    ldi r0, 256\n", &options).is_err());
+}
@@ -0,0 +1,40 @@
+use brisc_assembler::web::assemble_web;
+
+#[test]
+fn json_diagnostic_columns_with_tabs() {
+    // Two tabs before the instruction, and one between the register and the bad operand
+    let result = assemble_web("nop\n\t\tadd r0,\tr99\n");
+
+    assert_eq!(result.bytes, None);
+    assert_eq!(result.diagnostics.len(), 1);
+
+    let diagnostic = &result.diagnostics[0];
+    assert_eq!(diagnostic.line, Some(2));
+    // Every tab is one character, but is shown 4 columns wide
+    assert_eq!(diagnostic.column, Some(10));
+    assert_eq!(diagnostic.visual_column, Some(19));
+
+    let json = diagnostic.to_json();
+    assert!(json.contains("\"line\":2,\"column\":10,\"visual_column\":19}"), "{json}");
+}
+
+#[test]
+fn json_of_a_successful_assembly() {
+    let result = assemble_web("end: j end\n");
+
+    assert_eq!(result.to_json(), "{\"bytes\":[240,0],\"diagnostics\":[]}");
+}
+
+#[test]
+fn web_result_is_separate_from_the_library_result() {
+    use brisc_assembler::{web::WebAssembleResult, AssembleResult};
+
+    let web: WebAssembleResult = assemble_web("end: j end\n");
+    let library: AssembleResult = brisc_assembler::assemble(
+        &brisc_assembler::sources::SourceManager::new("end: j end\n", String::from("test.basm")),
+        &Default::default(),
+    )
+    .unwrap();
+
+    assert_eq!(web.bytes, Some(library.bytes));
+}